@@ -2,12 +2,17 @@
 #![feature(trait_alias)]
 
 pub extern crate binary;
+pub extern crate gc;
+
+pub use gc::{Handle, Pinned};
 
 use global::StringName;
 use std::any::Any;
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 
-pub trait VMTrait: VMTrait_Assembly + VMTrait_CPU + VMTrait_Statics {}
+pub trait VMTrait: VMTrait_Assembly + VMTrait_CPU + VMTrait_Statics + VMTrait_GC {}
 
 pub trait VMTrait_CPU {
     fn new_cpu(self: Arc<Self>) -> (u64, Arc<dyn CPUTrait>);
@@ -22,12 +27,27 @@ pub trait VMTrait_Assembly {
     fn assembly_manager(&self) -> Arc<dyn AssemblyManagerTrait>;
     fn add_assembly_lookuper(&self, f: Arc<dyn AssemblyLookuper>);
     fn add_assembly_dir(&self, p: &str);
+    /// Unload the assembly `name` once nothing uses it anymore, failing otherwise
+    fn unload_assembly(self: Arc<Self>, name: StringName) -> global::Result<()>;
 }
 
 pub trait VMTrait_Statics {
     fn load_statics(self: Arc<Self>) -> global::Result<()>;
 }
 
+pub trait VMTrait_GC {
+    fn collect_garbage(self: Arc<Self>) -> global::Result<()>;
+    fn heap_stats(&self) -> gc::HeapStats;
+    fn heap_config(&self) -> gc::HeapConfig;
+    /// Configure the heap of the VM, like the bytes it may hold at most or the objects marked by
+    /// every step of incremental collection, see [`gc::HeapConfig`]
+    fn set_heap_config(&self, config: gc::HeapConfig);
+    /// Live objects grouped by the name of their type
+    fn live_objects_by_type(&self) -> HashMap<StringName, gc::ObjectCount>;
+    /// Write the object graph and the roots to `path`, see [`gc::HeapSnapshot`]
+    fn dump_heap(&self, path: &Path) -> global::Result<()>;
+}
+
 pub trait CPUTrait {
     fn arc_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
     fn id(&self) -> u64;
@@ -46,8 +66,14 @@ pub trait AssemblyManagerTrait {
         assemblies: &[binary::Assembly],
     ) -> global::Result<()>;
     fn get_assembly(&self, assem_name: StringName) -> global::Result<Arc<dyn AssemblyTrait>>;
-    fn add_assembly(&self, assem: Arc<dyn AssemblyTrait>);
+    /// Add the assembly `assem`, failing if a version of it is loaded already or a dependency
+    /// of it is not loaded in a version it accepts
+    fn add_assembly(&self, assem: Arc<dyn AssemblyTrait>) -> global::Result<()>;
     fn load_core(self: Arc<Self>) -> global::Result<()>;
+    /// Verify the code of the assemblies loaded from binary as they are loaded, and fail the
+    /// load without keeping them when a method is rejected. Off by default, when each method is
+    /// verified before its first call instead.
+    fn set_verify_on_load(&self, verify: bool);
     fn resolve_type_references(&self) -> global::Result<()>;
 }
 
@@ -0,0 +1,343 @@
+use std::{
+    alloc::{self, Layout},
+    any::TypeId,
+    collections::{BTreeMap, HashMap, HashSet},
+    ptr::{self, NonNull},
+};
+
+use crate::{Generation, Trace};
+
+/// Sizes of the slots objects are bump allocated in, header included.
+/// Bigger objects get an allocation of their own.
+const SIZE_CLASSES: [usize; 12] = [32, 48, 64, 96, 128, 192, 256, 384, 512, 768, 1024, 2048];
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHUNK_ALIGN: usize = 16;
+
+pub(crate) const HEADER_SIZE: usize = size_of::<Header>();
+
+/// Everything the collector needs to know about the type of an object
+pub(crate) struct VTable {
+    pub(crate) size: usize,
+    align: usize,
+    type_id: fn() -> TypeId,
+    as_dyn: unsafe fn(*mut u8) -> *mut dyn Trace,
+}
+
+trait HasVTable: Trace + Sized {
+    const VTABLE: VTable = VTable {
+        size: size_of::<Self>(),
+        align: align_of::<Self>(),
+        type_id: TypeId::of::<Self>,
+        as_dyn: |p| p as *mut Self as *mut dyn Trace,
+    };
+}
+
+impl<T: Trace> HasVTable for T {}
+
+/// Placed right before every object. A slot without vtable is free.
+#[repr(C, align(16))]
+pub(crate) struct Header {
+    vtable: Option<&'static VTable>,
+    pub(crate) marked: bool,
+    pub(crate) generation: Generation,
+    /// Pinned objects are never moved
+    pub(crate) pins: u32,
+    /// Whether the object is in the large-object space
+    pub(crate) large: bool,
+}
+
+impl Header {
+    pub(crate) fn vtable(&self) -> &'static VTable {
+        self.vtable.unwrap()
+    }
+    pub(crate) fn payload(this: NonNull<Header>) -> *mut u8 {
+        unsafe { this.cast::<u8>().as_ptr().add(HEADER_SIZE) }
+    }
+    /// # Safety
+    /// `payload` must point at an object allocated by [`Arenas::alloc`]
+    pub(crate) unsafe fn of_payload(payload: *mut u8) -> NonNull<Header> {
+        unsafe { NonNull::new_unchecked(payload.sub(HEADER_SIZE).cast()) }
+    }
+    pub(crate) fn as_dyn(this: NonNull<Header>) -> *mut dyn Trace {
+        unsafe { (this.as_ref().vtable().as_dyn)(Self::payload(this)) }
+    }
+    pub(crate) fn is<T: Trace>(&self) -> bool {
+        (self.vtable().type_id)() == TypeId::of::<T>()
+    }
+}
+
+struct SizeClass {
+    slot_size: usize,
+    chunks: Vec<NonNull<u8>>,
+    /// Offset of the next unused slot in the last chunk
+    bump: usize,
+    free: Vec<NonNull<Header>>,
+}
+
+impl SizeClass {
+    fn new(slot_size: usize) -> Self {
+        Self {
+            slot_size,
+            chunks: Vec::new(),
+            bump: CHUNK_SIZE,
+            free: Vec::new(),
+        }
+    }
+    fn chunk_layout() -> Layout {
+        Layout::from_size_align(CHUNK_SIZE, CHUNK_ALIGN).unwrap()
+    }
+    /// Returns the slot and, if one was needed, the new chunk
+    fn alloc(&mut self) -> (NonNull<Header>, Option<NonNull<u8>>) {
+        if let Some(slot) = self.free.pop() {
+            return (slot, None);
+        }
+        let mut new_chunk = None;
+        if self.bump + self.slot_size > CHUNK_SIZE {
+            let layout = Self::chunk_layout();
+            // zeroed, so that unused slots read as free
+            let chunk = NonNull::new(unsafe { alloc::alloc_zeroed(layout) })
+                .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+            self.chunks.push(chunk);
+            self.bump = 0;
+            new_chunk = Some(chunk);
+        }
+        let chunk = *self.chunks.last().unwrap();
+        let slot = unsafe { chunk.add(self.bump) }.cast();
+        self.bump += self.slot_size;
+        (slot, new_chunk)
+    }
+    fn slots(&self) -> impl Iterator<Item = NonNull<Header>> + '_ {
+        self.chunks.iter().enumerate().flat_map(move |(i, chunk)| {
+            (0..self.chunk_end(i))
+                .step_by(self.slot_size)
+                .map(move |offset| unsafe { chunk.add(offset) }.cast())
+        })
+    }
+}
+
+impl SizeClass {
+    fn is_live(slot: NonNull<Header>) -> bool {
+        unsafe { slot.as_ref() }.vtable.is_some()
+    }
+    fn chunk_end(&self, i: usize) -> usize {
+        if i + 1 == self.chunks.len() {
+            self.bump
+        } else {
+            CHUNK_SIZE / self.slot_size * self.slot_size
+        }
+    }
+    /// Move the objects at the end of this class to free slots at its start, then release
+    /// the chunks left empty. Returns the released chunks.
+    fn compact(
+        &mut self,
+        can_move: &dyn Fn(&Header) -> bool,
+        forwarding: &mut HashMap<usize, usize>,
+    ) -> Vec<NonNull<u8>> {
+        let slots = self.slots().collect::<Vec<_>>();
+        let mut free = 0;
+        let mut live = slots.len();
+        loop {
+            while free < slots.len() && Self::is_live(slots[free]) {
+                free += 1;
+            }
+            live = slots[..live]
+                .iter()
+                .rposition(|x| Self::is_live(*x) && can_move(unsafe { x.as_ref() }))
+                .unwrap_or(0);
+            if free >= live {
+                break;
+            }
+            let (from, to) = (slots[live], slots[free]);
+            unsafe {
+                ptr::copy_nonoverlapping(from.as_ptr(), to.as_ptr(), 1);
+                ptr::copy_nonoverlapping(
+                    Header::payload(from),
+                    Header::payload(to),
+                    to.as_ref().vtable().size,
+                );
+                (*from.as_ptr()).vtable = None;
+            }
+            forwarding.insert(Header::payload(from) as usize, Header::payload(to) as usize);
+            free += 1;
+        }
+
+        let mut released = Vec::new();
+        let mut kept = Vec::new();
+        let last = self.chunks.len().wrapping_sub(1);
+        let mut last_kept = false;
+        for (i, chunk) in self.chunks.iter().copied().enumerate() {
+            let end = self.chunk_end(i);
+            let is_empty = (0..end)
+                .step_by(self.slot_size)
+                .all(|offset| !Self::is_live(unsafe { chunk.add(offset) }.cast()));
+            if is_empty {
+                released.push(chunk);
+            } else {
+                kept.push(chunk);
+                last_kept = i == last;
+            }
+        }
+        if !last_kept {
+            // the chunk now last had been filled up before
+            self.bump = CHUNK_SIZE / self.slot_size * self.slot_size;
+        }
+        if kept.is_empty() {
+            self.bump = CHUNK_SIZE;
+        }
+        self.chunks = kept;
+        self.free = self.slots().filter(|x| !Self::is_live(*x)).collect();
+        released
+    }
+}
+
+/// Memory of a heap: size-classed bump-pointer chunks for small objects,
+/// a separate allocation for every big one and every one of the large-object space
+pub(crate) struct Arenas {
+    classes: Vec<SizeClass>,
+    /// Objects allocated on their own
+    separate: HashSet<NonNull<Header>>,
+    /// Start of every chunk and large object, to their end and slot size
+    ranges: BTreeMap<usize, (usize, usize)>,
+}
+
+unsafe impl Send for Arenas {}
+
+impl Default for Arenas {
+    fn default() -> Self {
+        Self {
+            classes: SIZE_CLASSES.iter().copied().map(SizeClass::new).collect(),
+            separate: HashSet::new(),
+            ranges: BTreeMap::new(),
+        }
+    }
+}
+
+impl Arenas {
+    /// Objects in the `large` object space get an allocation of their own, so they are never
+    /// moved
+    pub(crate) fn alloc<T: Trace>(&mut self, val: T, large: bool) -> NonNull<Header> {
+        let vtable = &<T as HasVTable>::VTABLE;
+        let slot_size = HEADER_SIZE + vtable.size;
+        let class = if large {
+            None
+        } else {
+            Self::class_of(&mut self.classes, vtable)
+        };
+        let header = match class {
+            Some(class) => {
+                let (slot, new_chunk) = class.alloc();
+                if let Some(chunk) = new_chunk {
+                    let start = chunk.as_ptr() as usize;
+                    self.ranges
+                        .insert(start, (start + CHUNK_SIZE, class.slot_size));
+                }
+                slot
+            }
+            None => {
+                let (layout, offset) = Self::separate_layout(vtable);
+                let base = NonNull::new(unsafe { alloc::alloc(layout) })
+                    .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+                let header = unsafe { base.add(offset - HEADER_SIZE) }.cast();
+                let start = header.as_ptr() as usize;
+                self.ranges.insert(start, (start + slot_size, slot_size));
+                self.separate.insert(header);
+                header
+            }
+        };
+        unsafe {
+            header.write(Header {
+                vtable: Some(vtable),
+                marked: false,
+                generation: Generation::Young,
+                pins: 0,
+                large,
+            });
+            Header::payload(header).cast::<T>().write(val);
+        }
+        header
+    }
+    /// The size class objects of `vtable` are allocated in, `None` if they are too big
+    fn class_of<'a>(classes: &'a mut [SizeClass], vtable: &VTable) -> Option<&'a mut SizeClass> {
+        let slot_size = HEADER_SIZE + vtable.size;
+        if vtable.align > CHUNK_ALIGN {
+            return None;
+        }
+        classes.iter_mut().find(|x| x.slot_size >= slot_size)
+    }
+    /// Layout of a separate allocation and the offset of the object in it
+    fn separate_layout(vtable: &VTable) -> (Layout, usize) {
+        let align = vtable.align.max(CHUNK_ALIGN);
+        let offset = HEADER_SIZE.max(vtable.align);
+        (
+            Layout::from_size_align(offset + vtable.size, align).unwrap(),
+            offset,
+        )
+    }
+    /// Drop the object and release its slot
+    ///
+    /// # Safety
+    /// `header` must be a live object of these arenas, unreachable from now on
+    pub(crate) unsafe fn free(&mut self, header: NonNull<Header>) {
+        unsafe {
+            ptr::drop_in_place(Header::as_dyn(header));
+        }
+        let vtable = unsafe { header.as_ref() }.vtable();
+        if self.separate.remove(&header) {
+            let (layout, offset) = Self::separate_layout(vtable);
+            self.ranges.remove(&(header.as_ptr() as usize));
+            unsafe {
+                alloc::dealloc(
+                    header.cast::<u8>().as_ptr().sub(offset - HEADER_SIZE),
+                    layout,
+                );
+            }
+        } else {
+            unsafe { (*header.as_ptr()).vtable = None };
+            let class = Self::class_of(&mut self.classes, vtable).unwrap();
+            class.free.push(header);
+        }
+    }
+    /// Move small objects for which `can_move` holds into as few chunks as possible and
+    /// release the others. Returns the old addresses of the moved objects to their new ones.
+    pub(crate) fn compact(&mut self, can_move: &dyn Fn(&Header) -> bool) -> HashMap<usize, usize> {
+        let mut forwarding = HashMap::new();
+        for class in &mut self.classes {
+            for chunk in class.compact(can_move, &mut forwarding) {
+                self.ranges.remove(&(chunk.as_ptr() as usize));
+                unsafe { alloc::dealloc(chunk.as_ptr(), SizeClass::chunk_layout()) };
+            }
+        }
+        forwarding
+    }
+    /// Every live object
+    pub(crate) fn objects(&self) -> impl Iterator<Item = NonNull<Header>> + '_ {
+        self.classes
+            .iter()
+            .flat_map(SizeClass::slots)
+            .chain(self.separate.iter().copied())
+            .filter(|x| unsafe { x.as_ref() }.vtable.is_some())
+    }
+    /// The header of the live object at `addr`, if there is one in these arenas
+    pub(crate) fn header_of(&self, addr: usize) -> Option<NonNull<Header>> {
+        let (&start, &(end, slot_size)) = self.ranges.range(..=addr).next_back()?;
+        let offset = addr.checked_sub(start + HEADER_SIZE)?;
+        if addr >= end || offset % slot_size != 0 {
+            return None;
+        }
+        let header = unsafe { Header::of_payload(addr as *mut u8) };
+        unsafe { header.as_ref() }.vtable.map(|_| header)
+    }
+}
+
+impl Drop for Arenas {
+    fn drop(&mut self) {
+        for header in self.objects().collect::<Vec<_>>() {
+            unsafe { self.free(header) };
+        }
+        for class in &self.classes {
+            for chunk in &class.chunks {
+                unsafe { alloc::dealloc(chunk.as_ptr(), SizeClass::chunk_layout()) };
+            }
+        }
+    }
+}
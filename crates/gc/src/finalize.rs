@@ -0,0 +1,62 @@
+use crate::{Gc, Heap, Trace, heap::HeapInner};
+
+impl<T: Trace> Gc<T> {
+    /// Queue this object for finalization once it becomes unreachable,
+    /// instead of sweeping it directly
+    pub fn register_for_finalization(&self) {
+        let mut heap = self.heap().lock();
+        if !heap.finalizable.contains(&self.inner) {
+            heap.finalizable.push(self.inner);
+        }
+    }
+    pub fn suppress_finalization(&self) {
+        self.heap().lock().finalizable.retain(|x| self.inner.ne(x));
+    }
+}
+
+/// Move every unmarked finalizable object to the finalization queue
+/// and return them, so that they can be resurrected for this cycle
+pub(crate) fn queue_unmarked(heap: &mut HeapInner) -> Vec<usize> {
+    let HeapInner {
+        finalizable,
+        finalization_queue,
+        ..
+    } = heap;
+    let mut resurrected = Vec::new();
+    finalizable.retain(|gc| {
+        if gc.is_marked() {
+            true
+        } else {
+            resurrected.push(gc.addr());
+            finalization_queue.push(*gc);
+            false
+        }
+    });
+    resurrected
+}
+
+impl Heap {
+    /// Take the queued objects of type `T` whose finalizers should be run now.
+    ///
+    /// Every returned object is rooted for the duration of its finalization:
+    /// call [`Gc::unroot`] once the finalizer has run, after which the object
+    /// is reclaimed by the next collection which finds it unreachable.
+    pub fn drain_finalization_queue<T: Trace>(&self) -> Vec<Gc<T>> {
+        let mut heap = self.lock();
+        let HeapInner {
+            finalization_queue,
+            roots,
+            ..
+        } = &mut *heap;
+        let mut drained = Vec::new();
+        finalization_queue.retain(|gc| {
+            let is_t = gc.is::<T>();
+            if is_t {
+                roots.push(*gc);
+                drained.push(Gc::from_inner(*gc));
+            }
+            !is_t
+        });
+        drained
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+use crate::{Gc, InnerGc, Trace, heap::HeapInner};
+
+/// Keeps an object alive and in place until it is dropped.
+///
+/// Natives borrowing data out of an object should hold one for as long as the borrow lives.
+pub struct Pinned<T: Trace> {
+    gc: Gc<T>,
+}
+
+impl<T: Trace> Pinned<T> {
+    pub fn new(gc: &Gc<T>) -> Self {
+        gc.pin();
+        Self {
+            gc: Gc::from_inner(gc.inner),
+        }
+    }
+    pub fn gc(&self) -> &Gc<T> {
+        &self.gc
+    }
+}
+
+impl<T: Trace> Gc<T> {
+    /// Pin this object for the lifetime of the returned guard
+    pub fn pinned(&self) -> Pinned<T> {
+        Pinned::new(self)
+    }
+}
+
+impl<T: Trace> Deref for Pinned<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.gc
+    }
+}
+
+impl<T: Trace> Clone for Pinned<T> {
+    fn clone(&self) -> Self {
+        Self::new(&self.gc)
+    }
+}
+
+impl<T: Trace> Drop for Pinned<T> {
+    fn drop(&mut self) {
+        self.gc.unpin();
+    }
+}
+
+impl<T: Trace + Debug> Debug for Pinned<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Pinned({:?})", self.gc)
+    }
+}
+
+pub(crate) struct HandleSlot {
+    target: Mutex<InnerGc>,
+}
+
+/// Keeps an object alive until every clone of it is dropped, e.g. while an embedding host
+/// refers to it.
+///
+/// Unlike [`Pinned`], the object may be moved by compacting collections. The handle follows
+/// it, so take a fresh [`Handle::get`] after every collection.
+pub struct Handle<T> {
+    slot: Arc<HandleSlot>,
+    _phantom_data: PhantomData<*mut T>,
+}
+
+unsafe impl<T> Send for Handle<T> {}
+unsafe impl<T> Sync for Handle<T> {}
+
+impl<T: Trace> Handle<T> {
+    pub fn new(target: &Gc<T>) -> Self {
+        let slot = Arc::new(HandleSlot {
+            target: Mutex::new(target.inner),
+        });
+        target.heap().lock().handles.push(Arc::downgrade(&slot));
+        Self {
+            slot,
+            _phantom_data: PhantomData,
+        }
+    }
+    pub fn get(&self) -> Gc<T> {
+        Gc::from_inner(*self.slot.target.lock().unwrap())
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            slot: self.slot.clone(),
+            _phantom_data: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.slot, &other.slot)
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T: Trace + Debug> Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle({:?})", self.get())
+    }
+}
+
+/// The targets of the handles still alive, forgetting the dropped ones
+pub(crate) fn live_targets(heap: &mut HeapInner) -> Vec<usize> {
+    let mut targets = Vec::new();
+    heap.handles.retain(|slot| {
+        let Some(slot) = slot.upgrade() else {
+            return false;
+        };
+        targets.push(slot.target.lock().unwrap().addr());
+        true
+    });
+    targets
+}
+
+/// Point every handle whose target has been moved to its new address
+pub(crate) fn relocate(heap: &mut HeapInner, forward: &dyn Fn(usize) -> Option<usize>) {
+    for slot in heap.handles.iter().filter_map(std::sync::Weak::upgrade) {
+        slot.target.lock().unwrap().relocate(forward);
+    }
+}
@@ -0,0 +1,498 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Debug},
+    ptr::NonNull,
+    sync::{
+        LazyLock, Mutex, MutexGuard, RwLock,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Gc, InnerGc, Trace,
+    arena::{Arenas, Header},
+    finalize,
+    handle::{self, HandleSlot},
+    stats::{HeapStats, ObjectCount},
+    weak::{self, WeakSlot},
+};
+
+/// Objects which survived a collection are promoted from [`Generation::Young`]
+/// to [`Generation::Old`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Generation {
+    Young,
+    Old,
+}
+
+impl Generation {
+    pub const COUNT: usize = 2;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapConfig {
+    /// Whether full collections of the VM are followed by [`Heap::compact`]
+    pub compacting: bool,
+    /// Objects marked by every step of [`Heap::collect_incrementally`],
+    /// `0` disables incremental collection
+    pub mark_budget: usize,
+    /// Bytes allocated since the last collection after which
+    /// [`Heap::collect_incrementally`] starts a new cycle
+    pub incremental_threshold: usize,
+    /// Bytes the live objects may occupy at most, see [`Heap::try_alloc`]
+    pub max_bytes: Option<usize>,
+    /// Objects with a [`Trace::payload_size`] of at least this many bytes, like big arrays
+    /// and strings, are put in the large-object space, where they are never moved
+    pub large_object_threshold: Option<usize>,
+}
+
+/// An allocation would have gone over [`HeapConfig::max_bytes`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutOfMemory {
+    pub requested: usize,
+    pub limit: usize,
+}
+
+impl fmt::Display for OutOfMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "allocating {} bytes would exceed the heap limit of {} bytes",
+            self.requested, self.limit
+        )
+    }
+}
+
+impl std::error::Error for OutOfMemory {}
+
+/// State of a collection whose marking is in progress
+pub(crate) struct Marking {
+    generation: Generation,
+    gray: Vec<usize>,
+    /// The longest step of this collection so far
+    longest_pause: Duration,
+}
+
+#[derive(Default)]
+pub(crate) struct HeapInner {
+    pub(crate) arenas: Arenas,
+    pub(crate) roots: Vec<InnerGc>,
+    pub(crate) finalizable: Vec<InnerGc>,
+    pub(crate) finalization_queue: Vec<InnerGc>,
+    pub(crate) weak_slots: Vec<std::sync::Weak<WeakSlot>>,
+    pub(crate) handles: Vec<std::sync::Weak<HandleSlot>>,
+    pub(crate) stats: HeapStats,
+    pub(crate) marking: Option<Marking>,
+}
+
+/// A set of managed objects which are collected together.
+///
+/// Every VM owns its own heap, [`Gc::new`] allocates in [`Heap::global`].
+/// A [`Gc`] refers back to the heap it was allocated in, so heaps are never dropped:
+/// they are leaked on creation and their memory is only given back by collections.
+#[derive(Default)]
+pub struct Heap {
+    config: RwLock<HeapConfig>,
+    inner: Mutex<HeapInner>,
+    /// Whether `inner.marking` is set, read by write barriers without locking
+    marking: AtomicBool,
+    allocated_since_collection: AtomicUsize,
+}
+
+impl Heap {
+    pub fn new() -> &'static Self {
+        Box::leak(Box::default())
+    }
+    pub fn with_config(config: HeapConfig) -> &'static Self {
+        Box::leak(Box::new(Self {
+            config: RwLock::new(config),
+            ..Default::default()
+        }))
+    }
+    pub fn config(&self) -> HeapConfig {
+        *self.config.read().unwrap()
+    }
+    /// Replace the configuration, which applies from the next allocation or collection on
+    pub fn set_config(&self, config: HeapConfig) {
+        *self.config.write().unwrap() = config;
+    }
+    pub fn global() -> &'static Self {
+        static GLOBAL: LazyLock<Heap> = LazyLock::new(Heap::default);
+        &GLOBAL
+    }
+    pub(crate) fn lock(&self) -> MutexGuard<'_, HeapInner> {
+        self.inner.lock().unwrap()
+    }
+}
+
+impl Heap {
+    /// Move `val` into this heap. The result is not rooted.
+    ///
+    /// [`HeapConfig::max_bytes`] is not checked, see [`Heap::try_alloc`].
+    pub fn alloc<T: Trace>(&'static self, val: T) -> Gc<T> {
+        self.alloc_locked(&mut self.lock(), val)
+    }
+    /// Like [`Heap::alloc`], but fails if the live objects would occupy more than
+    /// [`HeapConfig::max_bytes`]. `val` is handed back with the error, so that the caller can
+    /// collect and retry.
+    pub fn try_alloc<T: Trace>(&'static self, val: T) -> Result<Gc<T>, (OutOfMemory, T)> {
+        let mut heap = self.lock();
+        let requested = size_of::<T>() + val.payload_size();
+        if let Some(limit) = self.config().max_bytes
+            && heap.stats.live_bytes + requested > limit
+        {
+            return Err((OutOfMemory { requested, limit }, val));
+        }
+        Ok(self.alloc_locked(&mut heap, val))
+    }
+    /// Count `additional` bytes the payload of an object grows by, failing like
+    /// [`Heap::try_alloc`] if the live objects would then occupy more than
+    /// [`HeapConfig::max_bytes`]
+    pub fn try_grow(&self, additional: usize) -> Result<(), OutOfMemory> {
+        let mut heap = self.lock();
+        if let Some(limit) = self.config().max_bytes
+            && heap.stats.live_bytes + additional > limit
+        {
+            return Err(OutOfMemory {
+                requested: additional,
+                limit,
+            });
+        }
+        self.allocated_since_collection
+            .fetch_add(additional, Ordering::Relaxed);
+        heap.stats.live_bytes += additional;
+        heap.stats.total_allocated_bytes += additional as u64;
+        Ok(())
+    }
+    fn alloc_locked<T: Trace>(&'static self, heap: &mut HeapInner, val: T) -> Gc<T> {
+        let payload_size = val.payload_size();
+        let size = size_of::<T>() + payload_size;
+        let large = self
+            .config()
+            .large_object_threshold
+            .is_some_and(|x| payload_size >= x);
+        let mut header = heap.arenas.alloc(val, large);
+        if let Some(marking) = &mut heap.marking {
+            // allocated black, as the marker has no reference to it to follow,
+            // so what it references must be shaded like stores are
+            unsafe { header.as_mut() }.marked = true;
+            marking
+                .gray
+                .extend(unsafe { (*Header::as_dyn(header)).trace() });
+        }
+        self.allocated_since_collection
+            .fetch_add(size, Ordering::Relaxed);
+        heap.stats.live_objects += 1;
+        heap.stats.live_bytes += size;
+        heap.stats.total_allocated_bytes += size as u64;
+        if large {
+            heap.stats.large_objects += 1;
+            heap.stats.large_bytes += size;
+        }
+        Gc::from_inner(InnerGc { header, heap: self })
+    }
+    pub(crate) fn root(&self, gc: InnerGc) {
+        let mut heap = self.lock();
+        if !heap.roots.contains(&gc) {
+            heap.roots.push(gc);
+        }
+    }
+    pub(crate) fn unroot(&self, gc: InnerGc) {
+        self.lock().roots.retain(|x| gc.ne(x));
+    }
+}
+
+impl Heap {
+    /// Collect every object unreachable from the roots created by [`Gc::root`]
+    pub fn collect(&self) {
+        self.collect_with_roots(|_| {});
+    }
+    /// Like [`Heap::collect`], but `visit_roots` may report extra roots
+    /// (e.g. values living in registers) which are not rooted themselves.
+    pub fn collect_with_roots(&self, visit_roots: impl FnOnce(&mut dyn FnMut(&dyn Trace))) {
+        self.collect_generation(Generation::Old, visit_roots);
+    }
+    /// Collect `generation` and every younger generation.
+    ///
+    /// A young collection treats every old object as alive. A collection started by
+    /// [`Heap::collect_incrementally`] is finished first.
+    pub fn collect_generation(
+        &self,
+        generation: Generation,
+        visit_roots: impl FnOnce(&mut dyn FnMut(&dyn Trace)),
+    ) {
+        let mut heap = self.lock();
+        let start = Instant::now();
+        let mut roots = Vec::new();
+        visit_roots(&mut |root| roots.extend(root.trace()));
+        let running = heap.marking.as_ref().map(|x| x.generation);
+        if running == Some(Generation::Young) && generation == Generation::Old {
+            self.finish_marking(&mut heap, roots.clone(), start);
+        }
+        if heap.marking.is_none() {
+            self.start_marking(&mut heap, generation);
+        }
+        self.finish_marking(&mut heap, roots, start);
+    }
+    /// Do a bounded part of a full collection, so that pauses stay short on big heaps.
+    ///
+    /// A collection is started once [`HeapConfig::incremental_threshold`] bytes have been
+    /// allocated since the last one. Every call marks at most [`HeapConfig::mark_budget`]
+    /// objects, the call finding nothing left to mark rescans the roots and sweeps.
+    /// `visit_roots` is only called when starting or finishing and reports roots like
+    /// [`Heap::collect_with_roots`].
+    ///
+    /// Until the collection is finished every reference stored into an object must be
+    /// reported with [`Gc::write_barrier`]. Returns whether a collection was finished.
+    pub fn collect_incrementally(
+        &self,
+        visit_roots: impl FnOnce(&mut dyn FnMut(&dyn Trace)),
+    ) -> bool {
+        if !self.wants_incremental_step() {
+            return false;
+        }
+        let mut heap = self.lock();
+        let start = Instant::now();
+        let Some(marking) = &mut heap.marking else {
+            self.start_marking(&mut heap, Generation::Old);
+            let marking = heap.marking.as_mut().unwrap();
+            visit_roots(&mut |root| marking.gray.extend(root.trace()));
+            marking.longest_pause = start.elapsed();
+            return false;
+        };
+        let mut gray = std::mem::take(&mut marking.gray);
+        mark(&heap.arenas, &mut gray, self.config().mark_budget);
+        let marking = heap.marking.as_mut().unwrap();
+        marking.gray = gray;
+        if !marking.gray.is_empty() {
+            marking.longest_pause = marking.longest_pause.max(start.elapsed());
+            return false;
+        }
+        let mut roots = Vec::new();
+        visit_roots(&mut |root| roots.extend(root.trace()));
+        self.finish_marking(&mut heap, roots, start);
+        true
+    }
+    /// Whether [`Heap::collect_incrementally`] has work to do
+    pub fn wants_incremental_step(&self) -> bool {
+        self.config().mark_budget != 0
+            && (self.marking.load(Ordering::Relaxed)
+                || self.allocated_since_collection.load(Ordering::Relaxed)
+                    >= self.config().incremental_threshold)
+    }
+    /// Shade `gc` gray if a collection is marking, see [`Gc::write_barrier`]
+    pub(crate) fn shade(&self, gc: InnerGc) {
+        if !self.marking.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut heap = self.lock();
+        if let Some(marking) = &mut heap.marking
+            && !gc.is_marked()
+        {
+            marking.gray.push(gc.addr());
+        }
+    }
+    /// Every object alive regardless of the roots reported by the caller
+    fn heap_roots(heap: &mut HeapInner, generation: Generation) -> Vec<usize> {
+        let mut roots = heap
+            .roots
+            .iter()
+            .chain(heap.finalization_queue.iter())
+            .map(InnerGc::addr)
+            .collect::<Vec<_>>();
+        roots.extend(handle::live_targets(heap));
+        roots.extend(
+            heap.arenas
+                .objects()
+                .filter(|x| {
+                    let header = unsafe { x.as_ref() };
+                    header.pins > 0
+                        || (generation == Generation::Young && header.generation == Generation::Old)
+                })
+                .map(|x| Header::payload(x) as usize),
+        );
+        roots
+    }
+    fn start_marking(&self, heap: &mut HeapInner, generation: Generation) {
+        heap.marking = Some(Marking {
+            generation,
+            gray: Self::heap_roots(heap, generation),
+            longest_pause: Duration::ZERO,
+        });
+        self.marking.store(true, Ordering::Relaxed);
+    }
+    /// Rescan the roots, mark everything left and sweep
+    fn finish_marking(&self, heap: &mut HeapInner, roots: Vec<usize>, start: Instant) {
+        let Marking {
+            generation,
+            mut gray,
+            longest_pause,
+        } = heap.marking.take().unwrap();
+        gray.extend(Self::heap_roots(heap, generation));
+        gray.extend(roots);
+        mark(&heap.arenas, &mut gray, usize::MAX);
+        weak::clear_unmarked(heap);
+        let mut resurrected = finalize::queue_unmarked(heap);
+        mark(&heap.arenas, &mut resurrected, usize::MAX);
+        let HeapInner { arenas, stats, .. } = heap;
+        // payloads may have grown or shrunk since allocation, so the survivors are counted anew
+        (stats.live_objects, stats.live_bytes) = (0, 0);
+        (stats.large_objects, stats.large_bytes) = (0, 0);
+        for mut header in arenas.objects().collect::<Vec<_>>() {
+            let header_ref = unsafe { header.as_mut() };
+            if header_ref.marked {
+                header_ref.marked = false;
+                header_ref.generation = Generation::Old;
+                let size = object_size(header);
+                stats.live_objects += 1;
+                stats.live_bytes += size;
+                if header_ref.large {
+                    stats.large_objects += 1;
+                    stats.large_bytes += size;
+                }
+            } else {
+                unsafe { arenas.free(header) };
+            }
+        }
+        stats.collection_counts[Generation::Young as usize] += 1;
+        if generation == Generation::Old {
+            stats.collection_counts[Generation::Old as usize] += 1;
+        }
+        stats.last_pause = longest_pause.max(start.elapsed());
+        self.marking.store(false, Ordering::Relaxed);
+        self.allocated_since_collection.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Heap {
+    /// Move live objects together and release the memory left empty. Returns the number of
+    /// moved objects.
+    ///
+    /// Objects rooted by [`Gc::root`], pinned by [`Gc::pin`] or queued for finalization stay
+    /// in place, as they may be referenced from outside the heap, and so do the
+    /// [`Trace::immovable`] targets of the objects and of the roots reported by `visit_roots`.
+    /// References inside the heap and from [`Handle`](crate::Handle)s are updated here,
+    /// `relocate_roots` must update the roots reported to the collection with
+    /// [`Trace::relocate`]. Any other reference to a moved object is left dangling.
+    pub fn compact(
+        &self,
+        visit_roots: impl FnOnce(&mut dyn FnMut(&dyn Trace)),
+        relocate_roots: impl FnOnce(&dyn Fn(usize) -> Option<usize>),
+    ) -> usize {
+        let mut heap = self.lock();
+        let mut fixed = heap
+            .roots
+            .iter()
+            .chain(heap.finalization_queue.iter())
+            .map(InnerGc::addr)
+            .collect::<HashSet<_>>();
+        for header in heap.arenas.objects() {
+            fixed.extend(unsafe { (*Header::as_dyn(header)).immovable() });
+        }
+        visit_roots(&mut |root| fixed.extend(root.immovable()));
+        let forwarding = heap.arenas.compact(&|header| {
+            header.pins == 0 && !fixed.contains(&(Header::payload(header.into()) as usize))
+        });
+        if forwarding.is_empty() {
+            return 0;
+        }
+        let forward = |addr| forwarding.get(&addr).copied();
+        for header in heap.arenas.objects() {
+            unsafe { (*Header::as_dyn(header)).relocate(&forward) };
+        }
+        for gc in heap.finalizable.iter_mut() {
+            gc.relocate(&forward);
+        }
+        if let Some(marking) = &mut heap.marking {
+            for addr in marking.gray.iter_mut() {
+                *addr = forward(*addr).unwrap_or(*addr);
+            }
+        }
+        weak::relocate(&mut heap, &forward);
+        handle::relocate(&mut heap, &forward);
+        relocate_roots(&forward);
+        forwarding.len()
+    }
+}
+
+/// Bytes occupied by the object and its payload
+pub(crate) fn object_size(header: NonNull<Header>) -> usize {
+    unsafe { header.as_ref().vtable().size + (*Header::as_dyn(header)).payload_size() }
+}
+
+/// Mark the objects reachable from `gray`, until `budget` objects have been marked
+fn mark(arenas: &Arenas, gray: &mut Vec<usize>, mut budget: usize) {
+    while budget > 0
+        && let Some(addr) = gray.pop()
+    {
+        let Some(mut header) = arenas.header_of(addr) else {
+            continue;
+        };
+        let header_ref = unsafe { header.as_mut() };
+        if !header_ref.marked {
+            header_ref.marked = true;
+            budget -= 1;
+            gray.extend(unsafe { (*Header::as_dyn(header)).trace() });
+        }
+    }
+}
+
+impl Heap {
+    pub fn stats(&self) -> HeapStats {
+        self.lock().stats
+    }
+    /// Count the live objects of type `T`, grouped by `key`
+    pub fn count_objects_by<T: Trace, K: Eq + std::hash::Hash>(
+        &self,
+        mut key: impl FnMut(&T) -> K,
+    ) -> HashMap<K, ObjectCount> {
+        let heap = self.lock();
+        let mut counts = HashMap::<K, ObjectCount>::new();
+        for header in heap.arenas.objects() {
+            if unsafe { header.as_ref() }.is::<T>() {
+                let val = unsafe { &*Header::payload(header).cast::<T>() };
+                let count = counts.entry(key(val)).or_default();
+                count.count += 1;
+                count.bytes += object_size(header);
+            }
+        }
+        counts
+    }
+    /// Whether an object of type `T` for which `pred` holds is reachable from the roots, the
+    /// ones `visit_roots` reports included like [`Heap::collect_with_roots`]. Nothing is marked
+    /// or freed, so the answer can be acted on before any object goes away.
+    pub fn any_reachable<T: Trace>(
+        &self,
+        mut pred: impl FnMut(&T) -> bool,
+        visit_roots: impl FnOnce(&mut dyn FnMut(&dyn Trace)),
+    ) -> bool {
+        let mut heap = self.lock();
+        let mut pending = Self::heap_roots(&mut heap, Generation::Old);
+        visit_roots(&mut |root| pending.extend(root.trace()));
+        let mut seen = HashSet::new();
+        while let Some(addr) = pending.pop() {
+            let Some(header) = heap.arenas.header_of(addr) else {
+                continue;
+            };
+            if !seen.insert(addr) {
+                continue;
+            }
+            if unsafe { header.as_ref() }.is::<T>()
+                && pred(unsafe { &*Header::payload(header).cast::<T>() })
+            {
+                return true;
+            }
+            pending.extend(unsafe { (*Header::as_dyn(header)).trace() });
+        }
+        false
+    }
+}
+
+impl Debug for Heap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Heap")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
@@ -1,83 +1,135 @@
-#![allow(static_mut_refs)]
-#![feature(downcast_unchecked)]
 #![feature(decl_macro)]
 
 pub use derives::*;
 
+mod arena;
+mod finalize;
+mod handle;
+mod heap;
+mod snapshot;
+mod stats;
+#[cfg(test)]
+mod tests;
 mod trace;
+mod weak;
 
 use std::{
-    any::Any,
-    collections::HashSet,
     fmt::Debug,
     marker::PhantomData,
-    mem::MaybeUninit,
     ops::{Deref, DerefMut},
-    ptr,
-    sync::Once,
+    ptr::NonNull,
 };
 
+use arena::Header;
+
+pub use handle::{Handle, Pinned};
+pub use heap::{Generation, Heap, HeapConfig, OutOfMemory};
+pub use snapshot::{Dominator, Dominators, HeapSnapshot, Retainer, SnapshotObject, SnapshotRoot};
+pub use stats::{HeapStats, ObjectCount};
 pub use trace::Trace;
+pub use weak::WeakGc;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Gc<T> {
     inner: InnerGc,
     _phantom_data: PhantomData<*mut T>,
 }
 
+// implemented by hand, deriving them would require `T` to be `Copy` and `PartialEq` too
+impl<T> Clone for Gc<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Gc<T> {}
+
+impl<T> PartialEq for Gc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<T> Eq for Gc<T> {}
+
 impl<T: Trace> Gc<T> {
-    /// Create a root reference to `val`
+    /// Create a root reference to `val` in the global heap
     pub fn new(val: T) -> Self {
-        let val = Box::leak(Box::new(val));
-        let inner = InnerGc {
-            data: val as *mut T,
-        };
-        let this = Self {
-            inner,
-            _phantom_data: PhantomData,
-        };
-        all().push(inner);
-        roots().push(inner);
+        let this = Heap::global().alloc(val);
+        this.root();
         this
     }
     pub fn root(&self) {
-        if !roots().contains(&self.inner) {
-            roots().push(self.inner)
-        }
+        self.heap().root(self.inner);
     }
     pub fn unroot(&self) {
-        roots().retain(|x| self.inner.eq(x));
+        self.heap().unroot(self.inner);
+    }
+    /// Keep this object alive and in place during compacting collections until [`Gc::unpin`]
+    /// is called. Prefer the scoped [`Gc::pinned`].
+    ///
+    /// Pins are counted, every call must be paired with one to [`Gc::unpin`].
+    pub fn pin(&self) {
+        let _heap = self.heap().lock();
+        unsafe { (*self.inner.header.as_ptr()).pins += 1 };
+    }
+    pub fn unpin(&self) {
+        let _heap = self.heap().lock();
+        unsafe { (*self.inner.header.as_ptr()).pins -= 1 };
+    }
+    /// Report that a reference to this object has been stored into another object.
+    ///
+    /// While [`Heap::collect_incrementally`] is marking, this shades the object so that it
+    /// is not freed because the marker had already visited the object storing it.
+    pub fn write_barrier(&self) {
+        self.heap().shade(self.inner);
+    }
+    /// Create a weak reference to this object, which does not keep it alive
+    pub fn downgrade(&self) -> WeakGc<T> {
+        WeakGc::new(self)
+    }
+}
+
+impl<T> Gc<T> {
+    pub(crate) fn from_inner(inner: InnerGc) -> Self {
+        Self {
+            inner,
+            _phantom_data: PhantomData,
+        }
+    }
+    pub(crate) fn heap(&self) -> &'static Heap {
+        self.inner.heap
+    }
+    /// The address of the object, which is also its id in a [`HeapSnapshot`]
+    pub fn addr(&self) -> usize {
+        self.inner.addr()
     }
 }
 
 impl<T: Trace> Trace for Gc<T> {
     fn trace(&self) -> Vec<usize> {
-        (**self).trace()
+        vec![self.inner.addr()]
+    }
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+        self.inner.relocate(forward);
     }
 }
 
 impl<T: Trace> Deref for Gc<T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
-        unsafe {
-            let x: &dyn Any = &*self.inner.data;
-            x.downcast_ref_unchecked()
-        }
+        unsafe { &*self.inner.data().cast() }
     }
 }
 
 impl<T: Trace> DerefMut for Gc<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe {
-            let x: &mut dyn Any = &mut *self.inner.data;
-            x.downcast_mut_unchecked()
-        }
+        unsafe { &mut *self.inner.data().cast() }
     }
 }
 
 impl<T: Trace + Debug> std::fmt::Pointer for Gc<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:p}", self.inner.data)
+        write!(f, "{:p}", self.inner.data())
     }
 }
 
@@ -87,55 +139,52 @@ impl<T: Trace + Debug> Debug for Gc<T> {
     }
 }
 
-#[derive(Clone, Copy, Eq)]
+#[derive(Clone, Copy)]
 struct InnerGc {
-    data: *mut dyn Trace,
+    header: NonNull<Header>,
+    heap: &'static Heap,
 }
 
 impl PartialEq for InnerGc {
     fn eq(&self, other: &Self) -> bool {
-        ptr::addr_eq(self.data, other.data)
+        self.header == other.header
     }
 }
 
-fn roots() -> &'static mut Vec<InnerGc> {
-    static mut DATA: MaybeUninit<Vec<InnerGc>> = MaybeUninit::uninit();
-    static ONCE: Once = Once::new();
+impl Eq for InnerGc {}
 
-    unsafe {
-        ONCE.call_once(|| {
-            DATA = MaybeUninit::new(Vec::new());
-        });
-        DATA.assume_init_mut()
-    }
-}
+// managed pointers are handed between CPUs like `Value`s, the heap guards its bookkeeping with a lock
+unsafe impl Send for InnerGc {}
+unsafe impl Sync for InnerGc {}
 
-fn all() -> &'static mut Vec<InnerGc> {
-    static mut DATA: MaybeUninit<Vec<InnerGc>> = MaybeUninit::uninit();
-    static ONCE: Once = Once::new();
-
-    unsafe {
-        ONCE.call_once(|| {
-            DATA = MaybeUninit::new(Vec::new());
-        });
-        DATA.assume_init_mut()
+impl InnerGc {
+    fn data(&self) -> *mut u8 {
+        Header::payload(self.header)
     }
-}
-
-pub fn collect() {
-    let mut marked_set = HashSet::new();
-    for gc in roots() {
-        unsafe {
-            for ele in (*gc.data).trace() {
-                marked_set.insert(ele);
-            }
-        }
+    fn addr(&self) -> usize {
+        self.data() as usize
+    }
+    fn is<T: Trace>(&self) -> bool {
+        unsafe { self.header.as_ref() }.is::<T>()
+    }
+    fn is_marked(&self) -> bool {
+        unsafe { self.header.as_ref() }.marked
     }
-    for (i, gc) in all().iter().enumerate() {
-        if !marked_set.contains(&i) {
-            unsafe {
-                gc.data.drop_in_place();
-            }
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+        if let Some(new) = forward(self.addr()) {
+            self.header = unsafe { Header::of_payload(new as *mut u8) };
         }
     }
 }
+
+/// Collect every object of the global heap unreachable from the roots
+/// created by [`Gc::new`] and [`Gc::root`]
+pub fn collect() {
+    Heap::global().collect();
+}
+
+/// Like [`collect`], but `visit_roots` may report extra roots
+/// (e.g. values living in registers) which are not rooted themselves.
+pub fn collect_with_roots(visit_roots: impl FnOnce(&mut dyn FnMut(&dyn Trace))) {
+    Heap::global().collect_with_roots(visit_roots);
+}
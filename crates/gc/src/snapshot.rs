@@ -0,0 +1,388 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{Heap, InnerGc, Trace, arena::Header, handle, heap};
+
+const HEADER: &str = "pl-heap-snapshot 1";
+
+/// An object of a [`HeapSnapshot`]. The `id` is the address of the object when the snapshot
+/// was taken.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SnapshotObject {
+    pub id: usize,
+    pub type_name: String,
+    pub size: usize,
+    /// Names and printed values of the fields
+    pub fields: Vec<(String, String)>,
+    /// Ids of the objects referenced directly
+    pub references: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotRoot {
+    /// Where the reference lives, e.g. a register or a static
+    pub name: String,
+    pub target: usize,
+}
+
+/// The whole object graph of a heap together with its roots
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeapSnapshot {
+    pub objects: Vec<SnapshotObject>,
+    pub roots: Vec<SnapshotRoot>,
+}
+
+impl Heap {
+    /// Record every object of this heap.
+    ///
+    /// `describe` gives the type name and the fields of objects of type `T`,
+    /// it must not allocate in this heap. `visit_roots` reports named roots like
+    /// [`Heap::collect_with_roots`].
+    pub fn snapshot<T: Trace>(
+        &self,
+        mut describe: impl FnMut(&T) -> (String, Vec<(String, String)>),
+        visit_roots: impl FnOnce(&mut dyn FnMut(&str, &dyn Trace)),
+    ) -> HeapSnapshot {
+        let mut heap = self.lock();
+        let handles = handle::live_targets(&mut heap);
+        let objects = heap
+            .arenas
+            .objects()
+            .map(|header| {
+                let (type_name, fields) = if unsafe { header.as_ref() }.is::<T>() {
+                    describe(unsafe { &*Header::payload(header).cast::<T>() })
+                } else {
+                    (String::from("<unknown>"), Vec::new())
+                };
+                let mut references = unsafe { (*Header::as_dyn(header)).trace() };
+                references.retain(|x| heap.arenas.header_of(*x).is_some());
+                SnapshotObject {
+                    id: Header::payload(header) as usize,
+                    type_name,
+                    size: heap::object_size(header),
+                    fields,
+                    references,
+                }
+            })
+            .collect();
+        let mut roots = Vec::new();
+        let mut push_roots = |name: &str, targets: Vec<usize>| {
+            roots.extend(
+                targets
+                    .into_iter()
+                    .filter(|x| heap.arenas.header_of(*x).is_some())
+                    .map(|target| SnapshotRoot {
+                        name: name.to_owned(),
+                        target,
+                    }),
+            );
+        };
+        push_roots("root", heap.roots.iter().map(InnerGc::addr).collect());
+        push_roots("handle", handles);
+        push_roots(
+            "pinned",
+            heap.arenas
+                .objects()
+                .filter(|x| unsafe { x.as_ref() }.pins > 0)
+                .map(|x| Header::payload(x) as usize)
+                .collect(),
+        );
+        push_roots(
+            "finalization queue",
+            heap.finalization_queue.iter().map(InnerGc::addr).collect(),
+        );
+        visit_roots(&mut |name, root| push_roots(name, root.trace()));
+        HeapSnapshot { objects, roots }
+    }
+}
+
+impl HeapSnapshot {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_to(&mut file)?;
+        file.flush()
+    }
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+    /// Write the snapshot as tab separated lines
+    pub fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "{HEADER}")?;
+        for root in &self.roots {
+            writeln!(w, "root\t{}\t{}", root.target, escape(&root.name))?;
+        }
+        for object in &self.objects {
+            writeln!(
+                w,
+                "object\t{}\t{}\t{}",
+                object.id,
+                object.size,
+                escape(&object.type_name)
+            )?;
+            for (name, val) in &object.fields {
+                writeln!(w, "field\t{}\t{}", escape(name), escape(val))?;
+            }
+            for reference in &object.references {
+                writeln!(w, "ref\t{reference}")?;
+            }
+        }
+        Ok(())
+    }
+    pub fn read_from(r: impl BufRead) -> io::Result<Self> {
+        let mut lines = r.lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            return Err(invalid_data("not a heap snapshot"));
+        }
+        let mut this = Self::default();
+        for line in lines {
+            let line = line?;
+            let mut parts = line.split('\t');
+            match parts.next() {
+                Some("root") => this.roots.push(SnapshotRoot {
+                    target: parse(parts.next())?,
+                    name: unescape(parts.next())?,
+                }),
+                Some("object") => this.objects.push(SnapshotObject {
+                    id: parse(parts.next())?,
+                    size: parse(parts.next())?,
+                    type_name: unescape(parts.next())?,
+                    fields: Vec::new(),
+                    references: Vec::new(),
+                }),
+                Some("field") => {
+                    let field = (unescape(parts.next())?, unescape(parts.next())?);
+                    this.last_object()?.fields.push(field);
+                }
+                Some("ref") => {
+                    let reference = parse(parts.next())?;
+                    this.last_object()?.references.push(reference);
+                }
+                _ => return Err(invalid_data(format!("unknown line `{line}`"))),
+            }
+        }
+        Ok(this)
+    }
+    fn last_object(&mut self) -> io::Result<&mut SnapshotObject> {
+        self.objects
+            .last_mut()
+            .ok_or_else(|| invalid_data("field or reference before any object"))
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: Option<&str>) -> io::Result<String> {
+    let s = s.ok_or_else(|| invalid_data("missing column"))?;
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => result.push('\\'),
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            _ => return Err(invalid_data(format!("bad escape in `{s}`"))),
+        }
+    }
+    Ok(result)
+}
+
+fn parse(s: Option<&str>) -> io::Result<usize> {
+    s.ok_or_else(|| invalid_data("missing column"))?
+        .parse()
+        .map_err(invalid_data)
+}
+
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Something holding a reference to an object
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Retainer<'a> {
+    Root(&'a SnapshotRoot),
+    Object(&'a SnapshotObject),
+}
+
+/// The closest node every path from the roots to an object goes through
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dominator {
+    /// The object is reachable through more than one root, or is a root itself
+    Roots,
+    Object(usize),
+}
+
+/// The dominator tree of a [`HeapSnapshot`]. Objects unreachable from the roots are not in it.
+#[derive(Clone, Debug, Default)]
+pub struct Dominators {
+    idom: HashMap<usize, Dominator>,
+    retained: HashMap<usize, usize>,
+}
+
+impl Dominators {
+    pub fn dominator(&self, id: usize) -> Option<Dominator> {
+        self.idom.get(&id).copied()
+    }
+    /// Bytes which would be freed if the object became unreachable
+    pub fn retained_size(&self, id: usize) -> Option<usize> {
+        self.retained.get(&id).copied()
+    }
+    /// The `n` objects with the largest retained sizes, largest first
+    pub fn largest(&self, n: usize) -> Vec<(usize, usize)> {
+        let mut result = self
+            .retained
+            .iter()
+            .map(|(&id, &size)| (id, size))
+            .collect::<Vec<_>>();
+        result.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        result.truncate(n);
+        result
+    }
+}
+
+impl HeapSnapshot {
+    pub fn object(&self, id: usize) -> Option<&SnapshotObject> {
+        self.objects.iter().find(|x| x.id == id)
+    }
+    /// Every root and object referencing `id` directly
+    pub fn retainers(&self, id: usize) -> Vec<Retainer<'_>> {
+        self.roots
+            .iter()
+            .filter(|x| x.target == id)
+            .map(Retainer::Root)
+            .chain(
+                self.objects
+                    .iter()
+                    .filter(|x| x.references.contains(&id))
+                    .map(Retainer::Object),
+            )
+            .collect()
+    }
+    /// Compute the dominator tree with the algorithm of Cooper, Harvey and Kennedy.
+    ///
+    /// Node `0` stands for the root set, objects are numbered from `1` in snapshot order.
+    pub fn dominators(&self) -> Dominators {
+        let index = self
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x.id, i + 1))
+            .collect::<HashMap<_, _>>();
+        let node_count = self.objects.len() + 1;
+        let mut successors = vec![Vec::new(); node_count];
+        successors[0] = self
+            .roots
+            .iter()
+            .filter_map(|x| index.get(&x.target).copied())
+            .collect();
+        for (i, object) in self.objects.iter().enumerate() {
+            successors[i + 1] = object
+                .references
+                .iter()
+                .filter_map(|x| index.get(x).copied())
+                .collect();
+        }
+
+        // reverse postorder of the nodes reachable from the roots
+        let mut postorder = vec![usize::MAX; node_count];
+        let mut order = Vec::new();
+        let mut visited = vec![false; node_count];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((node, next)) = stack.last_mut() {
+            if let Some(&succ) = successors[*node].get(*next) {
+                *next += 1;
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            } else {
+                postorder[*node] = order.len();
+                order.push(*node);
+                stack.pop();
+            }
+        }
+        order.reverse();
+
+        let mut predecessors = vec![Vec::new(); node_count];
+        for &node in &order {
+            for &succ in &successors[node] {
+                predecessors[succ].push(node);
+            }
+        }
+        let mut idom = vec![usize::MAX; node_count];
+        idom[0] = 0;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in order.iter().skip(1) {
+                let mut new_idom = usize::MAX;
+                for &pred in &predecessors[node] {
+                    if idom[pred] == usize::MAX {
+                        continue;
+                    }
+                    new_idom = if new_idom == usize::MAX {
+                        pred
+                    } else {
+                        intersect(&idom, &postorder, pred, new_idom)
+                    };
+                }
+                if idom[node] != new_idom {
+                    idom[node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut retained = vec![0; node_count];
+        for &node in order.iter().skip(1) {
+            retained[node] = self.objects[node - 1].size;
+        }
+        for &node in order.iter().skip(1).rev() {
+            let size = retained[node];
+            retained[idom[node]] += size;
+        }
+        let id_of = |node: usize| self.objects[node - 1].id;
+        Dominators {
+            idom: order
+                .iter()
+                .skip(1)
+                .map(|&node| {
+                    let dominator = match idom[node] {
+                        0 => Dominator::Roots,
+                        x => Dominator::Object(id_of(x)),
+                    };
+                    (id_of(node), dominator)
+                })
+                .collect(),
+            retained: order
+                .iter()
+                .skip(1)
+                .map(|&node| (id_of(node), retained[node]))
+                .collect(),
+        }
+    }
+}
+
+fn intersect(idom: &[usize], postorder: &[usize], mut a: usize, mut b: usize) -> usize {
+    while a != b {
+        while postorder[a] < postorder[b] {
+            a = idom[a];
+        }
+        while postorder[b] < postorder[a] {
+            b = idom[b];
+        }
+    }
+    a
+}
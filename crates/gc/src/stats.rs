@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use crate::Generation;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    pub live_objects: usize,
+    /// Bytes occupied by the live objects and their payloads
+    pub live_bytes: usize,
+    /// The live objects in the large-object space, which are counted in `live_objects` too
+    pub large_objects: usize,
+    /// Bytes occupied by `large_objects` and their payloads
+    pub large_bytes: usize,
+    /// Bytes allocated since the heap was created, including collected objects
+    pub total_allocated_bytes: u64,
+    /// Indexed by [`Generation`]. Collecting a generation collects every younger one too,
+    /// so it is counted for each of them.
+    pub collection_counts: [u64; Generation::COUNT],
+    pub last_pause: Duration,
+}
+
+impl HeapStats {
+    pub fn collection_count(&self, generation: Generation) -> u64 {
+        self.collection_counts[generation as usize]
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ObjectCount {
+    pub count: usize,
+    pub bytes: usize,
+}
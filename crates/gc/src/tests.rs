@@ -0,0 +1,144 @@
+use std::{
+    ptr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use crate::{Gc, Heap, Trace, collect};
+
+/// The heap is shared by every test, which must not collect it at the same time
+static HEAP: Mutex<()> = Mutex::new(());
+
+struct Node {
+    next: Option<Gc<Node>>,
+    dropped: &'static AtomicUsize,
+}
+
+impl Node {
+    fn alloc(next: Option<Gc<Node>>, dropped: &'static AtomicUsize) -> Gc<Node> {
+        Gc::new(Self { next, dropped })
+    }
+}
+
+impl Trace for Node {
+    fn trace(&self) -> Vec<usize> {
+        self.next.iter().flat_map(Trace::trace).collect()
+    }
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+        if let Some(next) = &mut self.next {
+            next.relocate(forward);
+        }
+    }
+}
+
+/// Holds a node through an `Arc` which may be shared outside of the heap
+struct Holder(Arc<Option<Gc<Node>>>);
+
+impl Trace for Holder {
+    fn trace(&self) -> Vec<usize> {
+        self.0.trace()
+    }
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+        self.0.relocate(forward);
+    }
+    fn immovable(&self) -> Vec<usize> {
+        self.0.immovable()
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_unroot() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    let _heap = HEAP.lock().unwrap();
+    let a = Node::alloc(None, &DROPPED);
+    let b = Node::alloc(None, &DROPPED);
+    a.unroot();
+    collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+    b.unroot();
+    collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_collect_reachable() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    let _heap = HEAP.lock().unwrap();
+    let tail = Node::alloc(None, &DROPPED);
+    tail.unroot();
+    let middle = Node::alloc(Some(tail), &DROPPED);
+    middle.unroot();
+    // reached through the chain from `head` only
+    let head = Node::alloc(Some(middle), &DROPPED);
+    collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+    head.unroot();
+    collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn test_weak() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    let _heap = HEAP.lock().unwrap();
+    let target = Node::alloc(None, &DROPPED);
+    let weak = target.downgrade();
+    collect();
+    assert!(weak.upgrade().is_some());
+    target.unroot();
+    collect();
+    assert!(!weak.is_alive());
+    assert!(weak.upgrade().is_none());
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_compact_shared_arc() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    let _heap = HEAP.lock().unwrap();
+    // garbage in front of the target, so that compacting moves it unless it is kept in place
+    for _ in 0..8 {
+        Node::alloc(None, &DROPPED).unroot();
+    }
+    let target = Node::alloc(None, &DROPPED);
+    target.unroot();
+    let addr = target.addr();
+    let shared = Arc::new(Some(target));
+    let holder = Gc::new(Holder(shared.clone()));
+    collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 8);
+    Heap::global().compact(|_| {}, |_| {});
+    assert_eq!(holder.0.as_ref().as_ref().unwrap().addr(), addr);
+    assert!(shared.as_ref().as_ref().unwrap().next.is_none());
+    holder.unroot();
+    drop(shared);
+    collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 9);
+}
+
+#[test]
+fn test_any_reachable() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    let _heap = HEAP.lock().unwrap();
+    let leaf = Node::alloc(None, &DROPPED);
+    leaf.unroot();
+    let addr = leaf.addr();
+    let head = Node::alloc(Some(leaf), &DROPPED);
+    head.unroot();
+    let is_leaf = |x: &Node| ptr::from_ref(x) as usize == addr;
+    // reached only through the root reported
+    assert!(Heap::global().any_reachable(is_leaf, |visit| visit(&head)));
+    assert!(!Heap::global().any_reachable(is_leaf, |_| {}));
+    // asking collects nothing
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+    collect();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 2);
+}
@@ -1,9 +1,29 @@
-use std::any::Any;
+use std::{
+    any::Any,
+    collections::HashMap,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    mem,
+    sync::Arc,
+};
 
 use global::{IndexMap, StringName};
 
 pub trait Trace: Any {
     fn trace(&self) -> Vec<usize>;
+    /// Point every reference reported by [`Trace::trace`] whose target has been moved by a
+    /// compacting collection to the new address given by `forward`
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>);
+    /// Targets of references which [`Trace::relocate`] can't update, like the ones behind an
+    /// `Arc` shared with other owners. Compacting collections leave these objects in place.
+    fn immovable(&self) -> Vec<usize> {
+        Vec::new()
+    }
+    /// Bytes owned by this value outside of its object, like the buffer of a `Vec`.
+    /// Counted in the heap statistics, big payloads put their object in the large-object space.
+    fn payload_size(&self) -> usize {
+        0
+    }
 }
 
 impl<T: Trace> Trace for Vec<T> {
@@ -14,9 +34,145 @@ impl<T: Trace> Trace for Vec<T> {
         }
         result
     }
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+        for v in self {
+            v.relocate(forward);
+        }
+    }
+    fn immovable(&self) -> Vec<usize> {
+        self.iter().flat_map(Trace::immovable).collect()
+    }
+    fn payload_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(Trace::payload_size).sum::<usize>()
+    }
+}
+
+impl<T: Trace, const N: usize> Trace for [T; N] {
+    fn trace(&self) -> Vec<usize> {
+        self.iter().flat_map(Trace::trace).collect()
+    }
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+        for v in self {
+            v.relocate(forward);
+        }
+    }
+    fn immovable(&self) -> Vec<usize> {
+        self.iter().flat_map(Trace::immovable).collect()
+    }
+    fn payload_size(&self) -> usize {
+        self.iter().map(Trace::payload_size).sum()
+    }
+}
+
+impl<T: ?Sized + 'static> Trace for PhantomData<T> {
+    fn trace(&self) -> Vec<usize> {
+        Vec::new()
+    }
+    fn relocate(&mut self, _: &dyn Fn(usize) -> Option<usize>) {}
+}
+
+impl<T: Trace> Trace for Option<T> {
+    fn trace(&self) -> Vec<usize> {
+        self.as_ref().map(Trace::trace).unwrap_or_default()
+    }
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+        if let Some(v) = self {
+            v.relocate(forward);
+        }
+    }
+    fn immovable(&self) -> Vec<usize> {
+        self.as_ref().map(Trace::immovable).unwrap_or_default()
+    }
+    fn payload_size(&self) -> usize {
+        self.as_ref().map_or(0, Trace::payload_size)
+    }
+}
+
+impl<T: Trace + ?Sized> Trace for Box<T> {
+    fn trace(&self) -> Vec<usize> {
+        (**self).trace()
+    }
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+        (**self).relocate(forward);
+    }
+    fn immovable(&self) -> Vec<usize> {
+        (**self).immovable()
+    }
+    fn payload_size(&self) -> usize {
+        size_of_val(&**self) + (**self).payload_size()
+    }
+}
+
+/// While the `Arc` is shared its value can't be changed, so the objects it references are
+/// [`Trace::immovable`] and never need relocating.
+impl<T: Trace + ?Sized> Trace for Arc<T> {
+    fn trace(&self) -> Vec<usize> {
+        (**self).trace()
+    }
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+        if let Some(v) = Arc::get_mut(self) {
+            v.relocate(forward);
+        }
+    }
+    fn immovable(&self) -> Vec<usize> {
+        if Arc::strong_count(self) == 1 && Arc::weak_count(self) == 0 {
+            (**self).immovable()
+        } else {
+            (**self).trace()
+        }
+    }
+    fn payload_size(&self) -> usize {
+        size_of_val(&**self) + (**self).payload_size()
+    }
+}
+
+impl<K: Trace + Hash + Eq, V: Trace, S: BuildHasher + Default + 'static> Trace
+    for HashMap<K, V, S>
+{
+    fn trace(&self) -> Vec<usize> {
+        let mut result = Vec::new();
+        for (k, v) in self {
+            result.extend(Trace::trace(k));
+            result.extend(Trace::trace(v));
+        }
+        result
+    }
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+        for v in self.values_mut() {
+            v.relocate(forward);
+        }
+        // moving a key changes its hash, so the map is rebuilt
+        if self
+            .keys()
+            .any(|k| k.trace().into_iter().any(|x| forward(x).is_some()))
+        {
+            *self = mem::take(self)
+                .into_iter()
+                .map(|(mut k, v)| {
+                    k.relocate(forward);
+                    (k, v)
+                })
+                .collect();
+        }
+    }
+    fn immovable(&self) -> Vec<usize> {
+        let mut result = Vec::new();
+        for (k, v) in self {
+            result.extend(k.immovable());
+            result.extend(v.immovable());
+        }
+        result
+    }
+    fn payload_size(&self) -> usize {
+        self.capacity() * (size_of::<K>() + size_of::<V>())
+            + self
+                .iter()
+                .map(|(k, v)| k.payload_size() + v.payload_size())
+                .sum::<usize>()
+    }
 }
 
-impl<K: Trace, V: Trace> Trace for IndexMap<K, V> {
+impl<K: Trace + Hash + Eq, V: Trace> Trace for IndexMap<K, V> {
     fn trace(&self) -> Vec<usize> {
         let mut result = Vec::new();
         for (k, v) in self {
@@ -25,11 +181,75 @@ impl<K: Trace, V: Trace> Trace for IndexMap<K, V> {
         }
         result
     }
+    fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+        for v in self.values_mut() {
+            v.relocate(forward);
+        }
+        // moving a key changes its hash, so the map is rebuilt
+        if self
+            .keys()
+            .any(|k| k.trace().into_iter().any(|x| forward(x).is_some()))
+        {
+            *self = mem::take(self)
+                .into_iter()
+                .map(|(mut k, v)| {
+                    k.relocate(forward);
+                    (k, v)
+                })
+                .collect();
+        }
+    }
+    fn immovable(&self) -> Vec<usize> {
+        let mut result = Vec::new();
+        for (k, v) in self {
+            result.extend(k.immovable());
+            result.extend(v.immovable());
+        }
+        result
+    }
+    fn payload_size(&self) -> usize {
+        self.capacity() * (size_of::<K>() + size_of::<V>())
+            + self
+                .iter()
+                .map(|(k, v)| k.payload_size() + v.payload_size())
+                .sum::<usize>()
+    }
 }
 
+macro impl_tuple($($t:ident $i:tt),*) {
+    impl<$($t: Trace),*> Trace for ($($t,)*) {
+        fn trace(&self) -> Vec<usize> {
+            let mut result = Vec::new();
+            $(result.extend(self.$i.trace());)*
+            result
+        }
+        fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+            $(self.$i.relocate(forward);)*
+        }
+        fn immovable(&self) -> Vec<usize> {
+            let mut result = Vec::new();
+            $(result.extend(self.$i.immovable());)*
+            result
+        }
+        fn payload_size(&self) -> usize {
+            0 $(+ self.$i.payload_size())*
+        }
+    }
+}
+
+impl_tuple!(A 0);
+impl_tuple!(A 0, B 1);
+impl_tuple!(A 0, B 1, C 2);
+impl_tuple!(A 0, B 1, C 2, D 3);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
 macro impl_empty($($t:ty)*) {$(
 	impl Trace for $t {
 		fn trace(&self) -> Vec<usize> { Vec::new() }
+		fn relocate(&mut self, _: &dyn Fn(usize) -> Option<usize>) {}
 	}
 )*}
 
@@ -46,5 +266,22 @@ impl_empty! {
     i64
     i128
 
+    usize
+    isize
+    f32
+    f64
+    bool
+    char
+
     StringName
 }
+
+impl Trace for String {
+    fn trace(&self) -> Vec<usize> {
+        Vec::new()
+    }
+    fn relocate(&mut self, _: &dyn Fn(usize) -> Option<usize>) {}
+    fn payload_size(&self) -> usize {
+        self.capacity()
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+use crate::{Gc, InnerGc, Trace, heap::HeapInner};
+
+pub(crate) struct WeakSlot {
+    target: Mutex<Option<InnerGc>>,
+}
+
+/// A reference to a [`Gc`] object which does not keep it alive.
+///
+/// Once the target has been collected, [`WeakGc::upgrade`] returns `None`.
+pub struct WeakGc<T> {
+    slot: Arc<WeakSlot>,
+    _phantom_data: PhantomData<*mut T>,
+}
+
+impl<T: Trace> WeakGc<T> {
+    pub fn new(target: &Gc<T>) -> Self {
+        let slot = Arc::new(WeakSlot {
+            target: Mutex::new(Some(target.inner)),
+        });
+        target.heap().lock().weak_slots.push(Arc::downgrade(&slot));
+        Self {
+            slot,
+            _phantom_data: PhantomData,
+        }
+    }
+    /// Create a weak reference without target
+    pub fn empty() -> Self {
+        Self {
+            slot: Arc::new(WeakSlot {
+                target: Mutex::new(None),
+            }),
+            _phantom_data: PhantomData,
+        }
+    }
+    pub fn upgrade(&self) -> Option<Gc<T>> {
+        self.slot.target.lock().unwrap().map(Gc::from_inner)
+    }
+    pub fn is_alive(&self) -> bool {
+        self.slot.target.lock().unwrap().is_some()
+    }
+}
+
+impl<T> Clone for WeakGc<T> {
+    fn clone(&self) -> Self {
+        Self {
+            slot: self.slot.clone(),
+            _phantom_data: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for WeakGc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.slot, &other.slot)
+    }
+}
+
+impl<T> Eq for WeakGc<T> {}
+
+impl<T: Trace> Trace for WeakGc<T> {
+    fn trace(&self) -> Vec<usize> {
+        Vec::new()
+    }
+    /// Weak targets are relocated by the heap
+    fn relocate(&mut self, _: &dyn Fn(usize) -> Option<usize>) {}
+}
+
+impl<T: Trace + Debug> Debug for WeakGc<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.upgrade() {
+            Some(target) => write!(f, "WeakGc({target:?})"),
+            None => write!(f, "WeakGc(<collected>)"),
+        }
+    }
+}
+
+/// Empty every weak reference whose target is going to be swept
+pub(crate) fn clear_unmarked(heap: &mut HeapInner) {
+    heap.weak_slots.retain(|slot| {
+        let Some(slot) = slot.upgrade() else {
+            return false;
+        };
+        let mut target = slot.target.lock().unwrap();
+        if target.is_some_and(|x| !x.is_marked()) {
+            *target = None;
+        }
+        true
+    });
+}
+
+/// Point every weak reference whose target has been moved to its new address
+pub(crate) fn relocate(heap: &mut HeapInner, forward: &dyn Fn(usize) -> Option<usize>) {
+    for slot in heap.weak_slots.iter().filter_map(std::sync::Weak::upgrade) {
+        if let Some(target) = slot.target.lock().unwrap().as_mut() {
+            target.relocate(forward);
+        }
+    }
+}
@@ -1,9 +1,11 @@
-#![feature(extend_one)]
+use std::collections::HashSet;
 
 use proc_macro_crate::FoundCrate;
-use proc_macro2::{Span, TokenStream};
+use proc_macro2::{Span, TokenStream, TokenTree};
 use quote::{format_ident, quote};
-use syn::{DeriveInput, Ident, parse_macro_input};
+use syn::{
+    Attribute, DeriveInput, Fields, Ident, Member, Path, Type, parse_macro_input, parse_quote,
+};
 
 fn get_crate_name_of(name: &str, span: Span) -> Ident {
     let Ok(crate_name) = proc_macro_crate::crate_name(name) else {
@@ -15,119 +17,209 @@ fn get_crate_name_of(name: &str, span: Span) -> Ident {
     }
 }
 
-#[proc_macro_derive(Trace, attributes(ignore_trace))]
+fn is_ignored(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|x| {
+        x.path()
+            .get_ident()
+            .map(|y| y.eq("ignore_trace"))
+            .unwrap_or(false)
+    })
+}
+
+/// The module given by `#[trace(with = path)]`, whose `trace` and `relocate` functions are used
+/// instead of the `Trace` impl of the field
+fn trace_with(attrs: &[Attribute]) -> syn::Result<Option<Path>> {
+    let mut with = None;
+    for attr in attrs.iter().filter(|x| x.path().is_ident("trace")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("with") {
+                with = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `with = path`"))
+            }
+        })?;
+    }
+    Ok(with)
+}
+
+/// Whether `tokens` mention any of `params`
+fn mentions(tokens: TokenStream, params: &HashSet<Ident>) -> bool {
+    tokens.into_iter().any(|x| match x {
+        TokenTree::Ident(ident) => params.contains(&ident),
+        TokenTree::Group(group) => mentions(group.stream(), params),
+        _ => false,
+    })
+}
+
+struct Binding {
+    name: Ident,
+    with: Option<Path>,
+}
+
+/// Bind every traced field of `fields` as `_0`, `_1`, ...
+/// Returns the pattern matching the fields and the bindings.
+fn bind_fields<'a>(
+    fields: &'a Fields,
+    traced_types: &mut Vec<&'a Type>,
+) -> syn::Result<(TokenStream, Vec<Binding>)> {
+    let mut bindings = Vec::new();
+    let mut pattern = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        if is_ignored(&field.attrs) {
+            continue;
+        }
+        let name = format_ident!("_{}", i);
+        let member = match &field.ident {
+            Some(name) => Member::Named(name.clone()),
+            None => Member::Unnamed(i.into()),
+        };
+        pattern.push(quote!(#member: #name));
+        let with = trace_with(&field.attrs)?;
+        if with.is_none() {
+            traced_types.push(&field.ty);
+        }
+        bindings.push(Binding { name, with });
+    }
+    Ok((quote!({ #(#pattern,)* .. }), bindings))
+}
+
+#[proc_macro_derive(Trace, attributes(ignore_trace, trace))]
 pub fn derive_trace(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let gc_name = get_crate_name_of("pure_lang_gc", Span::call_site());
     let ast = parse_macro_input!(input as DeriveInput);
+    expand(ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(mut ast: DeriveInput) -> syn::Result<TokenStream> {
+    let gc_name = get_crate_name_of("pure_lang_gc", Span::call_site());
     let ty_name = &ast.ident;
-    let (i_g, g, w) = ast.generics.split_for_impl();
-    let ts = match &ast.data {
+    let mut traced_types = Vec::new();
+    let arms = match &ast.data {
         syn::Data::Struct(data_struct) => {
-            let mut ts = TokenStream::new();
-            for (i, field) in data_struct.fields.iter().enumerate() {
-                if field.attrs.iter().any(|x| {
-                    x.path()
-                        .get_ident()
-                        .map(|y| y.eq("ignore_trace"))
-                        .unwrap_or(false)
-                }) {
-                    continue;
-                }
-                match &field.ident {
-                    Some(name) => ts.extend_one(quote! {
-                        result.extend(#gc_name ::Trace::trace(&self. #name));
-                    }),
-                    None => ts.extend_one(quote! {
-                        result.extend(#gc_name ::Trace::trace(&self. #i));
-                    }),
-                }
-            }
-            ts
+            let (pattern, bindings) = bind_fields(&data_struct.fields, &mut traced_types)?;
+            vec![(quote!(Self #pattern), bindings)]
         }
-        syn::Data::Enum(data_enum) => {
-            let mut ts = TokenStream::new();
-            ts.extend_one(quote!(match self));
-            let mut holder = TokenStream::new();
-            for variant in &data_enum.variants {
-                if variant.attrs.iter().any(|x| {
-                    x.path()
-                        .get_ident()
-                        .map(|y| y.eq("ignore_trace"))
-                        .unwrap_or(false)
-                }) {
-                    continue;
-                }
+        syn::Data::Enum(data_enum) => data_enum
+            .variants
+            .iter()
+            .map(|variant| {
                 let name = &variant.ident;
-                holder.extend_one(quote!(Self:: #name));
-                match &variant.fields {
-                    syn::Fields::Named(fields_named) => {
-                        let fields = &fields_named.named;
-                        for field in fields {
-                            if field.attrs.iter().any(|x| {
-                                x.path()
-                                    .get_ident()
-                                    .map(|y| y.eq("ignore_trace"))
-                                    .unwrap_or(false)
-                            }) {
-                                continue;
-                            }
-                            match &field.ident {
-                                Some(name) => holder.extend_one(quote! {
-                                    result.extend(#gc_name ::Trace::trace(&self. #name));
-                                }),
-                                None => panic!(),
-                            }
-                        }
-                    }
-                    syn::Fields::Unnamed(fields_unnamed) => {
-                        let fields = &fields_unnamed.unnamed;
-                        let identifiers: Vec<_> = fields
-                            .iter()
-                            .enumerate()
-                            .map(|(i, x)| {
-                                if x.attrs.iter().any(|x| {
-                                    x.path()
-                                        .get_ident()
-                                        .map(|y| y.eq("ignore_trace"))
-                                        .unwrap_or(false)
-                                }) {
-                                    Ident::new("_", Span::call_site())
-                                } else {
-                                    format_ident!("_{}", i)
-                                }
-                            })
-                            .collect();
-                        for field in fields.iter() {
-                            if field.attrs.iter().any(|x| {
-                                x.path()
-                                    .get_ident()
-                                    .map(|y| y.eq("ignore_trace"))
-                                    .unwrap_or(false)
-                            }) {
-                                continue;
-                            }
-                            holder.extend_one(quote! {
-                                (#(#identifiers,)*)=> {#(result.extend(#gc_name ::Trace::trace(#identifiers));)*}
-                            });
-                        }
-                    }
-                    syn::Fields::Unit => holder.extend_one(quote!(=> (),)),
+                if is_ignored(&variant.attrs) {
+                    return Ok((quote!(Self:: #name { .. }), Vec::new()));
                 }
-            }
-            ts.extend_one(quote!({#holder}));
-            ts
+                let (pattern, bindings) = bind_fields(&variant.fields, &mut traced_types)?;
+                Ok((quote!(Self:: #name #pattern), bindings))
+            })
+            .collect::<syn::Result<_>>()?,
+        syn::Data::Union(data_union) => {
+            return Err(syn::Error::new_spanned(
+                data_union.union_token,
+                "`Trace` cannot be derived for unions",
+            ));
         }
-        syn::Data::Union(_data_union) => todo!(),
     };
-    quote! {
+
+    // `Trace: Any`, so every type parameter must be `'static`, and the fields using one must
+    // be `Trace` themselves
+    let params = ast
+        .generics
+        .type_params()
+        .map(|x| x.ident.clone())
+        .collect::<HashSet<_>>();
+    let bounds = params
+        .iter()
+        .map(|x| -> syn::WherePredicate { parse_quote!(#x: 'static) })
+        .chain(
+            traced_types
+                .into_iter()
+                .filter(|x| mentions(quote!(#x), &params))
+                .map(|x| parse_quote!(#x: #gc_name ::Trace)),
+        )
+        .collect::<Vec<_>>();
+    ast.generics.make_where_clause().predicates.extend(bounds);
+    let (i_g, g, w) = ast.generics.split_for_impl();
+
+    let trace_arms = arms.iter().map(|(pattern, bindings)| {
+        let traces = bindings.iter().map(|Binding { name, with }| match with {
+            Some(with) => quote!(result.extend(#with ::trace(#name));),
+            None => quote!(result.extend(#gc_name ::Trace::trace(#name));),
+        });
+        quote! {
+            #pattern => {
+                #(#traces)*
+            }
+        }
+    });
+    let relocate_arms = arms.iter().map(|(pattern, bindings)| {
+        let relocates = bindings.iter().map(|Binding { name, with }| match with {
+            Some(with) => quote!(#with ::relocate(#name, forward);),
+            None => quote!(#gc_name ::Trace::relocate(#name, forward);),
+        });
+        quote! {
+            #pattern => {
+                #(#relocates)*
+            }
+        }
+    });
+    // fields traced `with` a module are relocated by its `relocate`, which must reach every target
+    let immovable_arms = arms.iter().map(|(pattern, bindings)| {
+        let immovables = bindings
+            .iter()
+            .filter(|x| x.with.is_none())
+            .map(|Binding { name, .. }| quote!(result.extend(#gc_name ::Trace::immovable(#name));));
+        quote! {
+            #pattern => {
+                #(#immovables)*
+            }
+        }
+    });
+    // fields traced `with` a module own no payload as far as the heap knows
+    let payload_arms = arms.iter().map(|(pattern, bindings)| {
+        let payloads = bindings
+            .iter()
+            .filter(|x| x.with.is_none())
+            .map(|Binding { name, .. }| quote!(result += #gc_name ::Trace::payload_size(#name);));
+        quote! {
+            #pattern => {
+                #(#payloads)*
+            }
+        }
+    });
+    Ok(quote! {
         #[automatically_derived]
         impl #i_g #gc_name ::Trace for #ty_name #g #w {
             fn trace(&self) -> Vec<usize> {
                 let mut result = Vec::new();
-                #ts
+                #[allow(unreachable_patterns)]
+                match self {
+                    #(#trace_arms)*
+                }
+                result
+            }
+            fn relocate(&mut self, forward: &dyn Fn(usize) -> Option<usize>) {
+                #[allow(unreachable_patterns)]
+                match self {
+                    #(#relocate_arms)*
+                }
+            }
+            fn immovable(&self) -> Vec<usize> {
+                let mut result = Vec::new();
+                #[allow(unreachable_patterns)]
+                match self {
+                    #(#immovable_arms)*
+                }
+                result
+            }
+            fn payload_size(&self) -> usize {
+                let mut result = 0;
+                #[allow(unreachable_patterns)]
+                match self {
+                    #(#payload_arms)*
+                }
                 result
             }
         }
-    }
-    .into()
+    })
 }
@@ -0,0 +1,128 @@
+use std::{fmt, io};
+
+use global::{StringName, StringTypeReference};
+
+use crate::type_system::{AssemblyDependency, AssemblyVersion};
+
+/// Errors of the runtime which [`global::errors::RuntimeError`] has no variant for.
+///
+/// They are carried to callers by an [`io::Error`] of a matching kind, from which `get_ref`
+/// recovers the `VMError`.
+#[derive(Debug, Clone)]
+pub enum VMError {
+    /// An allocation, or the growth of an object, would go over the heap limit even after
+    /// a full collection
+    OutOfMemory(gc::OutOfMemory),
+    /// The type argument `arg` given for the type variable `var` does not derive from
+    /// `parent`, the parent its binding requires
+    UnmetParentConstraint {
+        var: StringName,
+        arg: StringTypeReference,
+        parent: StringTypeReference,
+    },
+    /// More than one overload of the method `name` is the best match for the arguments of a
+    /// call
+    AmbiguousMethod {
+        name: StringName,
+        candidates: Vec<StringName>,
+    },
+    /// The assembly `name` cannot be unloaded while `reason`
+    AssemblyInUse { name: StringName, reason: String },
+    /// The core assembly is never unloaded
+    UnloadCore,
+    /// The assembly `name` at `version` is added while version `loaded` of it is loaded
+    AssemblyConflict {
+        name: StringName,
+        version: AssemblyVersion,
+        loaded: AssemblyVersion,
+    },
+    /// The assembly `name` at `version` needs `dependency`, of which version `loaded` is
+    /// loaded instead
+    DependencyVersionMismatch {
+        name: StringName,
+        version: AssemblyVersion,
+        dependency: AssemblyDependency,
+        loaded: AssemblyVersion,
+    },
+    /// The assembly `name` at `version` needs `dependency`, which is not loaded
+    MissingDependency {
+        name: StringName,
+        version: AssemblyVersion,
+        dependency: AssemblyDependency,
+    },
+}
+
+impl VMError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            Self::OutOfMemory(_) => io::ErrorKind::OutOfMemory,
+            Self::UnmetParentConstraint { .. }
+            | Self::AmbiguousMethod { .. }
+            | Self::UnloadCore => io::ErrorKind::InvalidInput,
+            Self::AssemblyInUse { .. } => io::ErrorKind::ResourceBusy,
+            Self::AssemblyConflict { .. } => io::ErrorKind::AlreadyExists,
+            Self::DependencyVersionMismatch { .. } => io::ErrorKind::InvalidData,
+            Self::MissingDependency { .. } => io::ErrorKind::NotFound,
+        }
+    }
+}
+
+impl fmt::Display for VMError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfMemory(e) => write!(f, "out of memory: {e}"),
+            Self::UnmetParentConstraint { var, arg, parent } => write!(
+                f,
+                "type argument {} does not meet the parent constraint {} of {var}",
+                arg.string_name_repr(),
+                parent.string_name_repr()
+            ),
+            Self::AmbiguousMethod { name, candidates } => write!(
+                f,
+                "ambiguous call to `{name}` between {}",
+                candidates
+                    .iter()
+                    .map(|x| format!("`{x}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::AssemblyInUse { name, reason } => {
+                write!(f, "assembly {name} is in use: {reason}")
+            }
+            Self::UnloadCore => write!(f, "the core assembly cannot be unloaded"),
+            Self::AssemblyConflict {
+                name,
+                version,
+                loaded,
+            } => write!(
+                f,
+                "cannot load assembly {name} {version}: version {loaded} is loaded already"
+            ),
+            Self::DependencyVersionMismatch {
+                name,
+                version,
+                dependency,
+                loaded,
+            } => write!(
+                f,
+                "assembly {name} {version} depends on {dependency}, but version {loaded} is loaded"
+            ),
+            Self::MissingDependency {
+                name,
+                version,
+                dependency,
+            } => write!(
+                f,
+                "assembly {name} {version} depends on {dependency}, which is not loaded"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VMError {}
+
+impl From<VMError> for global::Error {
+    fn from(e: VMError) -> Self {
+        io::Error::new(e.kind(), e).into()
+    }
+}
@@ -8,6 +8,7 @@
 #![feature(decl_macro)]
 #![feature(macro_metavar_expr)]
 #![feature(likely_unlikely)]
+#![feature(once_cell_try)]
 #![feature(format_args_nl)]
 #![feature(once_cell_get_mut)]
 #![feature(lock_value_accessors)]
@@ -23,6 +24,7 @@
     non_snake_case
 )]
 
+pub mod errors;
 mod pl_lib_impl;
 pub mod type_system;
 pub mod value;
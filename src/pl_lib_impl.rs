@@ -1,6 +1,9 @@
 #![allow(nonstandard_style)]
 
 use crate::type_system::{Assembly, AssemblyManager};
+use crate::value::Value;
+use crate::vm::CPU;
+use global::errors::{DynamicCheckingItem, RuntimeError};
 use global::{StringName, StringTypeReference};
 use std::sync::Arc;
 
@@ -9,6 +12,10 @@ pub mod System_String;
 
 pub mod System_Array_1;
 
+pub mod System_WeakReference_1;
+
+pub mod System_GC;
+
 pub mod System_Null;
 
 pub mod System_Object;
@@ -23,9 +30,29 @@ pub mod System_Integers;
 
 pub mod System_Boolean;
 
+pub mod System_Type;
+
+pub mod System_Reflection;
+
 #[cfg(test)]
 mod tests;
 
+/// Fail a native method given `args` if it does not take `expected` arguments, when dynamic
+/// checking is enabled
+pub(crate) fn check_arg_len(cpu: &Arc<CPU>, args: &[Value], expected: usize) -> global::Result<()> {
+    if cpu.vm().is_dynamic_checking_enabled() && args.len() != expected {
+        return Err(arg_len_error(args.len(), expected));
+    }
+    Ok(())
+}
+
+/// The error of a method given `got` arguments where it takes `expected`
+pub(crate) fn arg_len_error(got: usize, expected: usize) -> global::Error {
+    RuntimeError::DynamicCheckingFailed(DynamicCheckingItem::ArgLen { got, expected })
+        .throw()
+        .into()
+}
+
 pub trait ClassLoadToCore {
     const STRING_TYPE_REFERENCE: StringTypeReference;
     fn load_class(core_assembly: &Arc<Assembly>, assembly_manager: &AssemblyManager);
@@ -547,7 +547,7 @@ pub mod to_vm {
                         .into(),
                     );
                 }
-                let ty = this_val.string_type_reference();
+                let ty = this_val.string_type_reference(&cpu)?;
                 if ty != StringTypeReference::core_static_single_type("System.Void") {
                     return Err(
                         RuntimeError::DynamicCheckingFailed(DynamicCheckingItem::Type {
@@ -564,7 +564,7 @@ pub mod to_vm {
                 return Ok(Value::Void);
             };
             if cpu.vm().is_dynamic_checking_enabled() {
-                let ty = arg0.string_type_reference();
+                let ty = arg0.string_type_reference(&cpu)?;
                 if ty != StringTypeReference::core_static_single_type("System.String") {
                     return Err(
                         RuntimeError::DynamicCheckingFailed(DynamicCheckingItem::Type {
@@ -586,10 +586,13 @@ pub mod to_vm {
             };
             let mut output = arg0.get().as_bytes().to_vec();
             output.push(b'\n');
-            super::get_out()
-                .lock()
-                .unwrap()
-                .write_all(output.as_slice())?;
+            // the console may be locked by another thread or its reader be slow
+            cpu.vm().blocking(|| {
+                super::get_out()
+                    .lock()
+                    .unwrap()
+                    .write_all(output.as_slice())
+            })?;
             Ok(Value::Void)
         }
     }
@@ -641,7 +644,7 @@ pub mod to_vm {
                                     MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{Static}), 0),
                                     mt_ptr,
                                     TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.Void")),
-                                    vec![],
+                                    vec![TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.String"))],
                                     Default::default(),
                                     Self::WriteLine__System_String,
                                 ),
@@ -696,36 +699,22 @@ pub mod to_vm {
                 }
             }
             let (this_val,) = this_val.unwrap_struct_mut()?;
-            this_val.get_mut_field("Black")?.set_val(&Value::UInt8(0));
-            this_val
-                .get_mut_field("DarkBlue")?
-                .set_val(&Value::UInt8(1));
-            this_val
-                .get_mut_field("DarkGreen")?
-                .set_val(&Value::UInt8(2));
-            this_val
-                .get_mut_field("DarkCyan")?
-                .set_val(&Value::UInt8(3));
-            this_val.get_mut_field("DarkRed")?.set_val(&Value::UInt8(4));
-            this_val
-                .get_mut_field("DarkMagenta")?
-                .set_val(&Value::UInt8(5));
-            this_val
-                .get_mut_field("DarkYellow")?
-                .set_val(&Value::UInt8(6));
-            this_val.get_mut_field("Gray")?.set_val(&Value::UInt8(7));
-            this_val
-                .get_mut_field("DarkGray")?
-                .set_val(&Value::UInt8(8));
-            this_val.get_mut_field("Blue")?.set_val(&Value::UInt8(9));
-            this_val.get_mut_field("Green")?.set_val(&Value::UInt8(10));
-            this_val.get_mut_field("Cyan")?.set_val(&Value::UInt8(11));
-            this_val.get_mut_field("Red")?.set_val(&Value::UInt8(12));
-            this_val
-                .get_mut_field("Magenta")?
-                .set_val(&Value::UInt8(13));
-            this_val.get_mut_field("Yellow")?.set_val(&Value::UInt8(14));
-            this_val.get_mut_field("White")?.set_val(&Value::UInt8(15));
+            this_val.set_field("Black", &Value::UInt8(0))?;
+            this_val.set_field("DarkBlue", &Value::UInt8(1))?;
+            this_val.set_field("DarkGreen", &Value::UInt8(2))?;
+            this_val.set_field("DarkCyan", &Value::UInt8(3))?;
+            this_val.set_field("DarkRed", &Value::UInt8(4))?;
+            this_val.set_field("DarkMagenta", &Value::UInt8(5))?;
+            this_val.set_field("DarkYellow", &Value::UInt8(6))?;
+            this_val.set_field("Gray", &Value::UInt8(7))?;
+            this_val.set_field("DarkGray", &Value::UInt8(8))?;
+            this_val.set_field("Blue", &Value::UInt8(9))?;
+            this_val.set_field("Green", &Value::UInt8(10))?;
+            this_val.set_field("Cyan", &Value::UInt8(11))?;
+            this_val.set_field("Red", &Value::UInt8(12))?;
+            this_val.set_field("Magenta", &Value::UInt8(13))?;
+            this_val.set_field("Yellow", &Value::UInt8(14))?;
+            this_val.set_field("White", &Value::UInt8(15))?;
             Ok(Value::Void)
         }
     }
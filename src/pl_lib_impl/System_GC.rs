@@ -0,0 +1,225 @@
+#![allow(nonstandard_style)]
+
+use crate::pl_lib_impl::System_Object::System_Object;
+use crate::pl_lib_impl::{ClassLoadToCore, check_arg_len};
+use crate::type_system::{
+    Assembly, AssemblyManager, Class, CommonMethod, CommonMethodTable, TypeHandle,
+};
+use crate::value::Value;
+use crate::vm::CPU;
+use enumflags2::make_bitflags;
+use gc::Generation;
+use global::attrs::{
+    ClassImplementationFlags, MethodAttr, MethodImplementationFlags, TypeAttr, TypeSpecificAttr,
+    Visibility,
+};
+use global::errors::RuntimeError;
+use global::{IndexMap, StringTypeReference, indexmap, string_name};
+use std::sync::Arc;
+
+pub struct System_GC;
+
+impl System_GC {
+    fn generation_of(val: Option<&Value>) -> global::Result<Generation> {
+        match val {
+            Some(Value::UInt64(0)) => Ok(Generation::Young),
+            Some(Value::UInt64(_)) => Ok(Generation::Old),
+            _ => Err(RuntimeError::WrongType.into()),
+        }
+    }
+
+    /// Sign: `Collect()`
+    fn Collect(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        _this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        cpu.vm().collect_generation(Generation::Old)?;
+        Ok(Value::Void)
+    }
+
+    /// Sign: `Collect([!]System.UInt64)`
+    fn Collect__System_UInt64(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        _this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 1)?;
+        cpu.vm()
+            .collect_generation(Self::generation_of(args.first())?)?;
+        Ok(Value::Void)
+    }
+
+    /// Sign: `CollectionCount([!]System.UInt64)`
+    fn CollectionCount__System_UInt64(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        _this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 1)?;
+        let generation = Self::generation_of(args.first())?;
+        Ok(Value::UInt64(
+            cpu.vm().heap().stats().collection_count(generation),
+        ))
+    }
+
+    /// Sign: `GetTotalMemory()`
+    fn GetTotalMemory(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        _this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        Ok(Value::UInt64(cpu.vm().heap().stats().live_bytes as u64))
+    }
+
+    /// Sign: `GetLiveObjectCount()`
+    fn GetLiveObjectCount(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        _this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        Ok(Value::UInt64(cpu.vm().heap().stats().live_objects as u64))
+    }
+
+    /// Sign: `GetTotalAllocatedBytes()`
+    fn GetTotalAllocatedBytes(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        _this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        Ok(Value::UInt64(cpu.vm().heap().stats().total_allocated_bytes))
+    }
+
+    /// Sign: `GetLastPauseNanoseconds()`
+    fn GetLastPauseNanoseconds(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        _this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        Ok(Value::UInt64(
+            cpu.vm().heap().stats().last_pause.as_nanos() as u64
+        ))
+    }
+}
+
+impl ClassLoadToCore for System_GC {
+    const STRING_TYPE_REFERENCE: StringTypeReference =
+        StringTypeReference::core_static_single_type("System.GC");
+    fn load_class(core_assembly: &Arc<Assembly>, _: &AssemblyManager) {
+        let class = Class::new(
+            core_assembly,
+            TypeAttr::new(
+                Visibility::Public,
+                TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{Static})),
+            ),
+            Self::STRING_TYPE_REFERENCE.unwrap_single_name_ref().clone(),
+            |class| {
+                CommonMethodTable::new(
+                    |mt_ptr| {
+                        indexmap! {
+                            string_name!(".ctor()") => CommonMethod::native(
+                                string_name!(".ctor()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(AssemblyManager::System_Void_STRUCT_REF),
+                                vec![],
+                                Default::default(),
+                                |_, _, _, _, _| Err(RuntimeError::ConstructStaticClass.into()),
+                            ),
+                            string_name!("Collect()") => CommonMethod::native(
+                                string_name!("Collect()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{Static}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(AssemblyManager::System_Void_STRUCT_REF),
+                                vec![],
+                                Default::default(),
+                                Self::Collect,
+                            ),
+                            string_name!("Collect([!]System.UInt64)") => CommonMethod::native(
+                                string_name!("Collect([!]System.UInt64)"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{Static}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(AssemblyManager::System_Void_STRUCT_REF),
+                                vec![TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.UInt64"))],
+                                Default::default(),
+                                Self::Collect__System_UInt64,
+                            ),
+                            string_name!("CollectionCount([!]System.UInt64)") => CommonMethod::native(
+                                string_name!("CollectionCount([!]System.UInt64)"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{Static}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.UInt64")),
+                                vec![TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.UInt64"))],
+                                Default::default(),
+                                Self::CollectionCount__System_UInt64,
+                            ),
+                            string_name!("GetTotalMemory()") => CommonMethod::native(
+                                string_name!("GetTotalMemory()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{Static}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.UInt64")),
+                                vec![],
+                                Default::default(),
+                                Self::GetTotalMemory,
+                            ),
+                            string_name!("GetLiveObjectCount()") => CommonMethod::native(
+                                string_name!("GetLiveObjectCount()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{Static}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.UInt64")),
+                                vec![],
+                                Default::default(),
+                                Self::GetLiveObjectCount,
+                            ),
+                            string_name!("GetTotalAllocatedBytes()") => CommonMethod::native(
+                                string_name!("GetTotalAllocatedBytes()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{Static}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.UInt64")),
+                                vec![],
+                                Default::default(),
+                                Self::GetTotalAllocatedBytes,
+                            ),
+                            string_name!("GetLastPauseNanoseconds()") => CommonMethod::native(
+                                string_name!("GetLastPauseNanoseconds()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{Static}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.UInt64")),
+                                vec![],
+                                Default::default(),
+                                Self::GetLastPauseNanoseconds,
+                            ),
+                        }
+                    },
+                    &class,
+                    Some(
+                        core_assembly
+                            .get_type(&System_Object::STRING_TYPE_REFERENCE)
+                            .unwrap(),
+                    ),
+                )
+            },
+            IndexMap::new(),
+        );
+        core_assembly.add_type(TypeHandle::Class(class));
+    }
+}
@@ -0,0 +1,496 @@
+#![allow(nonstandard_style)]
+
+use crate::pl_lib_impl::System_Object::System_Object;
+use crate::pl_lib_impl::System_Type::System_Type;
+use crate::pl_lib_impl::{ClassLoadToCore, arg_len_error, check_arg_len};
+use crate::type_system::get_traits::{GetAssemblyMust, GetTypeName};
+use crate::type_system::{
+    Assembly, AssemblyManager, Class, CommonMethod, CommonMethodTable, MethodSignature, TypeHandle,
+};
+use crate::value::{ByRefValue, FieldInfoValue, MethodInfoValue, Value};
+use crate::vm::CPU;
+use enumflags2::make_bitflags;
+use global::attrs::{
+    ClassImplementationFlags, MethodAttr, MethodImplementationFlags, TypeAttr, TypeSpecificAttr,
+    Visibility,
+};
+use global::errors::RuntimeError;
+use global::{IndexMap, StringMethodReference, StringTypeReference, indexmap, string_name};
+use std::any::Any;
+use std::sync::Arc;
+
+fn is_null(val: &Value) -> bool {
+    matches!(val, Value::Reference(r) if matches!(**r, ByRefValue::Null))
+}
+
+pub struct System_Reflection_MethodInfo;
+
+impl System_Reflection_MethodInfo {
+    fn this_info(this_val: &Value) -> global::Result<MethodInfoValue> {
+        let (this,) = this_val.unwrap_reference_ref()?;
+        let (this,) = this.unwrap_method_info_ref()?;
+        Ok(this.clone())
+    }
+    fn method_ref(info: &MethodInfoValue) -> StringMethodReference {
+        StringMethodReference::Single(info.name().clone())
+    }
+    fn signature(info: &MethodInfoValue) -> global::Result<MethodSignature> {
+        let method_ref = Self::method_ref(info);
+        match info.declaring_type() {
+            TypeHandle::Class(class) => Ok(class.mt().get_method(&method_ref)?.signature()),
+            TypeHandle::Struct(s) => Ok(s.mt().get_method(&method_ref)?.signature()),
+            TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => {
+                Err(RuntimeError::FailedGetMethod(method_ref).into())
+            }
+        }
+    }
+
+    /// Sign: `get_Name()`
+    fn get_Name(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        let signature = Self::signature(&Self::this_info(this_val)?)?;
+        System_Type::string(&cpu, signature.name().as_str())
+    }
+
+    /// Sign: `get_DeclaringType()`
+    fn get_DeclaringType(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        let info = Self::this_info(this_val)?;
+        System_Type::type_object(&cpu, info.declaring_type().clone())
+    }
+
+    /// Sign: `get_ReturnType()`
+    fn get_ReturnType(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        let signature = Self::signature(&Self::this_info(this_val)?)?;
+        System_Type::type_object(&cpu, signature.ret_type().clone())
+    }
+
+    /// Sign: `GetParameterTypes()`
+    fn GetParameterTypes(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        let signature = Self::signature(&Self::this_info(this_val)?)?;
+        let items = signature
+            .params()
+            .iter()
+            .map(|x| System_Type::type_object(&cpu, x.clone()))
+            .try_collect::<Vec<_>>()?;
+        System_Type::array(&cpu, string_name!("System.Type"), items)
+    }
+
+    /// Sign: `Invoke([!]System.Object, [!]System.Array`1[[!]System.Object])`
+    ///
+    /// Calls the method on the object, which is null for a static method. An instance method is
+    /// dispatched on the type of the object like any other call, once the object is checked to
+    /// be of the type declaring the method.
+    fn Invoke(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        // the arguments are indexed below whether dynamic checking is enabled or not
+        let [receiver, arguments] = args else {
+            return Err(arg_len_error(args.len(), 2));
+        };
+        let info = Self::this_info(this_val)?;
+        let mut arguments = match &*arguments {
+            Value::Reference(r) if matches!(**r, ByRefValue::Null) => Vec::new(),
+            Value::Reference(r) => r.unwrap_array_ref()?.0[..].to_vec(),
+            _ => return Err(RuntimeError::WrongType.into()),
+        };
+        let method_ref = Self::method_ref(&info);
+        match info.declaring_type() {
+            TypeHandle::Class(class) => {
+                let method = class.mt().get_method(&method_ref)?;
+                Self::invoke(&cpu, &info, &method, receiver, &mut arguments)
+            }
+            TypeHandle::Struct(s) => {
+                let method = s.mt().get_method(&method_ref)?;
+                Self::invoke(&cpu, &info, &method, receiver, &mut arguments)
+            }
+            TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => {
+                Err(RuntimeError::FailedGetMethod(method_ref).into())
+            }
+        }
+    }
+    /// Call `method`, which `info` reflects, with `arguments` on `receiver`, once they are
+    /// checked to be what the method takes
+    fn invoke<T: Any + GetTypeName + GetAssemblyMust>(
+        cpu: &Arc<CPU>,
+        info: &MethodInfoValue,
+        method: &CommonMethod<T>,
+        receiver: &mut Value,
+        arguments: &mut [Value],
+    ) -> global::Result<Value> {
+        // the code of a method reads as many arguments as it declares, while natives may leave
+        // them undeclared and check them themselves
+        let native = method.instructions.is_empty();
+        if (!native || !method.args.is_empty()) && arguments.len() != method.args.len() {
+            return Err(arg_len_error(arguments.len(), method.args.len()));
+        }
+        if method.attr.is_static() {
+            if !is_null(receiver) {
+                return Err(RuntimeError::WrongType.into());
+            }
+            return method.call(cpu.clone(), &mut Value::Void, arguments);
+        }
+        if is_null(receiver)
+            || !receiver
+                .ty(cpu.clone())?
+                .is_subtype_of(info.declaring_type())
+        {
+            return Err(RuntimeError::WrongType.into());
+        }
+        cpu.clone().call_instance_method::<Class>(
+            None,
+            &Self::method_ref(info),
+            receiver,
+            arguments,
+        )
+    }
+}
+
+impl ClassLoadToCore for System_Reflection_MethodInfo {
+    const STRING_TYPE_REFERENCE: StringTypeReference =
+        StringTypeReference::core_static_single_type("System.Reflection.MethodInfo");
+    fn load_class(core_assembly: &Arc<Assembly>, _: &AssemblyManager) {
+        let class = Class::new(
+            core_assembly,
+            TypeAttr::new(
+                Visibility::Public,
+                TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+            ),
+            Self::STRING_TYPE_REFERENCE.unwrap_single_name_ref().clone(),
+            |class| {
+                CommonMethodTable::new(
+                    |mt_ptr| {
+                        indexmap! {
+                            string_name!(".ctor()") => CommonMethod::native(
+                                string_name!(".ctor()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(AssemblyManager::System_Void_STRUCT_REF),
+                                vec![],
+                                Default::default(),
+                                // only given out by the runtime, see `Type.GetMethods()`
+                                |_, _, _, _, _| Err(RuntimeError::UnsupportedObjectType.into()),
+                            ),
+                            string_name!("get_Name()") => CommonMethod::native(
+                                string_name!("get_Name()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.String")),
+                                vec![],
+                                Default::default(),
+                                Self::get_Name,
+                            ),
+                            string_name!("get_DeclaringType()") => CommonMethod::native(
+                                string_name!("get_DeclaringType()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(System_Type::STRING_TYPE_REFERENCE),
+                                vec![],
+                                Default::default(),
+                                Self::get_DeclaringType,
+                            ),
+                            string_name!("get_ReturnType()") => CommonMethod::native(
+                                string_name!("get_ReturnType()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(System_Type::STRING_TYPE_REFERENCE),
+                                vec![],
+                                Default::default(),
+                                Self::get_ReturnType,
+                            ),
+                            string_name!("GetParameterTypes()") => CommonMethod::native(
+                                string_name!("GetParameterTypes()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                System_Type::array_type(System_Type::STRING_TYPE_REFERENCE),
+                                vec![],
+                                Default::default(),
+                                Self::GetParameterTypes,
+                            ),
+                            string_name!("Invoke([!]System.Object, [!]System.Array`1[[!]System.Object])") => CommonMethod::native(
+                                string_name!("Invoke([!]System.Object, [!]System.Array`1[[!]System.Object])"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(System_Object::STRING_TYPE_REFERENCE),
+                                vec![
+                                    TypeHandle::Unloaded(System_Object::STRING_TYPE_REFERENCE),
+                                    System_Type::array_type(System_Object::STRING_TYPE_REFERENCE),
+                                ],
+                                Default::default(),
+                                Self::Invoke,
+                            ),
+                        }
+                    },
+                    &class,
+                    Some(
+                        core_assembly
+                            .get_type(&System_Object::STRING_TYPE_REFERENCE)
+                            .unwrap(),
+                    ),
+                )
+            },
+            IndexMap::new(),
+        );
+        core_assembly.add_type(TypeHandle::Class(class));
+    }
+}
+
+pub struct System_Reflection_FieldInfo;
+
+impl System_Reflection_FieldInfo {
+    fn this_info(this_val: &Value) -> global::Result<FieldInfoValue> {
+        let (this,) = this_val.unwrap_reference_ref()?;
+        let (this,) = this.unwrap_field_info_ref()?;
+        Ok(this.clone())
+    }
+    /// A managed reference to the field on `target`, which is ignored for a static field
+    fn address(cpu: &Arc<CPU>, info: &FieldInfoValue, target: &Value) -> global::Result<Value> {
+        if info.is_static() {
+            cpu.static_address(
+                &info.declaring_type().string_reference(),
+                info.name().as_str(),
+            )
+        } else {
+            cpu.field_address(target, info.name().as_str())
+        }
+    }
+
+    /// Sign: `get_Name()`
+    fn get_Name(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        System_Type::string(&cpu, Self::this_info(this_val)?.name().as_str())
+    }
+
+    /// Sign: `get_DeclaringType()`
+    fn get_DeclaringType(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        let info = Self::this_info(this_val)?;
+        System_Type::type_object(&cpu, info.declaring_type().clone())
+    }
+
+    /// Sign: `get_FieldType()`
+    fn get_FieldType(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        let info = Self::this_info(this_val)?;
+        let ty = match info.declaring_type() {
+            TypeHandle::Class(class) => class.fields().get(info.name()).map(|x| x.ty().clone()),
+            TypeHandle::Struct(s) => s.fields().get(info.name()).map(|x| x.ty().clone()),
+            TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => None,
+        }
+        .ok_or(RuntimeError::FailedGetField(info.name().clone()))?;
+        System_Type::type_object(&cpu, ty)
+    }
+
+    /// Sign: `get_IsStatic()`
+    fn get_IsStatic(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        Ok(if Self::this_info(this_val)?.is_static() {
+            Value::True
+        } else {
+            Value::False
+        })
+    }
+
+    /// Sign: `GetValue([!]System.Object)`
+    fn GetValue(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 1)?;
+        let info = Self::this_info(this_val)?;
+        let target = match &args[0] {
+            x if x.is_managed_reference() => cpu.load_indirect(x)?,
+            x => x.clone(),
+        };
+        match &target {
+            Value::Struct(s) if !info.is_static() => Ok(s.get_field(info.name().as_str())?.clone()),
+            _ => cpu.load_indirect(&Self::address(&cpu, &info, &target)?),
+        }
+    }
+
+    /// Sign: `SetValue([!]System.Object, [!]System.Object)`
+    ///
+    /// A field of a struct can only be set through a managed reference to it, since the struct
+    /// passed by value is a copy
+    fn SetValue(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 2)?;
+        let info = Self::this_info(this_val)?;
+        let val = args[1].clone();
+        if !info.is_static() && args[0].is_managed_reference() {
+            let mut target = cpu.load_indirect(&args[0])?;
+            if let Value::Struct(s) = &mut target {
+                s.set_field(info.name().as_str(), &val)?;
+                cpu.store_indirect(&args[0], target)?;
+                return Ok(Value::Void);
+            }
+            cpu.store_indirect(&Self::address(&cpu, &info, &target)?, val)?;
+            return Ok(Value::Void);
+        }
+        if !info.is_static() && matches!(args[0], Value::Struct(_)) {
+            return Err(RuntimeError::WrongType.into());
+        }
+        cpu.store_indirect(&Self::address(&cpu, &info, &args[0])?, val)?;
+        Ok(Value::Void)
+    }
+}
+
+impl ClassLoadToCore for System_Reflection_FieldInfo {
+    const STRING_TYPE_REFERENCE: StringTypeReference =
+        StringTypeReference::core_static_single_type("System.Reflection.FieldInfo");
+    fn load_class(core_assembly: &Arc<Assembly>, _: &AssemblyManager) {
+        let class = Class::new(
+            core_assembly,
+            TypeAttr::new(
+                Visibility::Public,
+                TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+            ),
+            Self::STRING_TYPE_REFERENCE.unwrap_single_name_ref().clone(),
+            |class| {
+                CommonMethodTable::new(
+                    |mt_ptr| {
+                        indexmap! {
+                            string_name!(".ctor()") => CommonMethod::native(
+                                string_name!(".ctor()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(AssemblyManager::System_Void_STRUCT_REF),
+                                vec![],
+                                Default::default(),
+                                // only given out by the runtime, see `Type.GetFields()`
+                                |_, _, _, _, _| Err(RuntimeError::UnsupportedObjectType.into()),
+                            ),
+                            string_name!("get_Name()") => CommonMethod::native(
+                                string_name!("get_Name()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.String")),
+                                vec![],
+                                Default::default(),
+                                Self::get_Name,
+                            ),
+                            string_name!("get_DeclaringType()") => CommonMethod::native(
+                                string_name!("get_DeclaringType()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(System_Type::STRING_TYPE_REFERENCE),
+                                vec![],
+                                Default::default(),
+                                Self::get_DeclaringType,
+                            ),
+                            string_name!("get_FieldType()") => CommonMethod::native(
+                                string_name!("get_FieldType()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(System_Type::STRING_TYPE_REFERENCE),
+                                vec![],
+                                Default::default(),
+                                Self::get_FieldType,
+                            ),
+                            string_name!("get_IsStatic()") => CommonMethod::native(
+                                string_name!("get_IsStatic()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.Boolean")),
+                                vec![],
+                                Default::default(),
+                                Self::get_IsStatic,
+                            ),
+                            string_name!("GetValue([!]System.Object)") => CommonMethod::native(
+                                string_name!("GetValue([!]System.Object)"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(System_Object::STRING_TYPE_REFERENCE),
+                                vec![TypeHandle::Unloaded(System_Object::STRING_TYPE_REFERENCE)],
+                                Default::default(),
+                                Self::GetValue,
+                            ),
+                            string_name!("SetValue([!]System.Object, [!]System.Object)") => CommonMethod::native(
+                                string_name!("SetValue([!]System.Object, [!]System.Object)"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(AssemblyManager::System_Void_STRUCT_REF),
+                                vec![
+                                    TypeHandle::Unloaded(System_Object::STRING_TYPE_REFERENCE),
+                                    TypeHandle::Unloaded(System_Object::STRING_TYPE_REFERENCE),
+                                ],
+                                Default::default(),
+                                Self::SetValue,
+                            ),
+                        }
+                    },
+                    &class,
+                    Some(
+                        core_assembly
+                            .get_type(&System_Object::STRING_TYPE_REFERENCE)
+                            .unwrap(),
+                    ),
+                )
+            },
+            IndexMap::new(),
+        );
+        core_assembly.add_type(TypeHandle::Class(class));
+    }
+}
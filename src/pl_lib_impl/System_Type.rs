@@ -0,0 +1,390 @@
+#![allow(nonstandard_style)]
+
+use crate::pl_lib_impl::System_Object::System_Object;
+use crate::pl_lib_impl::{ClassLoadToCore, check_arg_len};
+use crate::type_system::{
+    Assembly, AssemblyManager, Class, CommonMethod, CommonMethodTable, TypeHandle, TypeVar,
+};
+use crate::value::{
+    Array, ByRefValue, FieldInfoValue, MethodInfoValue, StringValue, TypeValue, Value,
+};
+use crate::vm::CPU;
+use enumflags2::make_bitflags;
+use global::attrs::{
+    ClassImplementationFlags, MethodAttr, MethodImplementationFlags, TypeAttr, TypeSpecificAttr,
+    Visibility,
+};
+use global::errors::RuntimeError;
+use global::{IndexMap, StringName, StringTypeReference, indexmap, string_name};
+use std::sync::Arc;
+
+pub struct System_Type;
+
+impl System_Type {
+    fn this_type(this_val: &Value) -> global::Result<TypeHandle> {
+        let (this,) = this_val.unwrap_reference_ref()?;
+        let (this,) = this.unwrap_type_ref()?;
+        Ok(this.get().clone())
+    }
+    fn string_arg(val: Option<&Value>) -> global::Result<&str> {
+        match val {
+            Some(Value::Reference(r)) => Ok(r.unwrap_string_ref()?.0.get()),
+            _ => Err(RuntimeError::WrongType.into()),
+        }
+    }
+    /// The `System.Type` describing `t`
+    pub(crate) fn type_object(cpu: &Arc<CPU>, t: TypeHandle) -> global::Result<Value> {
+        Ok(Value::Reference(
+            cpu.heap_alloc(ByRefValue::Type(TypeValue::new(t)))?,
+        ))
+    }
+    pub(crate) fn string(cpu: &Arc<CPU>, s: &str) -> global::Result<Value> {
+        Ok(Value::Reference(cpu.heap_alloc(ByRefValue::String(
+            StringValue::new(s.to_owned()),
+        ))?))
+    }
+    pub(crate) fn null(cpu: &Arc<CPU>) -> global::Result<Value> {
+        Ok(Value::Reference(cpu.vm().null()?))
+    }
+    /// An array of the core type `element` holding `items`
+    pub(crate) fn array(
+        cpu: &Arc<CPU>,
+        element: StringName,
+        items: Vec<Value>,
+    ) -> global::Result<Value> {
+        let t = cpu.vm().get_core_single_type(element)?;
+        Ok(Value::Reference(Array::alloc_with_data(
+            cpu.clone(),
+            t,
+            items,
+        )?))
+    }
+    /// The handle of the array of `element`, for the signatures of the natives
+    pub(crate) fn array_type(element: StringTypeReference) -> TypeHandle {
+        TypeHandle::Unloaded(StringTypeReference::core_generic_type(
+            string_name!("System.Array`1"),
+            Arc::new(indexmap! {
+                string_name!("@T") => element,
+            }),
+        ))
+    }
+    /// The methods `t` declares itself
+    fn declared_methods(t: &TypeHandle) -> Vec<StringName> {
+        match t {
+            TypeHandle::Class(class) => class.mt().methods().into_iter().map(|x| x.name).collect(),
+            TypeHandle::Struct(s) => s.mt().methods().into_iter().map(|x| x.name).collect(),
+            TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => Vec::new(),
+        }
+    }
+    /// The fields `t` declares itself, and whether each of them is static
+    fn declared_fields(t: &TypeHandle) -> Vec<(StringName, bool)> {
+        match t {
+            TypeHandle::Class(class) => class
+                .fields()
+                .iter()
+                .map(|x| (x.0.clone(), x.1.attr().is_static()))
+                .collect(),
+            TypeHandle::Struct(s) => s
+                .fields()
+                .iter()
+                .map(|x| (x.0.clone(), x.1.attr().is_static()))
+                .collect(),
+            TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => Vec::new(),
+        }
+    }
+
+    /// Sign: `get_Name()`
+    fn get_Name(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        Self::string(&cpu, Self::this_type(this_val)?.name().as_str())
+    }
+
+    /// Sign: `get_FullName()`
+    fn get_FullName(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        let name = Self::this_type(this_val)?
+            .string_reference()
+            .string_name_repr();
+        Self::string(&cpu, name.as_str())
+    }
+
+    /// Sign: `get_AssemblyName()`
+    fn get_AssemblyName(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        match Self::this_type(this_val)?.assembly() {
+            Some(assem) => Self::string(&cpu, assem.name().as_str()),
+            None => Self::null(&cpu),
+        }
+    }
+
+    /// Sign: `get_BaseType()`
+    fn get_BaseType(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        match Self::this_type(this_val)?.parent() {
+            Some(parent) => Self::type_object(&cpu, parent),
+            None => Self::null(&cpu),
+        }
+    }
+
+    /// Sign: `GetGenericArguments()`
+    fn GetGenericArguments(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        // the type variables of a definition are bound by nothing yet
+        let items = Self::this_type(this_val)?
+            .type_vars()
+            .values()
+            .filter_map(|x| match x {
+                TypeVar::Type(t) => Some(Self::type_object(&cpu, t.clone())),
+                TypeVar::Canon(_) => None,
+            })
+            .try_collect::<Vec<_>>()?;
+        Self::array(&cpu, string_name!("System.Type"), items)
+    }
+
+    /// Sign: `GetMethods()`
+    fn GetMethods(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        let t = Self::this_type(this_val)?;
+        let items = Self::declared_methods(&t)
+            .into_iter()
+            .map(|x| {
+                Ok::<_, global::Error>(Value::Reference(
+                    cpu.heap_alloc(ByRefValue::MethodInfo(MethodInfoValue::new(t.clone(), x)))?,
+                ))
+            })
+            .try_collect::<Vec<_>>()?;
+        Self::array(&cpu, string_name!("System.Reflection.MethodInfo"), items)
+    }
+
+    /// Sign: `GetMethod([!]System.String)`
+    ///
+    /// Looks the method up by its full name, `WriteLine([!]System.String)` for instance, in the
+    /// type and then in its parents
+    fn GetMethod__System_String(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 1)?;
+        let name = Self::string_arg(args.first())?;
+        let mut ty = Some(Self::this_type(this_val)?);
+        while let Some(t) = ty {
+            if let Some(found) = Self::declared_methods(&t)
+                .into_iter()
+                .find(|x| x.as_str() == name)
+            {
+                return Ok(Value::Reference(cpu.heap_alloc(ByRefValue::MethodInfo(
+                    MethodInfoValue::new(t, found),
+                ))?));
+            }
+            ty = t.parent();
+        }
+        Self::null(&cpu)
+    }
+
+    /// Sign: `GetFields()`
+    fn GetFields(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        let t = Self::this_type(this_val)?;
+        let items = Self::declared_fields(&t)
+            .into_iter()
+            .map(|(name, is_static)| {
+                Ok::<_, global::Error>(Value::Reference(cpu.heap_alloc(ByRefValue::FieldInfo(
+                    FieldInfoValue::new(t.clone(), name, is_static),
+                ))?))
+            })
+            .try_collect::<Vec<_>>()?;
+        Self::array(&cpu, string_name!("System.Reflection.FieldInfo"), items)
+    }
+
+    /// Sign: `GetField([!]System.String)`
+    fn GetField__System_String(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 1)?;
+        let name = Self::string_arg(args.first())?;
+        let mut ty = Some(Self::this_type(this_val)?);
+        while let Some(t) = ty {
+            if let Some((found, is_static)) = Self::declared_fields(&t)
+                .into_iter()
+                .find(|x| x.0.as_str() == name)
+            {
+                return Ok(Value::Reference(cpu.heap_alloc(ByRefValue::FieldInfo(
+                    FieldInfoValue::new(t, found, is_static),
+                ))?));
+            }
+            ty = t.parent();
+        }
+        Self::null(&cpu)
+    }
+}
+
+impl ClassLoadToCore for System_Type {
+    const STRING_TYPE_REFERENCE: StringTypeReference =
+        StringTypeReference::core_static_single_type("System.Type");
+    fn load_class(core_assembly: &Arc<Assembly>, _: &AssemblyManager) {
+        let class = Class::new(
+            core_assembly,
+            TypeAttr::new(
+                Visibility::Public,
+                TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+            ),
+            Self::STRING_TYPE_REFERENCE.unwrap_single_name_ref().clone(),
+            |class| {
+                CommonMethodTable::new(
+                    |mt_ptr| {
+                        indexmap! {
+                            string_name!(".ctor()") => CommonMethod::native(
+                                string_name!(".ctor()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(AssemblyManager::System_Void_STRUCT_REF),
+                                vec![],
+                                Default::default(),
+                                // only given out by the runtime, see `Object.GetType()`
+                                |_, _, _, _, _| Err(RuntimeError::UnsupportedObjectType.into()),
+                            ),
+                            string_name!("get_Name()") => CommonMethod::native(
+                                string_name!("get_Name()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.String")),
+                                vec![],
+                                Default::default(),
+                                Self::get_Name,
+                            ),
+                            string_name!("get_FullName()") => CommonMethod::native(
+                                string_name!("get_FullName()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.String")),
+                                vec![],
+                                Default::default(),
+                                Self::get_FullName,
+                            ),
+                            string_name!("get_AssemblyName()") => CommonMethod::native(
+                                string_name!("get_AssemblyName()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.String")),
+                                vec![],
+                                Default::default(),
+                                Self::get_AssemblyName,
+                            ),
+                            string_name!("get_BaseType()") => CommonMethod::native(
+                                string_name!("get_BaseType()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(Self::STRING_TYPE_REFERENCE),
+                                vec![],
+                                Default::default(),
+                                Self::get_BaseType,
+                            ),
+                            string_name!("GetGenericArguments()") => CommonMethod::native(
+                                string_name!("GetGenericArguments()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                Self::array_type(Self::STRING_TYPE_REFERENCE),
+                                vec![],
+                                Default::default(),
+                                Self::GetGenericArguments,
+                            ),
+                            string_name!("GetMethods()") => CommonMethod::native(
+                                string_name!("GetMethods()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                Self::array_type(StringTypeReference::core_static_single_type("System.Reflection.MethodInfo")),
+                                vec![],
+                                Default::default(),
+                                Self::GetMethods,
+                            ),
+                            string_name!("GetMethod([!]System.String)") => CommonMethod::native(
+                                string_name!("GetMethod([!]System.String)"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.Reflection.MethodInfo")),
+                                vec![TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.String"))],
+                                Default::default(),
+                                Self::GetMethod__System_String,
+                            ),
+                            string_name!("GetFields()") => CommonMethod::native(
+                                string_name!("GetFields()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                Self::array_type(StringTypeReference::core_static_single_type("System.Reflection.FieldInfo")),
+                                vec![],
+                                Default::default(),
+                                Self::GetFields,
+                            ),
+                            string_name!("GetField([!]System.String)") => CommonMethod::native(
+                                string_name!("GetField([!]System.String)"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.Reflection.FieldInfo")),
+                                vec![TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.String"))],
+                                Default::default(),
+                                Self::GetField__System_String,
+                            ),
+                        }
+                    },
+                    &class,
+                    Some(
+                        core_assembly
+                            .get_type(&System_Object::STRING_TYPE_REFERENCE)
+                            .unwrap(),
+                    ),
+                )
+            },
+            IndexMap::new(),
+        );
+        core_assembly.add_type(TypeHandle::Class(class));
+    }
+}
@@ -1,10 +1,10 @@
-use crate::pl_lib_impl::StructLoadToCore;
+use crate::pl_lib_impl::System_Type::System_Type;
+use crate::pl_lib_impl::{ClassLoadToCore, StructLoadToCore, check_arg_len};
 use crate::type_system::{Assembly, AssemblyManager, CommonMethodTable, Struct, TypeHandle};
 use crate::type_system::CommonMethod;
 use crate::value::{ByRefValue, StringValue, Value};
 use crate::vm::CPU;
 use enumflags2::make_bitflags;
-use gc::Gc;
 use global::StringMethodReference;
 use global::attrs::{MethodAttr, MethodImplementationFlags};
 use global::attrs::{StructImplementationFlags, TypeAttr, TypeSpecificAttr, Visibility};
@@ -33,10 +33,23 @@ impl System_ValueType {
                 .into(),
             );
         }
-        let s = this_val.ty(cpu)?.string_reference().string_name_repr();
-        Ok(Value::Reference(Gc::new(ByRefValue::String(
+        let s = this_val
+            .ty(cpu.clone())?
+            .string_reference()
+            .string_name_repr();
+        Ok(Value::Reference(cpu.heap_alloc(ByRefValue::String(
             StringValue::new(s.as_str().to_owned()),
-        ))))
+        ))?))
+    }
+    fn GetType(
+        _: &CommonMethod<Struct>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        System_Type::type_object(&cpu, this_val.ty(cpu.clone())?)
     }
 }
 
@@ -74,6 +87,15 @@ impl StructLoadToCore for System_ValueType {
                                 Default::default(),
                                 Self::ToString,
                             ),
+                            string_name!("GetType()") => CommonMethod::native(
+                                string_name!("GetType()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(System_Type::STRING_TYPE_REFERENCE),
+                                vec![],
+                                Default::default(),
+                                Self::GetType,
+                            ),
                         }
                     },
                     &s,
@@ -0,0 +1,168 @@
+#![allow(nonstandard_style)]
+
+use crate::pl_lib_impl::System_Object::System_Object;
+use crate::pl_lib_impl::{ClassLoadToCore, check_arg_len};
+use crate::type_system::{
+    Assembly, AssemblyManager, Class, CommonMethod, CommonMethodTable, TypeHandle, TypeVar,
+};
+use crate::value::{ByRefValue, Value, WeakReferenceValue};
+use crate::vm::CPU;
+use enumflags2::make_bitflags;
+use gc::Gc;
+use global::attrs::{
+    ClassImplementationFlags, MethodAttr, MethodImplementationFlags, TypeAttr, TypeSpecificAttr,
+    Visibility,
+};
+use global::errors::RuntimeError;
+use global::{IndexMap, StringTypeReference, indexmap, string_name};
+use std::sync::Arc;
+
+pub struct System_WeakReference;
+
+impl System_WeakReference {
+    fn target_of(val: Option<&Value>) -> global::Result<Option<&Gc<ByRefValue>>> {
+        match val {
+            None => Ok(None),
+            Some(Value::Reference(r)) if matches!(**r, ByRefValue::Null) => Ok(None),
+            Some(Value::Reference(r)) => Ok(Some(r)),
+            Some(_) => Err(RuntimeError::WrongType.into()),
+        }
+    }
+
+    /// Sign: `.ctor(@T)`
+    fn ctor(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 1)?;
+        let (this,) = this_val.unwrap_reference_mut()?;
+        let t = match this.ty(cpu.vm())?.type_vars().get("@T") {
+            Some(TypeVar::Type(t)) => t.clone(),
+            _ => return Err(RuntimeError::FailedMakeGeneric.throw().into()),
+        };
+        let target = Self::target_of(args.first())?;
+        **this = ByRefValue::WeakReference(WeakReferenceValue::new(t, target));
+        Ok(Value::Void)
+    }
+
+    /// Sign: `get_Target()`
+    fn get_Target(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        let (this,) = this_val.unwrap_reference_ref()?;
+        let (this,) = this.unwrap_weak_reference_ref()?;
+        Ok(Value::Reference(match this.target() {
+            Some(target) => target,
+            None => cpu.vm().null()?,
+        }))
+    }
+
+    /// Sign: `set_Target(@T)`
+    fn set_Target(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 1)?;
+        let target = Self::target_of(args.first())?;
+        let (this,) = this_val.unwrap_reference_mut()?;
+        let (this,) = this.unwrap_weak_reference_mut()?;
+        this.set_target(target);
+        Ok(Value::Void)
+    }
+
+    /// Sign: `get_IsAlive()`
+    fn get_IsAlive(
+        _method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this_val: &mut Value,
+        args: &mut [Value],
+        _register_start: u64,
+    ) -> global::Result<Value> {
+        check_arg_len(&cpu, args, 0)?;
+        let (this,) = this_val.unwrap_reference_ref()?;
+        let (this,) = this.unwrap_weak_reference_ref()?;
+        Ok(if this.is_alive() {
+            Value::True
+        } else {
+            Value::False
+        })
+    }
+}
+
+impl ClassLoadToCore for System_WeakReference {
+    const STRING_TYPE_REFERENCE: StringTypeReference =
+        StringTypeReference::core_single_type(string_name!("System.WeakReference`1"));
+    fn load_class(core_assembly: &Arc<Assembly>, _: &AssemblyManager) {
+        let class = Class::new(
+            core_assembly,
+            TypeAttr::new(
+                Visibility::Public,
+                TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+            ),
+            Self::STRING_TYPE_REFERENCE.unwrap_single_name_ref().clone(),
+            |class| {
+                CommonMethodTable::new(
+                    |mt_ptr| {
+                        indexmap! {
+                            string_name!(".ctor(@T)") => CommonMethod::native(
+                                string_name!(".ctor(@T)"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(AssemblyManager::System_Void_STRUCT_REF),
+                                vec![TypeHandle::Generic(string_name!("@T"))],
+                                Default::default(),
+                                Self::ctor,
+                            ),
+                            string_name!("get_Target()") => CommonMethod::native(
+                                string_name!("get_Target()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Generic(string_name!("@T")),
+                                vec![],
+                                Default::default(),
+                                Self::get_Target,
+                            ),
+                            string_name!("set_Target(@T)") => CommonMethod::native(
+                                string_name!("set_Target(@T)"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(AssemblyManager::System_Void_STRUCT_REF),
+                                vec![TypeHandle::Generic(string_name!("@T"))],
+                                Default::default(),
+                                Self::set_Target,
+                            ),
+                            string_name!("get_IsAlive()") => CommonMethod::native(
+                                string_name!("get_IsAlive()"),
+                                MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 0),
+                                mt_ptr,
+                                TypeHandle::Unloaded(StringTypeReference::core_static_single_type("System.Boolean")),
+                                vec![],
+                                Default::default(),
+                                Self::get_IsAlive,
+                            ),
+                        }
+                    },
+                    &class,
+                    Some(
+                        core_assembly
+                            .get_type(&System_Object::STRING_TYPE_REFERENCE)
+                            .unwrap(),
+                    ),
+                )
+            },
+            IndexMap::new(),
+        );
+        core_assembly.add_type(TypeHandle::Class(class));
+    }
+}
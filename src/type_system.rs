@@ -1,22 +1,33 @@
 mod assembly;
 mod class;
+mod field_layout;
 pub mod get_traits;
 mod manager;
 mod method;
 mod method_table;
+mod signature;
 mod r#struct;
 #[cfg(test)]
 mod tests;
 mod type_handle;
+mod verifier;
+mod version;
 
+use crate::errors::VMError;
 pub use assembly::Assembly;
 pub use class::{Class, Field as ClassField};
-use global::StringName;
+pub use field_layout::FieldLayout;
+use field_layout::resolve_field_type;
+use global::{IndexMap, Result, StringName};
 pub use manager::AssemblyManager;
 pub use method::CommonMethod;
+pub use signature::MethodSignature;
 use std::fmt::{Debug, Formatter};
 pub use r#struct::{Field as StructField, Struct};
 pub use type_handle::TypeHandle;
+pub(crate) use type_handle::refers_to_assembly;
+pub use verifier::{VerifyError, VerifyErrorKind};
+pub use version::{AssemblyDependency, AssemblyVersion, VersionRange};
 
 pub(crate) use method_table::CommonMethodTable;
 
@@ -26,6 +37,38 @@ pub struct GenericBinding {
     pub(crate) parent: Option<TypeHandle>,
 }
 
+impl GenericBinding {
+    /// Check that `arg`, given for the type variable `var`, derives from the parent the binding
+    /// requires.
+    ///
+    /// Types carry no list of the interfaces they implement, so the interface constraints are
+    /// not checked.
+    pub fn check(&self, var: &StringName, arg: &TypeHandle) -> Result<()> {
+        match &self.parent {
+            Some(parent) if !arg.is_subtype_of(parent) => Err(VMError::UnmetParentConstraint {
+                var: var.clone(),
+                arg: arg.string_reference(),
+                parent: parent.string_reference(),
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Check the type arguments `args` against the bindings of the type variables they are given for
+pub(crate) fn check_type_args(
+    vars: &IndexMap<StringName, TypeVar>,
+    args: &IndexMap<StringName, TypeHandle>,
+) -> Result<()> {
+    for (name, arg) in args {
+        if let Some(TypeVar::Canon(binding)) = vars.get(name) {
+            binding.check(name, arg)?;
+        }
+    }
+    Ok(())
+}
+
 #[derive(Clone)]
 pub enum TypeVar {
     Type(TypeHandle),
@@ -48,4 +91,16 @@ impl TypeVar {
             Self::Canon(_) => StringName::from_static_str("System.__Canon"),
         }
     }
+    /// Whether the type bound, or the parent and interfaces the binding requires, name a type
+    /// of the assembly `assem`
+    pub(crate) fn refers_to_assembly(&self, assem: &StringName) -> bool {
+        match self {
+            Self::Type(t) => t.refers_to_assembly(assem),
+            Self::Canon(binding) => binding
+                .parent
+                .iter()
+                .chain(&binding.implemented_interfaces)
+                .any(|t| t.refers_to_assembly(assem)),
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use super::{AssemblyManager, TypeHandle};
+use super::{AssemblyDependency, AssemblyManager, AssemblyVersion, TypeHandle};
 use export::{AssemblyManagerTrait, AssemblyTrait};
 use global::{
     Error, IndexMap, Result, StringName, StringTypeReference, ThreadSafe, errors::RuntimeError,
@@ -6,7 +6,10 @@ use global::{
 use std::any::Any;
 use std::{
     collections::HashMap,
-    sync::{Arc, RwLock, Weak},
+    sync::{
+        Arc, RwLock, Weak,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
 #[derive(Clone, ThreadSafe)]
@@ -14,6 +17,14 @@ pub struct Assembly {
     name: StringName,
     manager: Weak<AssemblyManager>,
     types: Arc<RwLock<HashMap<StringName, TypeHandle>>>,
+    version: AssemblyVersion,
+    /// The assemblies which must be loaded before it, see [`AssemblyManager::add_assembly`]
+    ///
+    /// [`AssemblyManager::add_assembly`]: export::AssemblyManagerTrait::add_assembly
+    dependencies: Vec<AssemblyDependency>,
+    /// The calls of methods of the assembly which have not returned yet, see
+    /// [`Assembly::enter_frame`]
+    running_frames: Arc<AtomicUsize>,
 }
 
 impl Assembly {
@@ -22,11 +33,24 @@ impl Assembly {
             name,
             manager: Arc::downgrade(manager),
             types: Arc::new(RwLock::new(HashMap::new())),
+            version: AssemblyVersion::default(),
+            dependencies: Vec::new(),
+            running_frames: Default::default(),
         }
     }
     pub fn from_dyn(d: Arc<dyn AssemblyTrait>) -> Arc<Self> {
         unsafe { d.arc_any().downcast_unchecked() }
     }
+    /// The assembly at `version`, which needs `dependencies` loaded before it
+    pub fn with_version(
+        mut self,
+        version: AssemblyVersion,
+        dependencies: Vec<AssemblyDependency>,
+    ) -> Self {
+        self.version = version;
+        self.dependencies = dependencies;
+        self
+    }
 }
 
 impl Assembly {
@@ -79,14 +103,10 @@ impl Assembly {
                             .map(|(n, t)| Ok::<_, Error>((n.clone(), self.get_type(t)?)))
                             .try_collect::<IndexMap<_, _>>()?,
                     );
-                    match self
-                        .types
-                        .read()
-                        .unwrap()
-                        .get(ty)
-                        .map(|x| x.make_generic(type_vars))
-                    {
-                        Some(e) => e,
+                    // the definition is not kept locked, as the instantiation is added beside it
+                    let definition = self.types.read().unwrap().get(ty).cloned();
+                    match definition {
+                        Some(definition) => definition.make_generic(type_vars),
                         None => Err(RuntimeError::FailedGetType(type_ref.clone()).into()),
                     }
                 } else {
@@ -98,9 +118,91 @@ impl Assembly {
     pub fn types(&self) -> &Arc<RwLock<HashMap<StringName, TypeHandle>>> {
         &self.types
     }
+    pub fn version(&self) -> AssemblyVersion {
+        self.version
+    }
+    pub fn dependencies(&self) -> &[AssemblyDependency] {
+        &self.dependencies
+    }
     pub fn add_type(&self, ty: TypeHandle) {
         self.types.write().unwrap().insert(ty.name().clone(), ty);
     }
+    /// The type named `name`, which is made by `make` and added if there is none yet.
+    ///
+    /// The assembly is not locked while `make` runs, since making a type may look up the
+    /// others of the assembly. Two threads asking for the same generic instantiation may both
+    /// make it, but the one added first is the one both get.
+    pub(crate) fn get_or_add_type(
+        &self,
+        name: StringName,
+        make: impl FnOnce() -> Result<TypeHandle>,
+    ) -> Result<TypeHandle> {
+        if let Some(ty) = self.types.read().unwrap().get(&name) {
+            return Ok(ty.clone());
+        }
+        let ty = make()?;
+        Ok(self
+            .types
+            .write()
+            .unwrap()
+            .entry(name)
+            .or_insert(ty)
+            .clone())
+    }
+    /// Count a call of a method of the assembly as running until the result is dropped, so
+    /// that the assembly is not unloaded meanwhile
+    pub(crate) fn enter_frame(&self) -> RunningFrame {
+        self.running_frames.fetch_add(1, Ordering::AcqRel);
+        RunningFrame(self.running_frames.clone())
+    }
+    pub fn running_frames(&self) -> usize {
+        self.running_frames.load(Ordering::Acquire)
+    }
+    /// Whether the assembly declares `assem` as a dependency, or a type of it depends on a type
+    /// of `assem`, see [`TypeHandle::depends_on`]. The instantiations over the types of `assem`
+    /// do not count, they go with it.
+    pub(crate) fn depends_on(&self, assem: &StringName) -> bool {
+        self.dependencies.iter().any(|x| x.name() == assem)
+            || self
+                .types
+                .read()
+                .unwrap()
+                .values()
+                .filter(|t| !t.refers_to_assembly(assem))
+                .any(|t| t.depends_on(assem))
+    }
+    /// Verify the code of every method of the types in the assembly that is verified before it
+    /// runs, now rather than on its first call, see [`CommonMethod::verify`]
+    ///
+    /// [`CommonMethod::verify`]: super::CommonMethod::verify
+    pub fn verify(&self) -> Result<()> {
+        let types: Vec<TypeHandle> = self.types.read().unwrap().values().cloned().collect();
+        for ty in types {
+            match ty {
+                TypeHandle::Class(class) => {
+                    for method in class.mt().map.values() {
+                        method.check_verified()?;
+                    }
+                }
+                TypeHandle::Struct(s) => {
+                    for method in s.mt().map.values() {
+                        method.check_verified()?;
+                    }
+                }
+                TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A call of a method of an assembly which has not returned yet, see [`Assembly::enter_frame`]
+pub(crate) struct RunningFrame(Arc<AtomicUsize>);
+
+impl Drop for RunningFrame {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
 }
 
 impl AssemblyTrait for Assembly {
@@ -0,0 +1,85 @@
+use export::AssemblyTrait;
+use global::{IndexMap, Result, StringName, StringTypeReference};
+
+use super::{
+    AssemblyManager, TypeHandle, TypeVar,
+    get_traits::{GetAssemblyMust, GetTypeVars},
+};
+
+/// The slots of the instances of a type, one per field, the inherited fields coming first.
+///
+/// Since a type only appends to the layout of its parent, a field has the same slot in the
+/// instances of every type derived from the one declaring it.
+#[derive(Debug, Clone)]
+pub struct FieldLayout<F> {
+    fields: IndexMap<StringName, F>,
+}
+
+impl<F: Clone> FieldLayout<F> {
+    /// Lay out `fields` after the ones of `parent`
+    pub(crate) fn new(
+        parent: Option<&Self>,
+        fields: impl IntoIterator<Item = (StringName, F)>,
+    ) -> Self {
+        let mut all = parent.map(|x| x.fields.clone()).unwrap_or_default();
+        all.extend(fields);
+        Self { fields: all }
+    }
+}
+
+impl<F> FieldLayout<F> {
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+    pub fn slot_of(&self, name: &str) -> Option<usize> {
+        self.fields.get_index_of(name)
+    }
+    /// The name and metadata of the field in `slot`
+    pub fn field(&self, slot: usize) -> Option<(&StringName, &F)> {
+        self.fields.get_index(slot)
+    }
+    pub fn fields(&self) -> &IndexMap<StringName, F> {
+        &self.fields
+    }
+}
+
+/// The type of a field declared by `t`, loaded as fields read from a binary assembly only
+/// reference it, and with the type variables bound by the instantiation `t` substituted.
+///
+/// A type naming a type variable `t` leaves unbound is kept as it is, as it has no single type
+/// until `t` is instantiated.
+pub(crate) fn resolve_field_type<T: GetAssemblyMust + GetTypeVars>(
+    t: &T,
+    ty: &TypeHandle,
+) -> Result<TypeHandle> {
+    let type_vars = t.type_vars();
+    let lookup = |name: &StringName| match type_vars.get(name) {
+        Some(TypeVar::Type(t)) => Some(t.clone()),
+        _ => None,
+    };
+    match ty {
+        TypeHandle::Unloaded(r) if binds_all(r, &lookup) => {
+            AssemblyManager::from_dyn(t.must_assembly().manager())
+                .get_type_from_str_complex(&lookup, r)
+        }
+        TypeHandle::Generic(g) => Ok(lookup(g).unwrap_or_else(|| ty.clone())),
+        TypeHandle::Unloaded(_) | TypeHandle::Class(_) | TypeHandle::Struct(_) => Ok(ty.clone()),
+    }
+}
+
+/// Whether `lookup` binds every type variable `type_ref` names
+fn binds_all(
+    type_ref: &StringTypeReference,
+    lookup: &dyn Fn(&StringName) -> Option<TypeHandle>,
+) -> bool {
+    match type_ref {
+        StringTypeReference::Generic(g) => lookup(g).is_some(),
+        StringTypeReference::WithGeneric { type_vars, .. } => {
+            type_vars.values().all(|x| binds_all(x, lookup))
+        }
+        _ => true,
+    }
+}
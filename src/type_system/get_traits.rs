@@ -2,7 +2,7 @@ use crate::type_system::{
     Assembly, Class, CommonMethod, CommonMethodTable, Struct, TypeHandle, TypeVar,
 };
 use global::instruction::StringInstruction;
-use global::{IndexMap, StringName};
+use global::{IndexMap, Result, StringName};
 use sealed::sealed;
 use std::any::Any;
 use std::sync::Arc;
@@ -102,6 +102,35 @@ impl GetFieldCount for Struct {
     }
 }
 
+#[sealed]
+pub trait GetFieldSlot {
+    /// The slot of the field `name` in the instances, or in the statics if `statics` holds.
+    /// Fails if the types of the fields can't be loaded.
+    fn field_slot(&self, name: &str, statics: bool) -> Result<Option<usize>>;
+}
+
+#[sealed]
+impl GetFieldSlot for Class {
+    fn field_slot(&self, name: &str, statics: bool) -> Result<Option<usize>> {
+        let layout = match statics {
+            true => self.static_layout()?,
+            false => self.instance_layout()?,
+        };
+        Ok(layout.slot_of(name))
+    }
+}
+
+#[sealed]
+impl GetFieldSlot for Struct {
+    fn field_slot(&self, name: &str, statics: bool) -> Result<Option<usize>> {
+        let layout = match statics {
+            true => self.static_layout()?,
+            false => self.instance_layout()?,
+        };
+        Ok(layout.slot_of(name))
+    }
+}
+
 #[sealed]
 pub trait GetTypeVars {
     fn type_vars(&self) -> Arc<IndexMap<StringName, TypeVar>>;
@@ -124,6 +153,8 @@ impl GetTypeVars for Struct {
 #[sealed]
 pub trait GetMethodTable: Any + GetTypeName + Sized {
     fn mt_ptr(&self) -> *mut CommonMethodTable<Self>;
+    /// Load the parent the method table references by name, once
+    fn resolve_references(&self) -> Result<()>;
 }
 
 #[sealed]
@@ -131,6 +162,9 @@ impl GetMethodTable for Class {
     fn mt_ptr(&self) -> *mut CommonMethodTable<Self> {
         self.mt.get()
     }
+    fn resolve_references(&self) -> Result<()> {
+        Class::resolve_references(self)
+    }
 }
 
 #[sealed]
@@ -138,6 +172,9 @@ impl GetMethodTable for Struct {
     fn mt_ptr(&self) -> *mut CommonMethodTable<Self> {
         self.mt.get()
     }
+    fn resolve_references(&self) -> Result<()> {
+        Struct::resolve_references(self)
+    }
 }
 
 #[sealed]
@@ -148,7 +185,11 @@ pub trait MTGetParent<T: GetTypeName + Any> {
 #[sealed]
 impl MTGetParent<Struct> for CommonMethodTable<Struct> {
     fn _parent(&self) -> Option<Arc<Struct>> {
-        match self.parent.clone()? {
+        match self.parent()? {
+            TypeHandle::Unloaded(_) => TypeHandle::Struct(self.ty())
+                .parent()?
+                .try_unwrap_struct()
+                .ok(),
             TypeHandle::Struct(s) => Some(s),
             _ => None,
         }
@@ -158,7 +199,11 @@ impl MTGetParent<Struct> for CommonMethodTable<Struct> {
 #[sealed]
 impl MTGetParent<Class> for CommonMethodTable<Class> {
     fn _parent(&self) -> Option<Arc<Class>> {
-        match self.parent.clone()? {
+        match self.parent()? {
+            TypeHandle::Unloaded(_) => TypeHandle::Class(self.ty())
+                .parent()?
+                .try_unwrap_class()
+                .ok(),
             TypeHandle::Class(c) => Some(c),
             _ => None,
         }
@@ -2,16 +2,23 @@ use super::{
     Assembly, Class, ClassField, CommonMethod, CommonMethodTable, GenericBinding, TypeHandle,
     TypeVar,
 };
+use crate::errors::VMError;
 use crate::pl_lib_impl::System_Array_1::System_Array;
 use crate::pl_lib_impl::System_Boolean::System_Boolean;
 use crate::pl_lib_impl::System_Console_::to_vm::System_Console;
 use crate::pl_lib_impl::System_Console_::to_vm::System_ConsoleColor;
 use crate::pl_lib_impl::System_Enum::System_Enum;
+use crate::pl_lib_impl::System_GC::System_GC;
 use crate::pl_lib_impl::System_Null::System_Null;
 use crate::pl_lib_impl::System_Object::System_Object;
+use crate::pl_lib_impl::System_Reflection::{
+    System_Reflection_FieldInfo, System_Reflection_MethodInfo,
+};
 use crate::pl_lib_impl::System_String::System_String;
+use crate::pl_lib_impl::System_Type::System_Type;
 use crate::pl_lib_impl::System_ValueType::System_ValueType;
 use crate::pl_lib_impl::System_Void::System_Void;
+use crate::pl_lib_impl::System_WeakReference_1::System_WeakReference;
 use crate::pl_lib_impl::{ClassLoadToCore, StructLoadToCore, System_Integers};
 use crate::type_system::Struct;
 use crate::type_system::StructField;
@@ -19,16 +26,20 @@ use binary::TypeDef;
 use export::{AssemblyManagerTrait, AssemblyTrait};
 use global::{IndexMap, Result, StringName, StringTypeReference, ThreadSafe, errors::RuntimeError};
 use std::any::Any;
-use std::hint::unreachable_unchecked;
 use std::{
     collections::HashMap,
-    sync::{Arc, RwLock},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 
 #[derive(derive_more::Debug, Clone, ThreadSafe)]
 pub struct AssemblyManager {
     #[debug("{:#?}", assemblies.read().unwrap().keys().map(|x| &**x).collect::<Vec<_>>())]
     assemblies: Arc<RwLock<HashMap<StringName, Arc<Assembly>>>>,
+    /// See [`AssemblyManagerTrait::set_verify_on_load`]
+    verify_on_load: Arc<AtomicBool>,
 }
 
 #[allow(non_upper_case_globals)]
@@ -47,6 +58,7 @@ impl AssemblyManager {
     pub fn new() -> Result<Arc<Self>> {
         let this = Arc::new(Self {
             assemblies: Default::default(),
+            verify_on_load: Default::default(),
         });
         this.clone().load_core()?;
         Ok(this)
@@ -57,10 +69,22 @@ impl AssemblyManager {
 }
 
 impl AssemblyManager {
+    /// The type `type_ref` names, with the parent it references by name loaded the first time
+    /// it is asked for, see [`TypeHandle::resolve_references`]
     pub fn get_type_from_str_complex(
         &self,
         type_vars_lookup: &dyn Fn(&StringName) -> Option<TypeHandle>,
         type_ref: &StringTypeReference,
+    ) -> Result<TypeHandle> {
+        let ty = self.find_type_complex(type_vars_lookup, type_ref)?;
+        ty.resolve_references()?;
+        Ok(ty)
+    }
+    /// The type `type_ref` names, leaving the types it references by name unloaded
+    pub(crate) fn find_type_complex(
+        &self,
+        type_vars_lookup: &dyn Fn(&StringName) -> Option<TypeHandle>,
+        type_ref: &StringTypeReference,
     ) -> Result<TypeHandle> {
         match type_ref {
             StringTypeReference::Single { assem, ty } => {
@@ -87,7 +111,7 @@ impl AssemblyManager {
                         .map(|(k, v)| {
                             Ok::<_, global::Error>((
                                 k.clone(),
-                                self.get_type_from_str_complex(type_vars_lookup, v)?,
+                                self.find_type_complex(type_vars_lookup, v)?,
                             ))
                         })
                         .try_collect::<IndexMap<_, _>>()?,
@@ -99,6 +123,126 @@ impl AssemblyManager {
     pub fn get_type_from_str(&self, type_ref: &StringTypeReference) -> Result<TypeHandle> {
         self.get_type_from_str_complex(&|_| None, type_ref)
     }
+    pub(crate) fn find_type(&self, type_ref: &StringTypeReference) -> Result<TypeHandle> {
+        self.find_type_complex(&|_| None, type_ref)
+    }
+    /// Add the assemblies `added` together, once no version of any of them is loaded and every
+    /// dependency of theirs is loaded or among them, in a version it accepts.
+    ///
+    /// A missing dependency is reported rather than looked up in the assembly directories.
+    fn add_assemblies(&self, added: Vec<Arc<Assembly>>) -> Result<()> {
+        let mut assemblies = self.assemblies.write().unwrap();
+        let mut versions = HashMap::with_capacity(added.len());
+        for assem in &added {
+            let loaded = assemblies
+                .get(&assem.name())
+                .map(|x| x.version())
+                .or_else(|| versions.get(&assem.name()).copied());
+            if let Some(loaded) = loaded {
+                return Err(VMError::AssemblyConflict {
+                    name: assem.name(),
+                    version: assem.version(),
+                    loaded,
+                }
+                .into());
+            }
+            versions.insert(assem.name(), assem.version());
+        }
+        for assem in &added {
+            for dependency in assem.dependencies() {
+                let found = versions
+                    .get(dependency.name())
+                    .copied()
+                    .or_else(|| assemblies.get(dependency.name()).map(|x| x.version()));
+                match found {
+                    Some(version) if dependency.range().contains(&version) => {}
+                    Some(loaded) => {
+                        return Err(VMError::DependencyVersionMismatch {
+                            name: assem.name(),
+                            version: assem.version(),
+                            dependency: dependency.clone(),
+                            loaded,
+                        }
+                        .into());
+                    }
+                    None => {
+                        return Err(VMError::MissingDependency {
+                            name: assem.name(),
+                            version: assem.version(),
+                            dependency: dependency.clone(),
+                        }
+                        .into());
+                    }
+                }
+            }
+        }
+        assemblies.extend(added.into_iter().map(|x| (x.name(), x)));
+        Ok(())
+    }
+    /// The assemblies with a type depending on a type of the assembly `assem`, see
+    /// [`Assembly::depends_on`]
+    pub(crate) fn dependents_of(&self, assem: &StringName) -> Vec<StringName> {
+        self.assemblies
+            .read()
+            .unwrap()
+            .values()
+            .filter(|x| x.name() != *assem && x.depends_on(assem))
+            .map(|x| x.name())
+            .collect()
+    }
+    /// Remove the assembly `assem` with the instantiations of generic types and methods over
+    /// its types, and empty the method tables of the types removed.
+    ///
+    /// The tables are only freed when the types are dropped, so the handles left to the types
+    /// fail to find their methods rather than reading freed memory.
+    ///
+    /// # Safety
+    /// Nothing may use the types removed concurrently: the world must be stopped with no object,
+    /// static, register or running method of them, and no thread outside the safepoints may
+    /// use them, see [`VM::unload`](crate::vm::VM::unload)
+    pub(crate) unsafe fn remove_assembly(&self, assem: &StringName) -> Result<()> {
+        let assembly = self
+            .assemblies
+            .write()
+            .unwrap()
+            .remove(assem)
+            .ok_or(RuntimeError::FailedGetAssembly.throw())?;
+        let mut removed = assembly
+            .types()
+            .write()
+            .unwrap()
+            .drain()
+            .map(|x| x.1)
+            .collect::<Vec<_>>();
+        for other in self.assemblies.read().unwrap().values() {
+            let mut types = other.types().write().unwrap();
+            removed.extend(
+                types
+                    .extract_if(|_, t| t.refers_to_assembly(assem))
+                    .map(|x| x.1),
+            );
+            for ty in types.values() {
+                match ty {
+                    TypeHandle::Class(c) => {
+                        unsafe { &*c.mt.get() }.remove_generic_methods_over(assem)
+                    }
+                    TypeHandle::Struct(s) => {
+                        unsafe { &*s.mt.get() }.remove_generic_methods_over(assem)
+                    }
+                    TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => {}
+                }
+            }
+        }
+        // the types reference each other through their tables, so they would never be dropped
+        for ty in removed {
+            match ty {
+                TypeHandle::Class(c) => unsafe { (*c.mt.get()).clear() },
+                TypeHandle::Struct(s) => unsafe { (*s.mt.get()).clear() },
+                TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => {}
+            }
+        }
+        Ok(())
+    }
     pub fn all_types(&self) -> HashMap<StringName, HashMap<StringName, TypeHandle>> {
         let assemblies = self.assemblies.read().unwrap();
         let mut map = HashMap::with_capacity(assemblies.len());
@@ -126,9 +270,13 @@ impl AssemblyManagerTrait for AssemblyManager {
                 x => TypeHandle::Unloaded(x),
             }
         }
+        let mut added = Vec::with_capacity(binary_assemblies.len());
         for b_assem in binary_assemblies {
-            let assembly = Arc::new(Assembly::new(b_assem.name().clone(), this));
             let types = b_assem.type_defs();
+            // TODO: the binary format carries neither versions nor dependencies, so until it
+            // does the assemblies loaded from it are at version 0.0.0 and declare no dependency,
+            // the types they reference being loaded when first used
+            let assembly = Arc::new(Assembly::new(b_assem.name().clone(), this));
             for ty in types.values() {
                 let ty = match ty {
                     TypeDef::Class(c) => {
@@ -161,7 +309,8 @@ impl AssemblyManagerTrait for AssemblyManager {
                                                             .collect(),
                                                         parent: g_binding.parent().clone().map(TypeHandle::Unloaded),
                                                     }))).collect()),
-                                            );
+                                            )
+                                            .verified_before_run();
                                             Ok((m_name.clone(), method))
                                         }).try_collect()
                                     },
@@ -214,7 +363,8 @@ impl AssemblyManagerTrait for AssemblyManager {
                                                             .collect(),
                                                         parent: g_binding.parent().clone().map(TypeHandle::Unloaded),
                                                     }))).collect()),
-                                            );
+                                            )
+                                            .verified_before_run();
                                             Ok((m_name.clone(), method))
                                         }).try_collect()
                                     },
@@ -240,9 +390,18 @@ impl AssemblyManagerTrait for AssemblyManager {
                 };
                 assembly.add_type(ty);
             }
-            self.add_assembly(assembly);
+            added.push(assembly);
+        }
+        self.add_assemblies(added.clone())?;
+        if self.verify_on_load.load(Ordering::Relaxed)
+            && let Err(e) = added.iter().try_for_each(|x| x.verify())
+        {
+            // nothing but the verification has used the types yet
+            for assem in &added {
+                unsafe { self.remove_assembly(&assem.name()) }?;
+            }
+            return Err(e);
         }
-        self.resolve_type_references()?;
         Ok(())
     }
     fn get_assembly(&self, assem_name: StringName) -> Result<Arc<dyn AssemblyTrait>> {
@@ -256,9 +415,8 @@ impl AssemblyManagerTrait for AssemblyManager {
                 x
             })
     }
-    fn add_assembly(&self, assem: Arc<dyn AssemblyTrait>) {
-        let mut assemblies = self.assemblies.write().unwrap();
-        assemblies.insert(assem.name(), Assembly::from_dyn(assem));
+    fn add_assembly(&self, assem: Arc<dyn AssemblyTrait>) -> Result<()> {
+        self.add_assemblies(vec![Assembly::from_dyn(assem)])
     }
     fn load_core(self: Arc<Self>) -> Result<()> {
         let core_assembly = Arc::new(Assembly::new(
@@ -266,7 +424,7 @@ impl AssemblyManagerTrait for AssemblyManager {
             &self,
         ));
 
-        self.add_assembly(core_assembly.clone());
+        self.add_assembly(core_assembly.clone())?;
         //<editor-fold desc="Basic Types">
         System_Object::load_class(&core_assembly, &self);
         System_ValueType::load_struct(&core_assembly, &self);
@@ -283,110 +441,34 @@ impl AssemblyManagerTrait for AssemblyManager {
         System_Integers::load_integers(&core_assembly, &self);
         System_Null::load_class(&core_assembly, &self);
         System_Array::load_class(&core_assembly, &self);
+        System_WeakReference::load_class(&core_assembly, &self);
         System_String::load_class(&core_assembly, &self);
         System_Console::load_class(&core_assembly, &self);
-        self.add_assembly(core_assembly);
-        self.resolve_type_references()?;
+        System_GC::load_class(&core_assembly, &self);
+        //<editor-fold desc="Reflection">
+        System_Type::load_class(&core_assembly, &self);
+        System_Reflection_MethodInfo::load_class(&core_assembly, &self);
+        System_Reflection_FieldInfo::load_class(&core_assembly, &self);
+        //</editor-fold>
         Ok(())
     }
-
+    fn set_verify_on_load(&self, verify: bool) {
+        self.verify_on_load.store(verify, Ordering::Relaxed);
+    }
+    /// Resolve the references of every type loaded now rather than on first use, so that a
+    /// missing type is reported at once
     fn resolve_type_references(&self) -> Result<()> {
-        fn map_type_vars(
-            assembly_manager: &AssemblyManager,
-            type_vars_origin: &Arc<IndexMap<StringName, TypeVar>>,
-        ) -> Result<IndexMap<StringName, TypeVar>> {
-            let mut type_vars = IndexMap::new();
-            for (t_name, type_var) in type_vars_origin.iter() {
-                if let TypeVar::Type(TypeHandle::Unloaded(t)) = type_var {
-                    type_vars.insert(
-                        t_name.clone(),
-                        TypeVar::Type(assembly_manager.get_type_from_str(t)?),
-                    );
-                } else if let TypeVar::Canon(GenericBinding {
-                    implemented_interfaces,
-                    parent,
-                }) = type_var
-                {
-                    let parent = if let Some(parent) = parent {
-                        if let TypeHandle::Unloaded(p) = parent {
-                            Some(assembly_manager.get_type_from_str(p)?)
-                        } else {
-                            Some(parent.clone())
-                        }
-                    } else {
-                        None
-                    };
-                    let mut operated_implemented_interfaces = Vec::new();
-                    for i in implemented_interfaces {
-                        if let TypeHandle::Unloaded(s) = i {
-                            operated_implemented_interfaces
-                                .push(assembly_manager.get_type_from_str(s)?);
-                        } else {
-                            operated_implemented_interfaces.push(i.clone());
-                        }
-                    }
-                    type_vars.insert(
-                        t_name.clone(),
-                        TypeVar::Canon(GenericBinding {
-                            parent,
-                            implemented_interfaces: operated_implemented_interfaces,
-                        }),
-                    );
-                } else {
-                    type_vars.insert(t_name.clone(), type_var.clone());
-                }
-            }
-            Ok(type_vars)
-        }
-        fn map_type_handle(this: &AssemblyManager, handle: &mut TypeHandle) -> global::Result<()> {
-            eprintln!("Resolving TypeHandle: {}", handle.name());
-            if let TypeHandle::Unloaded(r) = handle {
-                let t = this.get_type_from_str(r)?;
-                *handle = t;
-            }
-            Ok(())
-        }
         for assembly in self.assemblies.read().unwrap().clone().into_values() {
-            eprintln!("Resolving {}", assembly.name());
-            let types = assembly.types().read().unwrap();
-            for (ty_name, ty) in types.iter() {
-                eprintln!("Resolving {ty_name}");
-                let mut ty = ty.clone();
-                map_type_handle(self, &mut ty)?;
-                match ty {
-                    TypeHandle::Class(ref c) => {
-                        let mt = unsafe { &mut *c.mt.get() };
-                        for method in mt.map.values_mut() {
-                            method.type_vars = Arc::new(map_type_vars(self, &method.type_vars)?);
-                            for arg_type in method.args.iter_mut() {
-                                if let TypeHandle::Unloaded(r) = arg_type {
-                                    let t = self.get_type_from_str(r)?;
-                                    *arg_type = t;
-                                }
-                            }
-                        }
-                        if let Some(ref mut parent) = mt.parent {
-                            map_type_handle(self, parent)?;
-                        }
-                    }
-                    TypeHandle::Struct(ref s) => {
-                        let mt = unsafe { &mut *s.mt.get() };
-                        for method in mt.map.values_mut() {
-                            method.type_vars = Arc::new(map_type_vars(self, &method.type_vars)?);
-                            for arg_type in method.args.iter_mut() {
-                                if let TypeHandle::Unloaded(r) = arg_type {
-                                    let t = self.get_type_from_str(r)?;
-                                    *arg_type = t;
-                                }
-                            }
-                        }
-                        if let Some(ref mut parent) = mt.parent {
-                            map_type_handle(self, parent)?;
-                        }
-                    }
-                    TypeHandle::Generic(_) => {}
-                    TypeHandle::Unloaded(_) => unsafe { unreachable_unchecked() },
-                }
+            // resolving may instantiate generic types into the assembly, so it is not kept locked
+            let types = assembly
+                .types()
+                .read()
+                .unwrap()
+                .values()
+                .cloned()
+                .collect::<Vec<_>>();
+            for ty in types {
+                ty.resolve_references()?;
             }
         }
         Ok(())
@@ -1,5 +1,8 @@
-use super::{AssemblyManager, CommonMethodTable, TypeHandle, TypeVar, get_traits::GetTypeName};
-use crate::type_system::get_traits::{GetAssemblyMust, GetInstruction, GetTypeVars};
+use super::{
+    AssemblyManager, CommonMethodTable, MethodSignature, TypeHandle, TypeVar, VerifyError,
+    check_type_args, get_traits::GetTypeName, refers_to_assembly,
+};
+use crate::type_system::get_traits::{GetAssemblyMust, GetFieldSlot, GetInstruction, GetTypeVars};
 use crate::value::{Array, ByRefValue};
 use crate::{value::Value, vm::CPU};
 use export::AssemblyTrait;
@@ -9,7 +12,11 @@ use global::{
     IndexMap, Result, StringName, StringTypeReference, attrs::MethodAttr,
     errors::RuntimeError, string_name,
 };
-use std::{any::Any, cell::Cell, sync::Arc};
+use std::{
+    any::Any,
+    cell::Cell,
+    sync::{Arc, OnceLock},
+};
 
 #[allow(clippy::type_complexity, unused)]
 #[derive(derive_more::Debug)]
@@ -28,6 +35,21 @@ pub struct CommonMethod<T: Any + GetTypeName> {
     pub(crate) args: Vec<TypeHandle>,
     #[debug("{:#?}", type_vars.iter().map(|x| (x.0, x.1.name())).collect::<IndexMap<_, _>>())]
     pub(crate) type_vars: Arc<IndexMap<StringName, TypeVar>>,
+    /// The slot of the field set by each instruction, see [`CommonMethod::field_slots`]
+    #[debug(skip)]
+    pub(crate) field_slots: Arc<OnceLock<Box<[Option<usize>]>>>,
+    /// The type variables of the instantiation the method was looked up on, when it is shared
+    /// by the instantiations over classes, see [`CommonMethodTable::make_generic`]
+    #[debug(skip)]
+    pub(crate) context: Option<Arc<IndexMap<StringName, TypeVar>>>,
+    /// The signature once every type it names is loaded, see [`CommonMethod::signature`]. A
+    /// copy given other type variables or another context gets its own.
+    #[debug(skip)]
+    pub(crate) signature: Arc<OnceLock<MethodSignature>>,
+    /// The outcome of [`CommonMethod::verify`], run before the first call of methods read from
+    /// a binary assembly. Methods made by the runtime itself are trusted and have none.
+    #[debug(skip)]
+    pub(crate) verified: Option<Arc<OnceLock<std::result::Result<(), VerifyError>>>>,
 }
 
 impl<T: Any + GetTypeName> Clone for CommonMethod<T> {
@@ -42,11 +64,15 @@ impl<T: Any + GetTypeName> Clone for CommonMethod<T> {
             args: self.args.clone(),
             entry_point: self.entry_point.clone(),
             type_vars: self.type_vars.clone(),
+            field_slots: self.field_slots.clone(),
+            context: self.context.clone(),
+            signature: self.signature.clone(),
+            verified: self.verified.clone(),
         }
     }
 }
 
-impl<T: Any + GetTypeName + GetAssemblyMust + GetTypeVars> CommonMethod<T> {
+impl<T: Any + GetTypeName + GetAssemblyMust + GetTypeVars + GetFieldSlot> CommonMethod<T> {
     pub fn new(
         name: StringName,
         attr: MethodAttr,
@@ -65,6 +91,10 @@ impl<T: Any + GetTypeName + GetAssemblyMust + GetTypeVars> CommonMethod<T> {
             args,
             entry_point: Arc::new(default_entry_point),
             type_vars,
+            field_slots: Arc::new(OnceLock::new()),
+            context: None,
+            signature: Arc::new(OnceLock::new()),
+            verified: None,
         }
     }
     pub fn native(
@@ -85,24 +115,45 @@ impl<T: Any + GetTypeName + GetAssemblyMust + GetTypeVars> CommonMethod<T> {
             args,
             entry_point: Arc::new(entry_point),
             type_vars,
+            field_slots: Arc::new(OnceLock::new()),
+            context: None,
+            signature: Arc::new(OnceLock::new()),
+            verified: None,
         }
     }
+    /// Have the code verified before it is first run, as it is for methods read from a binary
+    /// assembly
+    pub fn verified_before_run(mut self) -> Self {
+        self.verified = Some(Default::default());
+        self
+    }
 }
 
-impl<T: Any + GetTypeName> CommonMethod<T> {
+impl<T: Any + GetTypeName + GetAssemblyMust> CommonMethod<T> {
     pub fn call(&self, cpu: Arc<CPU>, this_val: &mut Value, args: &mut [Value]) -> Result<Value> {
-        (self.entry_point)(
-            self,
-            cpu.clone(),
-            this_val,
-            args,
-            cpu.find_register_continuous_start(self.attr.register_len() as _) as _,
-        )
+        // the callee may borrow out of `this` and the arguments across a collection
+        let mut pins = Vec::new();
+        this_val.pin_references(&mut pins);
+        for arg in args.iter() {
+            arg.pin_references(&mut pins);
+        }
+        let vm = cpu.vm();
+        let _mutator = vm.enter_managed();
+        // the assembly of the method is not unloaded while it runs; the frame is only counted
+        // once the thread takes part in the safepoints, so none starts while the world is stopped
+        let _frame = self.mt().ty().must_assembly().enter_frame();
+        // references to the registers of the frame stay valid until it returns
+        let register_len = self.attr.register_len() as usize;
+        let register_start = cpu.enter_frame(register_len);
+        let res = (self.entry_point)(self, cpu.clone(), this_val, args, register_start as _);
+        cpu.leave_frame(register_start, register_len);
+        res
     }
 }
 
 impl<T: Any + GetTypeName> CommonMethod<T> {
     pub fn make_generic(&self, type_vars: Arc<IndexMap<StringName, TypeHandle>>) -> Result<Self> {
+        check_type_args(&self.type_vars, &type_vars)?;
         let name = StringMethodReference::WithGeneric(
             self.name.clone(),
             Arc::new(
@@ -121,6 +172,11 @@ impl<T: Any + GetTypeName> CommonMethod<T> {
             ret_type: self.ret_type.clone(),
             args: self.args.clone(),
             entry_point: self.entry_point.clone(),
+            field_slots: self.field_slots.clone(),
+            context: self.context.clone(),
+            signature: Arc::new(OnceLock::new()),
+            // the code is checked again with the types it is instantiated over
+            verified: self.verified.as_ref().map(|_| Default::default()),
             type_vars: Arc::new(
                 type_vars
                     .iter()
@@ -134,16 +190,106 @@ impl<T: Any + GetTypeName> CommonMethod<T> {
     }
 }
 
+impl<T: Any + GetTypeName + GetFieldSlot> CommonMethod<T> {
+    /// Resolve the field set by every instruction to its slot in the declaring type, or in its
+    /// statics for the static constructor. Done when linking, or on the first call otherwise.
+    pub(crate) fn field_slots(&self) -> Result<&[Option<usize>]> {
+        self.field_slots
+            .get_or_try_init(|| {
+                let statics = self.name == StringMethodReference::STATIC_CTOR_REF.unwrap_single();
+                let ty = self.mt().ty();
+                self.instructions
+                    .iter()
+                    .map(|x| match x {
+                        StringInstruction::SetField { field, .. } => ty.field_slot(field, statics),
+                        _ => Ok(None),
+                    })
+                    .collect()
+            })
+            .map(|x| &**x)
+    }
+}
+
+impl<T: Any + GetTypeName> CommonMethod<T> {
+    /// Whether the signature, the type variables or the code of the method names a type of the
+    /// assembly `assem`
+    pub(crate) fn refers_to_assembly(&self, assem: &StringName) -> bool {
+        let refers = |t: &StringTypeReference| refers_to_assembly(t, assem);
+        let method_refers = |m: &StringMethodReference| match m {
+            StringMethodReference::WithGeneric(_, type_vars) => type_vars.values().any(refers),
+            _ => false,
+        };
+        self.args
+            .iter()
+            .chain([&self.ret_type])
+            .any(|t| t.refers_to_assembly(assem))
+            || self.type_vars.values().any(|x| x.refers_to_assembly(assem))
+            || self.instructions.iter().any(|x| match x {
+                StringInstruction::StaticCall { ty, method, .. } => {
+                    refers(ty) || method_refers(method)
+                }
+                StringInstruction::InstanceCall { method, .. } => method_refers(method),
+                StringInstruction::LoadStatic { ty, .. }
+                | StringInstruction::NewObject { ty, .. } => refers(ty),
+                _ => false,
+            })
+    }
+}
+
 impl<T: GetTypeVars + GetTypeName + GetAssemblyMust + 'static> CommonMethod<T> {
+    /// The type variables of the type the method belongs to, which are those of the
+    /// instantiation it was looked up on when it is shared
+    fn type_context(&self) -> Arc<IndexMap<StringName, TypeVar>> {
+        match &self.context {
+            Some(context) => context.clone(),
+            None => self.mt().ty().type_vars(),
+        }
+    }
+    /// What the method is told apart from its overloads by, with the type variables bound by
+    /// it or by the instantiation it belongs to filled in and the types named loaded.
+    ///
+    /// It is kept once every type could be loaded, so that the types are looked up on the
+    /// first use only.
+    pub fn signature(&self) -> MethodSignature {
+        if let Some(signature) = self.signature.get() {
+            return signature.clone();
+        }
+        let context = self.type_context();
+        let loaded = Cell::new(true);
+        let bind = |t: &TypeHandle| match t {
+            TypeHandle::Generic(g) => match self.type_vars.get(g).or_else(|| context.get(g)) {
+                Some(TypeVar::Type(t)) => t.clone(),
+                _ => t.clone(),
+            },
+            TypeHandle::Unloaded(r) => self.solve_str_type(r).unwrap_or_else(|_| {
+                loaded.set(false);
+                t.clone()
+            }),
+            _ => t.clone(),
+        };
+        let signature = MethodSignature::new(
+            self.name
+                .as_str()
+                .split_once('(')
+                .map_or(self.name.as_str(), |x| x.0)
+                .into(),
+            self.args.iter().map(bind).collect(),
+            self.type_vars.len(),
+            bind(&self.ret_type),
+        );
+        if loaded.get() {
+            // another thread may have got there first with the same signature
+            let _ = self.signature.set(signature.clone());
+        }
+        signature
+    }
     pub fn solve_str_type(&self, type_reference: &StringTypeReference) -> Result<TypeHandle> {
         match type_reference {
             StringTypeReference::Generic(ty) => {
                 if let Some(TypeVar::Type(t)) = self.type_vars.get(ty) {
                     return Ok(t.clone());
                 }
-                let mt = unsafe { &*self.mt.get() };
-                mt.ty()
-                    .type_vars()
+                self.type_context()
                     .get(ty)
                     .filter(|x| matches!(x, TypeVar::Type(_)))
                     .map(|x| {
@@ -154,15 +300,14 @@ impl<T: GetTypeVars + GetTypeName + GetAssemblyMust + 'static> CommonMethod<T> {
                     .ok_or(RuntimeError::FailedGetType(type_reference.clone()).into())
             }
             _ => {
-                let mt = unsafe { &*self.mt.get() };
-                AssemblyManager::from_dyn(mt.ty().must_assembly().manager())
+                let context = self.type_context();
+                AssemblyManager::from_dyn(self.mt().ty().must_assembly().manager())
                     .get_type_from_str_complex(
                         &|s| {
                             if let Some(TypeVar::Type(t)) = self.type_vars.get(s) {
                                 return Some(t.clone());
                             }
-                            mt.ty()
-                                .type_vars()
+                            context
                                 .get(s)
                                 .and_then(|x| {
                                     let TypeVar::Type(t) = x else {
@@ -184,7 +329,7 @@ impl<T: GetTypeVars + GetTypeName + GetAssemblyMust + 'static> CommonMethod<T> {
     clippy::too_many_arguments,
     clippy::only_used_in_recursion
 )]
-fn match_code<T: GetTypeVars + GetTypeName + GetAssemblyMust + Any>(
+fn match_code<T: GetTypeVars + GetTypeName + GetAssemblyMust + GetFieldSlot + Any>(
     #[allow(unused)] method: &CommonMethod<T>,
     #[allow(unused)] cpu: Arc<CPU>,
     #[allow(unused)] this_val: &mut Value,
@@ -236,7 +381,7 @@ fn match_code<T: GetTypeVars + GetTypeName + GetAssemblyMust + Any>(
                 cpu.vm()
                     .get_core_single_type(string_name!("System.String"))?,
                 args,
-            )),
+            )?),
         )?,
         StringInstruction::InstanceCall {
             val,
@@ -244,11 +389,16 @@ fn match_code<T: GetTypeVars + GetTypeName + GetAssemblyMust + Any>(
             args,
             ret_at,
         } => {
-            let mut val = cpu.read_register(*val)?;
+            // the arguments are copies, but a struct receiver is passed by reference, so what
+            // the callee changes in it is kept
+            let mut this = match cpu.read_register(*val)? {
+                Value::Struct(_) => Value::RegisterReference(*val),
+                this => this,
+            };
             let res = cpu.clone().call_instance_method(
                 Some(method),
                 method_target,
-                &mut val,
+                &mut this,
                 args.iter()
                     .map(|x| cpu.read_register(*x))
                     .try_collect::<Vec<_>>()?
@@ -305,6 +455,7 @@ fn match_code<T: GetTypeVars + GetTypeName + GetAssemblyMust + Any>(
         } => {
             let register_addr = *register_addr;
             let val = cpu.read_register(register_addr)?;
+            let slot = method.field_slots()?.get(*pc).copied().flatten();
             match this_val {
                 Value::Void
                 | Value::True
@@ -321,19 +472,32 @@ fn match_code<T: GetTypeVars + GetTypeName + GetAssemblyMust + Any>(
                 | Value::Int128(_) => {
                     return Err(RuntimeError::FailedGetField(field.clone()).into());
                 }
-                Value::Struct(s) => {
-                    s.get_mut_field(field.as_str())?.set_val(&val);
-                }
+                Value::Struct(s) => match slot {
+                    Some(slot) => s.set_slot(slot, &val)?,
+                    None => s.set_field(field.as_str(), &val)?,
+                },
                 Value::Reference(r) => match &mut **r {
-                    ByRefValue::Object(obj) => {
-                        obj.get_mut_field(field.clone())?.set_val(&val);
-                    }
-                    ByRefValue::Array(_) | ByRefValue::String(_) | ByRefValue::Null => {
+                    ByRefValue::Object(obj) => match slot {
+                        Some(slot) => obj.set_slot(slot, &val)?,
+                        None => obj.set_field(field.clone(), &val)?,
+                    },
+                    ByRefValue::Array(_)
+                    | ByRefValue::String(_)
+                    | ByRefValue::WeakReference(_)
+                    | ByRefValue::Type(_)
+                    | ByRefValue::MethodInfo(_)
+                    | ByRefValue::FieldInfo(_)
+                    | ByRefValue::Null => {
                         return Err(RuntimeError::FailedGetField(field.clone()).into());
                     }
                 },
-                Value::RegisterReference(r) => {
-                    let mut _this = cpu.read_register(*r)?;
+                Value::RegisterReference(_)
+                | Value::FieldReference(..)
+                | Value::ElementReference(..)
+                | Value::StaticReference(..) => {
+                    // what the reference points to is changed, not the reference
+                    let reference = this_val.clone();
+                    let mut _this = cpu.load_indirect(&reference)?;
                     match_code(
                         method,
                         cpu,
@@ -347,7 +511,7 @@ fn match_code<T: GetTypeVars + GetTypeName + GetAssemblyMust + Any>(
                         pc,
                         res,
                     )?;
-                    *this_val = _this;
+                    cpu.store_indirect(&reference, _this)?;
                 }
             }
         }
@@ -355,13 +519,14 @@ fn match_code<T: GetTypeVars + GetTypeName + GetAssemblyMust + Any>(
     Ok(())
 }
 
-fn default_entry_point<T: Any + GetTypeName + GetAssemblyMust + GetTypeVars>(
+fn default_entry_point<T: Any + GetTypeName + GetAssemblyMust + GetTypeVars + GetFieldSlot>(
     method: &CommonMethod<T>,
     cpu: Arc<CPU>,
     this_val: &mut Value,
     args: &mut [Value],
     register_start: u64,
 ) -> Result<Value> {
+    method.check_verified()?;
     let instructions = &method.instructions();
     let mut pc = 0usize;
     let mut res = None;
@@ -374,6 +539,9 @@ fn default_entry_point<T: Any + GetTypeName + GetAssemblyMust + GetTypeVars>(
                 return Ok(res.unwrap_unchecked());
             }
         }
+        let vm = cpu.vm();
+        vm.safepoint();
+        vm.collect_incrementally()?;
         let i = &instructions[pc];
         match_code(
             method,
@@ -1,15 +1,17 @@
 use super::{
-    AssemblyManager, Class, CommonMethod, Struct, TypeHandle,
+    AssemblyManager, Class, CommonMethod, MethodSignature, Struct, TypeHandle, TypeVar,
     get_traits::{GetAssemblyMust, GetTypeName},
 };
-use crate::type_system::get_traits::{GetFieldCount, GetMethodTable, MTGetParent};
+use crate::errors::VMError;
+use crate::type_system::get_traits::{GetFieldCount, GetMethodTable, GetTypeVars, MTGetParent};
 use export::AssemblyTrait;
 use global::{
     Error, IndexMap, Result, StringMethodReference, StringName, ThreadSafe, errors::RuntimeError,
 };
 use std::{
     any::Any,
-    sync::{Arc, Weak},
+    collections::HashMap,
+    sync::{Arc, OnceLock, RwLock, Weak},
 };
 
 #[derive(ThreadSafe, derive_more::Debug)]
@@ -20,9 +22,26 @@ where
     pub(crate) map: IndexMap<StringName, CommonMethod<T>>,
     #[debug(skip)]
     pub(crate) t: Weak<T>,
+    /// The parent as declared, see [`CommonMethodTable::parent`]
     #[debug("{:#?}", parent.as_ref().map(|x| x.name().clone()))]
     pub(crate) parent: Option<TypeHandle>,
+    /// The parent loaded by [`CommonMethodTable::resolve_references`], shared by the copies of
+    /// the table
+    #[debug(skip)]
+    resolved_parent: Arc<OnceLock<TypeHandle>>,
     pub(crate) field_count: u64,
+    /// The instantiations of the generic methods made by [`CommonMethodTable::get_method`],
+    /// by the method reference they were made for
+    #[debug(skip)]
+    pub(crate) generic_methods: Arc<RwLock<HashMap<StringName, CommonMethod<T>>>>,
+    /// The table of the generic definition whose methods this instantiation shares, see
+    /// [`CommonMethodTable::make_generic`]
+    #[debug(skip)]
+    pub(crate) shared: Option<*mut CommonMethodTable<T>>,
+    /// The methods of the definition given the type variables of this instantiation by
+    /// [`CommonMethodTable::shared_method`], kept so that their signatures are too
+    #[debug(skip)]
+    shared_methods: Arc<RwLock<HashMap<StringName, CommonMethod<T>>>>,
 }
 
 impl<T: Any + GetTypeName> Clone for CommonMethodTable<T> {
@@ -31,7 +50,11 @@ impl<T: Any + GetTypeName> Clone for CommonMethodTable<T> {
             map: self.map.clone(),
             t: self.t.clone(),
             parent: self.parent.clone(),
+            resolved_parent: self.resolved_parent.clone(),
             field_count: self.field_count,
+            generic_methods: self.generic_methods.clone(),
+            shared: self.shared,
+            shared_methods: self.shared_methods.clone(),
         }
     }
 }
@@ -60,7 +83,11 @@ impl<T: Any + GetTypeName + GetFieldCount> CommonMethodTable<T> {
             map: IndexMap::new(),
             t: Arc::downgrade(t),
             parent,
+            resolved_parent: Default::default(),
             field_count: t.field_count() as _,
+            generic_methods: Default::default(),
+            shared: None,
+            shared_methods: Default::default(),
         }));
         let ptr = this as *mut _;
         let map = map_generator(ptr)?;
@@ -69,16 +96,37 @@ impl<T: Any + GetTypeName + GetFieldCount> CommonMethodTable<T> {
     }
 }
 
-impl<T: Any + GetTypeName + GetAssemblyMust + GetMethodTable> CommonMethodTable<T>
+impl<T: Any + GetTypeName + GetAssemblyMust + GetMethodTable + GetTypeVars> CommonMethodTable<T>
 where
     Self: MTGetParent<T>,
 {
+    /// The method `name` of the definition this instantiation shares its methods with, which
+    /// is given the type variables of the instantiation to resolve its types with
+    fn shared_method(&self, name: &StringName) -> Option<CommonMethod<T>> {
+        let shared = unsafe { &*self.shared? };
+        if let Some(method) = self.shared_methods.read().unwrap().get(name) {
+            return Some(method.clone());
+        }
+        let mut method = shared.map.get(name)?.clone();
+        method.context = Some(self.ty().type_vars());
+        method.signature = Default::default();
+        Some(
+            self.shared_methods
+                .write()
+                .unwrap()
+                .entry(name.clone())
+                .or_insert(method)
+                .clone(),
+        )
+    }
     pub fn get_method(&self, method_ref: &StringMethodReference) -> Result<CommonMethod<T>> {
+        self.ty().resolve_references()?;
         match method_ref {
             StringMethodReference::Single(name) => self
                 .map
                 .get(name)
                 .cloned()
+                .or_else(|| self.shared_method(name))
                 .or_else(|| {
                     unsafe { &*self._parent()?.mt_ptr() }
                         .get_method(method_ref)
@@ -86,40 +134,186 @@ where
                 })
                 .ok_or(RuntimeError::FailedGetMethod(method_ref.clone()).into()),
             StringMethodReference::WithGeneric(name, type_vars) => {
-                match self.map.get(name).map(|x| {
-                    x.make_generic(Arc::new(
-                        type_vars
-                            .iter()
-                            .map(|x| {
-                                Ok::<_, Error>((
-                                    x.0.clone(),
-                                    AssemblyManager::from_dyn(self.ty().must_assembly().manager())
+                let key = method_ref.string_name_repr();
+                if let Some(method) = self.generic_methods.read().unwrap().get(&key) {
+                    return Ok(method.clone());
+                }
+                // made while the instantiations are locked, so that it is made only once
+                let mut generic_methods = self.generic_methods.write().unwrap();
+                if let Some(method) = generic_methods.get(&key) {
+                    return Ok(method.clone());
+                }
+                let method = match self
+                    .map
+                    .get(name)
+                    .cloned()
+                    .or_else(|| self.shared_method(name))
+                    .map(|x| {
+                        x.make_generic(Arc::new(
+                            type_vars
+                                .iter()
+                                .map(|x| {
+                                    Ok::<_, Error>((
+                                        x.0.clone(),
+                                        AssemblyManager::from_dyn(
+                                            self.ty().must_assembly().manager(),
+                                        )
                                         .get_type_from_str(x.1)?,
-                                ))
-                            })
-                            .try_collect::<IndexMap<_, _>>()?,
-                    ))
-                }) {
-                    Some(m) => m,
-                    None => Err(RuntimeError::FailedGetMethod(method_ref.clone()).into()),
+                                    ))
+                                })
+                                .try_collect::<IndexMap<_, _>>()?,
+                        ))
+                    }) {
+                    Some(m) => m?,
+                    None => return Err(RuntimeError::FailedGetMethod(method_ref.clone()).into()),
+                };
+                generic_methods.insert(key, method.clone());
+                Ok(method)
+            }
+        }
+    }
+    /// The methods declared by the type, including the ones it shares with its generic
+    /// definition
+    pub fn methods(&self) -> Vec<CommonMethod<T>> {
+        let shared = self.shared.map(|x| unsafe { &*x });
+        self.map
+            .values()
+            .cloned()
+            .chain(
+                shared
+                    .iter()
+                    .flat_map(|x| x.map.keys())
+                    .filter_map(|x| self.shared_method(x)),
+            )
+            .collect()
+    }
+    /// The method `name` of the type or of one it derives from that best matches arguments of
+    /// the types `args`.
+    ///
+    /// A method hides the ones of the parents taking the same parameters and as many type
+    /// parameters. The best matches are the methods no other one accepting the arguments has
+    /// more derived parameters than, and among them the ones with the fewest type parameters.
+    /// When more than one is left the call is ambiguous.
+    pub fn resolve_method(&self, name: &str, args: &[TypeHandle]) -> Result<CommonMethod<T>> {
+        let mut candidates: Vec<(CommonMethod<T>, MethodSignature)> = Vec::new();
+        let mut mt = Some(self);
+        while let Some(table) = mt {
+            table.ty().resolve_references()?;
+            for method in table.methods() {
+                let signature = method.signature();
+                if signature.name().as_str() != name
+                    || !signature.accepts(args)
+                    || candidates.iter().any(|x| x.1.has_same_params(&signature))
+                {
+                    continue;
                 }
+                candidates.push((method, signature));
             }
+            mt = table._parent().map(|x| unsafe { &*x.mt_ptr() });
+        }
+        let beats = |x: &MethodSignature, y: &MethodSignature| {
+            x.is_more_specific_than(y) && !y.is_more_specific_than(x)
+        };
+        let mut best = candidates
+            .iter()
+            .filter(|x| !candidates.iter().any(|y| beats(&y.1, &x.1)))
+            .collect::<Vec<_>>();
+        if let Some(arity) = best.iter().map(|x| *x.1.generic_arity()).min() {
+            best.retain(|x| *x.1.generic_arity() == arity);
+        }
+        match best.as_slice() {
+            [] => Err(
+                RuntimeError::FailedGetMethod(StringMethodReference::Single(name.into())).into(),
+            ),
+            [method] => Ok(method.0.clone()),
+            _ => Err(VMError::AmbiguousMethod {
+                name: name.into(),
+                candidates: best.iter().map(|x| x.0.name.clone()).collect(),
+            }
+            .into()),
         }
     }
 }
 
 impl<T: Any + GetTypeName> CommonMethodTable<T> {
+    /// Load the parent, which is only referenced by name when read from a binary assembly, and
+    /// fail if it cannot be.
+    ///
+    /// The table is shared by every thread, so it is never changed: the parent loaded is kept
+    /// aside, and the types the methods name stay references, loaded wherever the methods are
+    /// used. A method whose signature names a missing type thus fails only when it is used.
+    pub(crate) fn resolve_references(&self, manager: &AssemblyManager) -> Result<()> {
+        if let Some(TypeHandle::Unloaded(r)) = &self.parent {
+            let parent = manager.get_type_from_str(r)?;
+            self.resolved_parent.get_or_init(|| parent);
+        }
+        Ok(())
+    }
+    /// The parent, loaded if [`CommonMethodTable::resolve_references`] has loaded it
+    pub(crate) fn parent(&self) -> Option<TypeHandle> {
+        self.resolved_parent
+            .get()
+            .cloned()
+            .or_else(|| self.parent.clone())
+    }
+    /// Whether the parent or a method names a type of the assembly `assem`, see
+    /// [`CommonMethod::refers_to_assembly`]
+    pub(crate) fn refers_to_assembly(&self, assem: &StringName) -> bool {
+        self.parent.iter().any(|t| t.refers_to_assembly(assem))
+            || self.map.values().any(|m| m.refers_to_assembly(assem))
+    }
+    /// Forget the instantiations of generic methods over types of the assembly `assem`
+    pub(crate) fn remove_generic_methods_over(&self, assem: &StringName) {
+        self.generic_methods.write().unwrap().retain(|_, method| {
+            !method.type_vars.values().any(|x| match x {
+                TypeVar::Type(t) => t.refers_to_assembly(assem),
+                TypeVar::Canon(_) => false,
+            })
+        });
+    }
+    /// Empty the table of a type of an unloaded assembly, dropping the methods and parent that
+    /// keep the other types alive. Looking up a method of the type fails afterwards.
+    ///
+    /// # Safety
+    /// Nothing may read the table concurrently, see [`AssemblyManager::remove_assembly`]
+    pub(crate) unsafe fn clear(&mut self) {
+        self.map.clear();
+        self.parent = None;
+        self.resolved_parent = Arc::new(OnceLock::new());
+        self.generic_methods.write().unwrap().clear();
+        self.shared = None;
+        self.shared_methods.write().unwrap().clear();
+    }
+}
+
+impl<T: Any + GetTypeName + GetMethodTable> CommonMethodTable<T> {
+    /// The table of the instantiation of this generic type with `type_vars`.
+    ///
+    /// Code over classes works the same whatever they are, so the instantiations over classes
+    /// only share the methods of the definition, which are given the exact type variables when
+    /// looked up. Only the instantiations over value types get methods of their own. The ones
+    /// over classes point to the table the definition owns, whichever copy of it `self` is.
     pub fn make_generic(&self, type_vars: Arc<IndexMap<StringName, TypeHandle>>) -> Result<Self> {
+        let shared = type_vars
+            .values()
+            .all(|x| matches!(x, TypeHandle::Class(_)));
         Ok(Self {
-            map: self
-                .map
-                .iter()
-                .map(|x| Ok::<_, Error>((x.0.clone(), x.1.make_generic(type_vars.clone())?)))
-                .try_collect::<IndexMap<_, _>>()?,
+            map: match shared {
+                true => IndexMap::new(),
+                false => self
+                    .map
+                    .iter()
+                    .map(|x| Ok::<_, Error>((x.0.clone(), x.1.make_generic(type_vars.clone())?)))
+                    .try_collect::<IndexMap<_, _>>()?,
+            },
+            shared: shared.then(|| self.shared.unwrap_or_else(|| self.ty().mt_ptr())),
 
             t: self.t.clone(),
             parent: self.parent.clone(),
+            resolved_parent: Default::default(),
             field_count: self.field_count,
+            generic_methods: Default::default(),
+            shared_methods: Default::default(),
         })
     }
 }
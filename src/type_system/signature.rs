@@ -0,0 +1,68 @@
+use global::{StringName, getset::Getters};
+
+use super::{AssemblyManager, TypeHandle};
+
+/// What overloads of a method are told apart by
+#[derive(Clone, Getters)]
+#[getset(get = "pub")]
+pub struct MethodSignature {
+    /// The name of the method without its parameter list, `WriteLine` for
+    /// `WriteLine([!]System.String)`
+    name: StringName,
+    params: Vec<TypeHandle>,
+    generic_arity: usize,
+    ret_type: TypeHandle,
+}
+
+impl MethodSignature {
+    pub fn new(
+        name: StringName,
+        params: Vec<TypeHandle>,
+        generic_arity: usize,
+        ret_type: TypeHandle,
+    ) -> Self {
+        Self {
+            name,
+            params,
+            generic_arity,
+            ret_type,
+        }
+    }
+    /// Whether the method can be called with arguments of the types `args`
+    pub fn accepts(&self, args: &[TypeHandle]) -> bool {
+        self.params.len() == args.len() && self.params.iter().zip(args).all(|(p, a)| accepts(p, a))
+    }
+    /// Whether every parameter is at least as derived as the one of `other` in its place, so
+    /// that the method is the better candidate for any call both accept
+    pub fn is_more_specific_than(&self, other: &Self) -> bool {
+        self.params.len() == other.params.len()
+            && self
+                .params
+                .iter()
+                .zip(&other.params)
+                .all(|(p, o)| accepts(o, p))
+    }
+    /// Whether both methods take the same parameters and as many type parameters, in which case
+    /// one hides the other
+    pub fn has_same_params(&self, other: &Self) -> bool {
+        self.generic_arity == other.generic_arity
+            && self.params.len() == other.params.len()
+            && self
+                .params
+                .iter()
+                .zip(&other.params)
+                .all(|(p, o)| p.string_reference() == o.string_reference())
+    }
+}
+
+/// Whether an argument of type `arg` can be passed for a parameter of type `param`. A type
+/// variable left open takes anything, and null is taken by every class.
+pub(super) fn accepts(param: &TypeHandle, arg: &TypeHandle) -> bool {
+    match param {
+        TypeHandle::Generic(_) => true,
+        _ if arg.string_reference() == AssemblyManager::System_Null_CLASS_REF => {
+            !matches!(param, TypeHandle::Struct(_))
+        }
+        _ => arg.is_subtype_of(param),
+    }
+}
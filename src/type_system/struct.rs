@@ -1,4 +1,8 @@
-use super::{Assembly, CommonMethodTable, TypeHandle, TypeVar};
+use super::{
+    Assembly, AssemblyManager, CommonMethodTable, FieldLayout, TypeHandle, TypeVar,
+    check_type_args, resolve_field_type,
+};
+use crate::type_system::get_traits::MTGetParent;
 use export::AssemblyTrait;
 use global::derive_ctor::ctor;
 use global::getset::CopyGetters;
@@ -11,7 +15,7 @@ use global::{
 use std::{
     cell::Cell,
     ptr,
-    sync::{Arc, Weak},
+    sync::{Arc, OnceLock, Weak},
 };
 
 #[derive(Getters, ThreadSafe, derive_more::Debug, CopyGetters)]
@@ -30,6 +34,17 @@ pub struct Struct {
     fields: IndexMap<StringName, Field>,
     #[debug("{:#?}", type_vars.iter().map(|x| (x.0, x.1.name())).collect::<IndexMap<_, _>>())]
     pub(crate) type_vars: Arc<IndexMap<StringName, TypeVar>>,
+    #[getset(skip)]
+    #[debug(skip)]
+    instance_layout: OnceLock<Arc<FieldLayout<Field>>>,
+    #[getset(skip)]
+    #[debug(skip)]
+    static_layout: OnceLock<Arc<FieldLayout<Field>>>,
+    /// Whether the parent the method table references by name has been loaded, see
+    /// [`Struct::resolve_references`]
+    #[getset(skip)]
+    #[debug(skip)]
+    references_resolved: OnceLock<()>,
 }
 
 impl Struct {
@@ -48,6 +63,9 @@ impl Struct {
             mt: Cell::new(ptr::null_mut()),
             fields,
             type_vars: Arc::new(IndexMap::new()),
+            instance_layout: OnceLock::new(),
+            static_layout: OnceLock::new(),
+            references_resolved: OnceLock::new(),
         });
         let mt = mt_generator(this.clone())?;
         assert!(!mt.is_null());
@@ -70,6 +88,9 @@ impl Struct {
             mt: Cell::new(ptr::null_mut()),
             fields,
             type_vars: Arc::new(IndexMap::new()),
+            instance_layout: OnceLock::new(),
+            static_layout: OnceLock::new(),
+            references_resolved: OnceLock::new(),
         });
         let mt = mt_generator(this.clone());
         assert!(!mt.is_null());
@@ -97,26 +118,107 @@ impl Struct {
             ),
         }
         .string_name_repr();
-        if let Ok(this) = self.assem().get_single_type(name.clone()) {
-            return Ok(this.unwrap_struct());
+        // the instantiation is made once, the next ones get it from the assembly
+        let assem = self.assem();
+        assem
+            .get_or_add_type(name.clone(), || {
+                check_type_args(&self.type_vars, &type_vars)?;
+                let mt: *mut CommonMethodTable<Self> = Box::leak(Box::new(
+                    unsafe { &*self.mt.get() }.make_generic(type_vars.clone())?,
+                ));
+                let type_vars = Arc::new(
+                    type_vars
+                        .iter()
+                        .map(|(k, v)| (k.clone(), TypeVar::Type(v.clone())))
+                        .collect::<IndexMap<_, _>>(),
+                );
+                let this = Arc::new(Self {
+                    assem: self.assem.clone(),
+                    attr: self.attr,
+                    name,
+                    general_name: self.general_name.clone(),
+                    mt: Cell::new(mt),
+                    fields: self.fields.clone(),
+                    type_vars,
+                    instance_layout: OnceLock::new(),
+                    static_layout: OnceLock::new(),
+                    references_resolved: OnceLock::new(),
+                });
+                // the instantiated table and its methods must point back to the instantiation,
+                // not to the generic definition they were copied from
+                unsafe {
+                    (*mt).t = Arc::downgrade(&this);
+                    for method in (*mt).map.values() {
+                        method.mt.set(mt);
+                    }
+                }
+                Ok(TypeHandle::Struct(this))
+            })
+            .map(TypeHandle::unwrap_struct)
+    }
+}
+
+impl Struct {
+    /// `field` with its type resolved by [`resolve_field_type`]
+    fn resolve_field(&self, field: &Field) -> Result<Field> {
+        Ok(Field {
+            ty: resolve_field_type(self, &field.ty)?,
+            ..field.clone()
+        })
+    }
+    /// The slots of the instances, laid out once the parent has been resolved
+    pub fn instance_layout(&self) -> Result<Arc<FieldLayout<Field>>> {
+        self.instance_layout
+            .get_or_try_init(|| {
+                let parent = unsafe { &*self.mt.get() }
+                    ._parent()
+                    .map(|x| x.instance_layout())
+                    .transpose()?;
+                let fields = self
+                    .fields
+                    .iter()
+                    .filter(|x| !x.1.attr.is_static())
+                    .map(|(k, v)| Ok((k.clone(), self.resolve_field(v)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Arc::new(FieldLayout::new(parent.as_deref(), fields)))
+            })
+            .cloned()
+    }
+    /// The slots of the object holding the statics of this type and its parents
+    pub fn static_layout(&self) -> Result<Arc<FieldLayout<Field>>> {
+        self.static_layout
+            .get_or_try_init(|| {
+                let parent = unsafe { &*self.mt.get() }
+                    ._parent()
+                    .map(|x| x.static_layout())
+                    .transpose()?;
+                let fields = self
+                    .fields
+                    .iter()
+                    .filter(|x| x.1.attr.is_static())
+                    .map(|(k, v)| Ok((k.clone(), self.resolve_field(v)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Arc::new(FieldLayout::new(parent.as_deref(), fields)))
+            })
+            .cloned()
+    }
+}
+
+impl Struct {
+    /// Free the method table, see [`Class::free_method_table`]
+    ///
+    /// [`Class::free_method_table`]: super::Class::free_method_table
+    unsafe fn free_method_table(&self) {
+        let mt = self.mt.replace(ptr::null_mut());
+        if !mt.is_null() {
+            drop(unsafe { Box::from_raw(mt) });
         }
-        let mt = Box::leak(Box::new(self.mt().make_generic(type_vars.clone())?));
-        let type_vars = Arc::new(
-            type_vars
-                .iter()
-                .map(|(k, v)| (k.clone(), TypeVar::Type(v.clone())))
-                .collect::<IndexMap<_, _>>(),
-        );
-        let this = Arc::new(Self {
-            assem: self.assem.clone(),
-            attr: self.attr,
-            name,
-            general_name: self.general_name.clone(),
-            mt: Cell::new(mt),
-            fields: self.fields.clone(),
-            type_vars,
-        });
-        Ok(this)
+    }
+}
+
+impl Drop for Struct {
+    fn drop(&mut self) {
+        unsafe { self.free_method_table() }
     }
 }
 
@@ -124,6 +226,16 @@ impl Struct {
     pub fn mt(&self) -> CommonMethodTable<Self> {
         unsafe { (*self.mt.get()).clone() }
     }
+    /// Load the parent the type references by name, the first time the type is used rather
+    /// than when its assembly is loaded, see [`CommonMethodTable::resolve_references`]
+    pub(crate) fn resolve_references(&self) -> Result<()> {
+        self.references_resolved
+            .get_or_try_init(|| {
+                let manager = AssemblyManager::from_dyn(self.assem().manager());
+                unsafe { &*self.mt.get() }.resolve_references(&manager)
+            })
+            .map(|_| ())
+    }
     pub fn assem(&self) -> Arc<Assembly> {
         self.assem.upgrade().unwrap()
     }
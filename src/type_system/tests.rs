@@ -1,8 +1,22 @@
+use enumflags2::make_bitflags;
 use export::AssemblyManagerTrait;
-use global::{Result, StringName, StringTypeReference, indexmap, string_name};
-use std::sync::Arc;
+use global::{
+    IndexMap, Result, StringMethodReference, StringName, StringTypeReference,
+    attrs::{
+        ClassImplementationFlags, MethodAttr, MethodImplementationFlags, TypeAttr,
+        TypeSpecificAttr, Visibility,
+    },
+    indexmap,
+    instruction::StringInstruction,
+    string_name,
+};
+use std::{ptr, sync::Arc};
 
-use super::AssemblyManager;
+use super::{
+    Assembly, AssemblyDependency, AssemblyManager, AssemblyVersion, Class, CommonMethod,
+    CommonMethodTable, GenericBinding, TypeHandle, TypeVar, VerifyErrorKind, VersionRange,
+};
+use crate::value::Value;
 
 #[test]
 fn test_generic() -> Result<()> {
@@ -24,6 +38,605 @@ fn test_generic() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_generic_back_pointers() -> Result<()> {
+    let assembly_manager = AssemblyManager::new()?;
+    let object_type = assembly_manager.get_type_from_str(&StringTypeReference::Single {
+        assem: string_name!("!"),
+        ty: string_name!("System.Object"),
+    })?;
+    let array_type = assembly_manager.get_type_from_str(&StringTypeReference::Single {
+        assem: string_name!("!"),
+        ty: string_name!("System.Array`1"),
+    })?;
+    let definition = array_type.unwrap_class_ref().mt.get();
+    let integer_type = assembly_manager.get_type_from_str(&StringTypeReference::Single {
+        assem: string_name!("!"),
+        ty: string_name!("System.UInt64"),
+    })?;
+    // the table and methods of an instantiation over a value type are its own, not the
+    // generic definition's
+    let integers = array_type.make_generic(Arc::new(indexmap! {
+        StringName::from_static_str("T") => integer_type
+    }))?;
+    let class = integers.unwrap_class_ref();
+    let mt = class.mt.get();
+    unsafe {
+        assert!(Arc::ptr_eq(&(*mt).ty(), class));
+        assert!(!(*mt).map.is_empty());
+        for method in (*mt).map.values() {
+            assert_eq!(method.mt.get(), mt);
+        }
+    }
+    // an instantiation over a class has its own table, which hands out the methods of the
+    // definition with its type variables as their context
+    let objects = array_type.make_generic(Arc::new(indexmap! {
+        StringName::from_static_str("T") => object_type
+    }))?;
+    let class = objects.unwrap_class_ref();
+    let mt = class.mt.get();
+    unsafe {
+        assert!(Arc::ptr_eq(&(*mt).ty(), class));
+        assert_eq!((*mt).shared, Some(definition));
+        for name in (*definition).map.keys() {
+            let method = (*mt).get_method(&StringMethodReference::Single(name.clone()))?;
+            assert_eq!(method.mt.get(), definition);
+            assert!(Arc::ptr_eq(
+                method.context.as_ref().unwrap(),
+                &class.type_vars
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_generic_constraints() -> Result<()> {
+    let assembly_manager = AssemblyManager::new()?;
+    let get = |name| {
+        assembly_manager.get_type_from_str(&StringTypeReference::core_static_single_type(name))
+    };
+    let method = CommonMethod::<Class>::native(
+        string_name!("Get()"),
+        MethodAttr::new(
+            Visibility::Public,
+            make_bitflags!(MethodImplementationFlags::{Static}),
+            0,
+        ),
+        ptr::null_mut(),
+        get("System.Void")?,
+        vec![],
+        Arc::new(indexmap! {
+            string_name!("@T") => TypeVar::Canon(GenericBinding {
+                implemented_interfaces: vec![],
+                parent: Some(get("System.ValueType")?),
+            }),
+        }),
+        |_, _, _, _, _| Ok(Value::Void),
+    );
+    method.make_generic(Arc::new(indexmap! {
+        string_name!("@T") => get("System.UInt64")?,
+    }))?;
+    assert!(
+        method
+            .make_generic(Arc::new(indexmap! {
+                string_name!("@T") => get("System.String")?,
+            }))
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_generic_instantiation_cache() -> Result<()> {
+    let assembly_manager = AssemblyManager::new()?;
+    let array_type = assembly_manager.get_type_from_str(
+        &StringTypeReference::core_static_single_type("System.Array`1"),
+    )?;
+    let object_type = assembly_manager.get_type_from_str(
+        &StringTypeReference::core_static_single_type("System.Object"),
+    )?;
+    let make = || {
+        array_type.make_generic(Arc::new(indexmap! {
+            string_name!("@T") => object_type.clone(),
+        }))
+    };
+    let first = make()?;
+    let second = make()?;
+    assert!(Arc::ptr_eq(
+        first.unwrap_class_ref(),
+        second.unwrap_class_ref()
+    ));
+
+    let assem = Arc::new(Assembly::new(string_name!("Test"), &assembly_manager));
+    let class = Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+        ),
+        string_name!("Test.Generic"),
+        |class| {
+            CommonMethodTable::new(
+                |table| {
+                    indexmap! {
+                        string_name!("Get()") => CommonMethod::native(
+                            string_name!("Get()"),
+                            MethodAttr::new(
+                                Visibility::Public,
+                                make_bitflags!(MethodImplementationFlags::{Static}),
+                                0,
+                            ),
+                            table,
+                            object_type.clone(),
+                            vec![],
+                            Arc::new(indexmap! {
+                                string_name!("@T") => TypeVar::Canon(GenericBinding {
+                                    implemented_interfaces: vec![],
+                                    parent: None,
+                                }),
+                            }),
+                            |_, _, _, _, _| Ok(Value::Void),
+                        ),
+                    }
+                },
+                &class,
+                None,
+            )
+        },
+        indexmap! {},
+    );
+    let method_ref = StringMethodReference::WithGeneric(
+        string_name!("Get()"),
+        Arc::new(indexmap! {
+            string_name!("@T") => StringTypeReference::core_static_single_type("System.Object"),
+        }),
+    );
+    let mt = unsafe { &*class.mt.get() };
+    let first = mt.get_method(&method_ref)?;
+    let second = mt.get_method(&method_ref)?;
+    assert!(Arc::ptr_eq(&first.type_vars, &second.type_vars));
+    Ok(())
+}
+
+#[test]
+fn test_instantiation_looks_up_its_assembly() -> Result<()> {
+    let assembly_manager = AssemblyManager::new()?;
+    let core =
+        Assembly::from_dyn(assembly_manager.get_assembly(StringTypeReference::CORE_ASSEMBLY_NAME)?);
+    // as checking the type arguments does when their parents are in the same assembly
+    let made = core.get_or_add_type(string_name!("Test.Made"), || {
+        core.get_single_type(string_name!("System.Object"))
+    })?;
+    assert_eq!(made.name().as_str(), "System.Object");
+    Ok(())
+}
+
+#[test]
+fn test_shared_generic_code() -> Result<()> {
+    fn mt(t: &TypeHandle) -> &CommonMethodTable<Class> {
+        unsafe { &*t.unwrap_class_ref().mt.get() }
+    }
+    let assembly_manager = AssemblyManager::new()?;
+    let get = |name| {
+        assembly_manager.get_type_from_str(&StringTypeReference::core_static_single_type(name))
+    };
+    let array_type = get("System.Array`1")?;
+    let make = |t: TypeHandle| {
+        array_type.make_generic(Arc::new(indexmap! {
+            string_name!("@T") => t,
+        }))
+    };
+    let objects = make(get("System.Object")?)?;
+    let strings = make(get("System.String")?)?;
+    let integers = make(get("System.UInt64")?)?;
+    // the instantiations over classes share the methods of the definition
+    assert!(mt(&objects).map.is_empty());
+    assert!(mt(&objects).shared.is_some());
+    assert_eq!(mt(&objects).shared, mt(&strings).shared);
+    assert_eq!(
+        mt(&objects).shared,
+        Some(array_type.unwrap_class_ref().mt.get())
+    );
+    // the ones over value types have their own
+    assert!(mt(&integers).shared.is_none());
+    assert!(!mt(&integers).map.is_empty());
+
+    let method = mt(&strings).get_method(&StringMethodReference::Single(string_name!(
+        "__op_Index([!]System.UInt64)"
+    )))?;
+    let Some(TypeVar::Type(t)) = method.context.as_ref().unwrap().get("@T") else {
+        panic!()
+    };
+    assert_eq!(t.name().as_str(), "System.String");
+    Ok(())
+}
+
+#[test]
+fn test_overload_resolution() -> Result<()> {
+    let assembly_manager = AssemblyManager::new()?;
+    let get = |name| {
+        assembly_manager.get_type_from_str(&StringTypeReference::core_static_single_type(name))
+    };
+    let object = get("System.Object")?;
+    let string = get("System.String")?;
+    let method = |table, name: &str, args: Vec<TypeHandle>| {
+        (
+            StringName::from(name),
+            CommonMethod::native(
+                name.into(),
+                MethodAttr::new(
+                    Visibility::Public,
+                    make_bitflags!(MethodImplementationFlags::{Static}),
+                    0,
+                ),
+                table,
+                object.clone(),
+                args,
+                Default::default(),
+                |_, _, _, _, _| Ok(Value::Void),
+            ),
+        )
+    };
+    let assem = Arc::new(Assembly::new(string_name!("Test"), &assembly_manager));
+    let attr = TypeAttr::new(
+        Visibility::Public,
+        TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+    );
+    let base = Class::new(
+        &assem,
+        attr,
+        string_name!("Test.Base"),
+        |class| {
+            CommonMethodTable::new(
+                |table| {
+                    IndexMap::from_iter([
+                        method(table, "Pick([!]System.Object)", vec![object.clone()]),
+                        method(table, "Pick([!]System.String)", vec![string.clone()]),
+                        method(
+                            table,
+                            "Mix([!]System.Object, [!]System.String)",
+                            vec![object.clone(), string.clone()],
+                        ),
+                        method(
+                            table,
+                            "Mix([!]System.String, [!]System.Object)",
+                            vec![string.clone(), object.clone()],
+                        ),
+                    ])
+                },
+                &class,
+                None,
+            )
+        },
+        indexmap! {},
+    );
+    let derived = Class::new(
+        &assem,
+        attr,
+        string_name!("Test.Derived"),
+        |class| {
+            CommonMethodTable::new(
+                |table| {
+                    let (name, mut generic) =
+                        method(table, "Pick([!]System.String)", vec![string.clone()]);
+                    generic.type_vars = Arc::new(indexmap! {
+                        string_name!("@T") => TypeVar::Canon(GenericBinding {
+                            implemented_interfaces: vec![],
+                            parent: None,
+                        }),
+                    });
+                    IndexMap::from_iter([
+                        method(table, "Pick([!]System.Object)", vec![object.clone()]),
+                        (name, generic),
+                    ])
+                },
+                &class,
+                Some(TypeHandle::Class(base.clone())),
+            )
+        },
+        indexmap! {},
+    );
+    let mt = unsafe { &*derived.mt.get() };
+
+    // the most derived parameters win, wherever the method is declared, and a method without
+    // type parameters wins over an equally good generic one
+    let picked = mt.resolve_method("Pick", &[string.clone()])?;
+    assert_eq!(picked.name.as_str(), "Pick([!]System.String)");
+    assert!(ptr::eq(picked.mt(), base.mt.get()));
+    // a method hides the one of the parent taking the same parameters
+    let picked = mt.resolve_method("Pick", &[object.clone()])?;
+    assert!(ptr::eq(picked.mt(), mt));
+    assert_eq!(picked.signature().params().len(), 1);
+    assert_eq!(picked.signature().name().as_str(), "Pick");
+
+    // neither overload is better for two strings
+    assert!(
+        mt.resolve_method("Mix", &[string.clone(), string.clone()])
+            .is_err()
+    );
+    let picked = mt.resolve_method("Mix", &[object.clone(), string.clone()])?;
+    assert_eq!(
+        picked.name.as_str(),
+        "Mix([!]System.Object, [!]System.String)"
+    );
+    assert!(mt.resolve_method("Pick", &[]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_lazy_references() -> Result<()> {
+    let assembly_manager = AssemblyManager::new()?;
+    let missing = StringTypeReference::make_static_single("Missing", "Missing.Type");
+    let take = string_name!("Take([Missing]Missing.Type)");
+    let assem = Arc::new(Assembly::new(string_name!("Test"), &assembly_manager));
+    let attr = TypeAttr::new(
+        Visibility::Public,
+        TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+    );
+    let class = |name, parent: StringTypeReference| {
+        Class::new(
+            &assem,
+            attr,
+            name,
+            |class| {
+                CommonMethodTable::new(
+                    |table| {
+                        indexmap! {
+                            take.clone() => CommonMethod::native(
+                                take.clone(),
+                                MethodAttr::new(
+                                    Visibility::Public,
+                                    make_bitflags!(MethodImplementationFlags::{Static}),
+                                    0,
+                                ),
+                                table,
+                                TypeHandle::Unloaded(AssemblyManager::System_Void_STRUCT_REF),
+                                vec![TypeHandle::Unloaded(missing.clone())],
+                                Default::default(),
+                                |_, _, _, _, _| Ok(Value::Void),
+                            ),
+                        }
+                    },
+                    &class,
+                    Some(TypeHandle::Unloaded(parent)),
+                )
+            },
+            indexmap! {},
+        )
+    };
+    assem.add_type(TypeHandle::Class(class(
+        string_name!("Test.Used"),
+        AssemblyManager::System_Object_CLASS_REF,
+    )));
+    assem.add_type(TypeHandle::Class(class(
+        string_name!("Test.Broken"),
+        missing.clone(),
+    )));
+    // nothing is resolved when the assembly is added, so the missing type does not fail it
+    assembly_manager.add_assembly(assem)?;
+
+    // a copy of the table taken before the parent is loaded sees it once it is
+    let early = assembly_manager
+        .find_type(&StringTypeReference::make_static_single(
+            "Test",
+            "Test.Used",
+        ))?
+        .unwrap_class_ref()
+        .mt();
+    let used = assembly_manager.get_type_from_str(&StringTypeReference::make_static_single(
+        "Test",
+        "Test.Used",
+    ))?;
+    assert!(matches!(used.parent(), Some(TypeHandle::Class(_))));
+    assert!(matches!(early.parent(), Some(TypeHandle::Class(_))));
+    assert!(matches!(
+        unsafe { &*used.unwrap_class_ref().mt.get() }.parent(),
+        Some(TypeHandle::Class(_))
+    ));
+    // a method naming the missing type keeps the reference
+    let method = used
+        .unwrap_class_ref()
+        .mt()
+        .get_method(&StringMethodReference::Single(take))?;
+    assert!(matches!(method.args[0], TypeHandle::Unloaded(_)));
+    // and its signature is looked up again until the type can be loaded, unlike one naming
+    // types which are
+    method.signature();
+    assert!(method.signature.get().is_none());
+    let to_string = used
+        .unwrap_class_ref()
+        .mt()
+        .get_method(&StringMethodReference::Single(string_name!("ToString()")))?;
+    to_string.signature();
+    assert!(to_string.signature.get().is_some());
+    // a type whose parent is missing fails when it is used
+    assert!(
+        assembly_manager
+            .get_type_from_str(&StringTypeReference::make_static_single(
+                "Test",
+                "Test.Broken"
+            ))
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_verify() -> Result<()> {
+    let assembly_manager = AssemblyManager::new()?;
+    let get = |name| {
+        assembly_manager.get_type_from_str(&StringTypeReference::core_static_single_type(name))
+    };
+    let object = get("System.Object")?;
+    let string = get("System.String")?;
+    let method = |table, name: &str, ret_type: &TypeHandle, instructions: Vec<_>| {
+        (
+            StringName::from(name),
+            CommonMethod::new(
+                name.into(),
+                MethodAttr::new(
+                    Visibility::Public,
+                    make_bitflags!(MethodImplementationFlags::{Static}),
+                    1,
+                ),
+                table,
+                instructions.into(),
+                ret_type.clone(),
+                vec![string.clone()],
+                Default::default(),
+            ),
+        )
+    };
+    let assem = Arc::new(Assembly::new(string_name!("Test"), &assembly_manager));
+    let class = Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+        ),
+        string_name!("Test.Verified"),
+        |class| {
+            CommonMethodTable::new(
+                |table| {
+                    IndexMap::from_iter([
+                        method(
+                            table,
+                            "Valid([!]System.String)",
+                            &object,
+                            vec![
+                                StringInstruction::LoadArg {
+                                    register_addr: 0,
+                                    arg: 0,
+                                },
+                                StringInstruction::ReturnVal { register_addr: 0 },
+                            ],
+                        ),
+                        method(
+                            table,
+                            "BadArg([!]System.String)",
+                            &object,
+                            vec![StringInstruction::LoadArg {
+                                register_addr: 0,
+                                arg: 1,
+                            }],
+                        ),
+                        method(
+                            table,
+                            "BadRegister([!]System.String)",
+                            &object,
+                            vec![
+                                StringInstruction::LoadTrue { register_addr: 0 },
+                                StringInstruction::ReturnVal { register_addr: 1 },
+                            ],
+                        ),
+                        method(
+                            table,
+                            "BadReturn([!]System.String)",
+                            &string,
+                            vec![
+                                StringInstruction::LoadTrue { register_addr: 0 },
+                                StringInstruction::ReturnVal { register_addr: 0 },
+                            ],
+                        ),
+                        method(
+                            table,
+                            "BadArgCount([!]System.String)",
+                            &object,
+                            vec![StringInstruction::StaticCall {
+                                ty: StringTypeReference::make_static_single(
+                                    "Test",
+                                    "Test.Verified",
+                                ),
+                                method: StringMethodReference::Single(string_name!(
+                                    "Valid([!]System.String)"
+                                )),
+                                args: vec![],
+                                ret_at: 0,
+                            }],
+                        ),
+                    ])
+                },
+                &class,
+                None,
+            )
+        },
+        indexmap! {},
+    );
+    assem.add_type(TypeHandle::Class(class.clone()));
+    assembly_manager.add_assembly(assem.clone())?;
+    let mt = unsafe { &*class.mt.get() };
+    let verify = |name: &str| mt.map[&StringName::from(name)].verify();
+
+    verify("Valid([!]System.String)").unwrap();
+    let e = verify("BadArg([!]System.String)").unwrap_err();
+    assert_eq!(e.index, 0);
+    assert!(matches!(
+        e.kind,
+        VerifyErrorKind::ArgOutOfRange { arg: 1, arg_len: 1 }
+    ));
+    let e = verify("BadRegister([!]System.String)").unwrap_err();
+    assert_eq!(e.index, 1);
+    assert!(matches!(
+        e.kind,
+        VerifyErrorKind::RegisterOutOfRange {
+            register: 1,
+            register_len: 1
+        }
+    ));
+    let e = verify("BadReturn([!]System.String)").unwrap_err();
+    assert_eq!(e.index, 1);
+    assert!(matches!(e.kind, VerifyErrorKind::TypeMismatch { .. }));
+    assert_eq!(e.method.as_str(), "BadReturn([!]System.String)");
+    // only natives may leave their parameters undeclared
+    let e = verify("BadArgCount([!]System.String)").unwrap_err();
+    assert!(matches!(
+        e.kind,
+        VerifyErrorKind::ArgCountMismatch {
+            got: 0,
+            expected: 1
+        }
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_assembly_versions() -> Result<()> {
+    let assembly_manager = AssemblyManager::new()?;
+    let assembly = |name: &'static str, version, dependencies| {
+        Arc::new(
+            Assembly::new(StringName::from_static_str(name), &assembly_manager)
+                .with_version(version, dependencies),
+        )
+    };
+    let shared = |range| vec![AssemblyDependency::new(string_name!("Shared"), range)];
+    let v1 = AssemblyVersion::new(1, 0, 0);
+    let v1_2 = AssemblyVersion::new(1, 2, 0);
+    let v2 = AssemblyVersion::new(2, 0, 0);
+    assert!(VersionRange::compatible_with(v1).contains(&v1_2));
+    assert!(!VersionRange::compatible_with(v1).contains(&v2));
+    assert!(!VersionRange::compatible_with(v1_2).contains(&v1));
+
+    // a dependency must be loaded first
+    let app = assembly("App", v1, shared(VersionRange::compatible_with(v1)));
+    assert!(assembly_manager.add_assembly(app.clone()).is_err());
+    assembly_manager.add_assembly(assembly("Shared", v1_2, vec![]))?;
+    assembly_manager.add_assembly(app)?;
+    // another version of a loaded assembly conflicts with it, rather than replacing it
+    assert!(
+        assembly_manager
+            .add_assembly(assembly("Shared", v2, vec![]))
+            .is_err()
+    );
+    assert_eq!(
+        Assembly::from_dyn(assembly_manager.get_assembly(string_name!("Shared"))?).version(),
+        v1_2
+    );
+    let newer = assembly("Newer", v1, shared(VersionRange::compatible_with(v2)));
+    assert!(assembly_manager.add_assembly(newer).is_err());
+    Ok(())
+}
+
 #[test]
 fn test_parse_binary() -> global::Result<()> {
     let assembly_manager = AssemblyManager::new()?;
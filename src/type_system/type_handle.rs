@@ -1,11 +1,13 @@
 use std::sync::Arc;
 
 use derive_more::{TryUnwrap, Unwrap};
+use export::AssemblyTrait;
+use gc::Trace;
 use global::{
     IndexMap, Result, StringName, StringTypeReference, ThreadSafe, WithType, errors::RuntimeError,
 };
 
-use super::{Assembly, Class, Struct, TypeVar};
+use super::{Assembly, AssemblyManager, Class, Struct, TypeVar};
 
 #[derive(Clone, Unwrap, TryUnwrap, ThreadSafe, WithType)]
 #[unwrap(ref, ref_mut)]
@@ -19,6 +21,14 @@ pub enum TypeHandle {
     Unloaded(StringTypeReference),
 }
 
+/// Types live outside of the heap and reference no managed objects
+impl Trace for TypeHandle {
+    fn trace(&self) -> Vec<usize> {
+        Vec::new()
+    }
+    fn relocate(&mut self, _: &dyn Fn(usize) -> Option<usize>) {}
+}
+
 impl TypeHandle {
     pub fn name(&self) -> StringName {
         match self {
@@ -47,21 +57,60 @@ impl TypeHandle {
 }
 
 impl TypeHandle {
+    /// Load the parent this type references by name, once
+    pub fn resolve_references(&self) -> Result<()> {
+        match self {
+            TypeHandle::Class(class) => class.resolve_references(),
+            TypeHandle::Struct(s) => s.resolve_references(),
+            TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => Ok(()),
+        }
+    }
+    /// The type this one derives from, loaded now if the references of this type are not yet.
+    /// A parent that cannot be loaded is left unloaded.
+    pub fn parent(&self) -> Option<TypeHandle> {
+        let (parent, assembly) = match self {
+            TypeHandle::Class(class) => (unsafe { &*class.mt.get() }.parent(), class.assem()),
+            TypeHandle::Struct(s) => (unsafe { &*s.mt.get() }.parent(), s.assem()),
+            TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => return None,
+        };
+        match parent? {
+            TypeHandle::Unloaded(r) => Some(
+                AssemblyManager::from_dyn(assembly.manager())
+                    .find_type(&r)
+                    .unwrap_or(TypeHandle::Unloaded(r)),
+            ),
+            parent => Some(parent),
+        }
+    }
+    /// Whether this type is `other` or derives from it
+    pub fn is_subtype_of(&self, other: &TypeHandle) -> bool {
+        let other = other.string_reference();
+        let mut ty = Some(self.clone());
+        while let Some(t) = ty {
+            if t.string_reference() == other {
+                return true;
+            }
+            ty = t.parent();
+        }
+        false
+    }
+}
+
+impl TypeHandle {
+    /// The instantiation of this generic type with `type_vars`, which the assembly of the
+    /// definition makes once and shares
     pub fn make_generic(&self, type_vars: Arc<IndexMap<StringName, TypeHandle>>) -> Result<Self> {
-        let this = match self {
-            TypeHandle::Class(class) => class
-                .clone()
-                .make_generic(type_vars)
-                .map(TypeHandle::Class)?,
-            TypeHandle::Struct(s) => s.clone().make_generic(type_vars).map(TypeHandle::Struct)?,
-            TypeHandle::Generic(g) => type_vars
+        match self {
+            TypeHandle::Class(class) => {
+                class.clone().make_generic(type_vars).map(TypeHandle::Class)
+            }
+            TypeHandle::Struct(s) => s.clone().make_generic(type_vars).map(TypeHandle::Struct),
+            TypeHandle::Generic(g) => Ok(type_vars
                 .get(g)
                 .ok_or(RuntimeError::FailedMakeGeneric.throw())?
-                .clone(),
-            Self::Unloaded(r) => return Err(RuntimeError::UnloadedType(r.clone()).throw().into()),
-        };
-        self.assembly().unwrap().add_type(this.clone());
-        Ok(this)
+                .clone()),
+            Self::Unloaded(r) => Err(RuntimeError::UnloadedType(r.clone()).throw().into()),
+        }
     }
     pub fn type_vars(&self) -> Arc<IndexMap<StringName, TypeVar>> {
         match self {
@@ -72,3 +121,42 @@ impl TypeHandle {
         }
     }
 }
+
+impl TypeHandle {
+    /// Whether this is a type of the assembly `assem`, or an instantiation over one
+    pub fn refers_to_assembly(&self, assem: &StringName) -> bool {
+        refers_to_assembly(&self.string_reference(), assem)
+    }
+    /// Whether the parent, a field, a type variable or a method of this type names a type of
+    /// the assembly `assem`
+    pub(crate) fn depends_on(&self, assem: &StringName) -> bool {
+        let refers = |t: &TypeHandle| t.refers_to_assembly(assem);
+        let var_refers = |x: &TypeVar| x.refers_to_assembly(assem);
+        match self {
+            TypeHandle::Class(class) => {
+                unsafe { &*class.mt.get() }.refers_to_assembly(assem)
+                    || class.fields().values().any(|f| refers(f.ty()))
+                    || class.type_vars.values().any(var_refers)
+            }
+            TypeHandle::Struct(s) => {
+                unsafe { &*s.mt.get() }.refers_to_assembly(assem)
+                    || s.fields().values().any(|f| refers(f.ty()))
+                    || s.type_vars.values().any(var_refers)
+            }
+            TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => false,
+        }
+    }
+}
+
+/// Whether `type_ref` names a type of the assembly `assem`, or an instantiation over one
+pub(crate) fn refers_to_assembly(type_ref: &StringTypeReference, assem: &StringName) -> bool {
+    match type_ref {
+        StringTypeReference::Single { assem: a, .. } => a == assem,
+        StringTypeReference::WithGeneric {
+            assem: a,
+            type_vars,
+            ..
+        } => a == assem || type_vars.values().any(|x| refers_to_assembly(x, assem)),
+        _ => false,
+    }
+}
@@ -0,0 +1,366 @@
+use std::{any::Any, fmt, io};
+
+use global::{
+    StringMethodReference, StringName, StringTypeReference, instruction::StringInstruction,
+};
+
+use super::{
+    CommonMethod, MethodSignature, TypeHandle,
+    get_traits::{GetAssemblyMust, GetFieldSlot, GetTypeName, GetTypeVars},
+    signature,
+};
+
+/// What is wrong with an instruction, see [`VerifyError`]
+#[derive(Debug, Clone)]
+pub enum VerifyErrorKind {
+    /// The register is outside of the `register_len` registers of the method
+    RegisterOutOfRange {
+        register: u64,
+        register_len: u64,
+    },
+    /// The method takes only `arg_len` arguments
+    ArgOutOfRange {
+        arg: u64,
+        arg_len: usize,
+    },
+    UnresolvedType(StringTypeReference),
+    UnresolvedMethod(StringMethodReference),
+    UnresolvedField(StringName),
+    /// Only classes can be constructed with `NewObject`
+    NotAClass(StringTypeReference),
+    /// A value of type `got` is used where one of type `expected` is
+    TypeMismatch {
+        got: StringTypeReference,
+        expected: StringTypeReference,
+    },
+    /// A method taking `expected` arguments is called with `got`
+    ArgCountMismatch {
+        got: usize,
+        expected: usize,
+    },
+}
+
+/// A method rejected by [`CommonMethod::verify`], with the instruction at fault
+#[derive(Debug, Clone)]
+pub struct VerifyError {
+    pub ty: StringName,
+    pub method: StringName,
+    pub index: usize,
+    pub kind: VerifyErrorKind,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}::{}, instruction {}: ",
+            self.ty, self.method, self.index
+        )?;
+        match &self.kind {
+            VerifyErrorKind::RegisterOutOfRange {
+                register,
+                register_len,
+            } => write!(
+                f,
+                "register {register} is out of the {register_len} registers of the method"
+            ),
+            VerifyErrorKind::ArgOutOfRange { arg, arg_len } => {
+                write!(f, "argument {arg} is out of the {arg_len} arguments")
+            }
+            VerifyErrorKind::UnresolvedType(t) => {
+                write!(f, "type {} not found", t.string_name_repr())
+            }
+            VerifyErrorKind::UnresolvedMethod(m) => {
+                write!(f, "method {} not found", m.string_name_repr())
+            }
+            VerifyErrorKind::UnresolvedField(name) => write!(f, "field {name} not found"),
+            VerifyErrorKind::NotAClass(t) => {
+                write!(f, "{} is not a class", t.string_name_repr())
+            }
+            VerifyErrorKind::TypeMismatch { got, expected } => write!(
+                f,
+                "expected {}, got {}",
+                expected.string_name_repr(),
+                got.string_name_repr()
+            ),
+            VerifyErrorKind::ArgCountMismatch { got, expected } => {
+                write!(f, "expected {expected} arguments, got {got}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+impl From<VerifyError> for global::Error {
+    fn from(e: VerifyError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e).into()
+    }
+}
+
+/// Whether the reference names a type variable, which only an instantiation binds
+fn is_open(type_ref: &StringTypeReference) -> bool {
+    match type_ref {
+        StringTypeReference::Generic(_) => true,
+        StringTypeReference::WithGeneric { type_vars, .. } => type_vars.values().any(is_open),
+        _ => false,
+    }
+}
+
+/// A method called, as far as the call can be checked against it
+struct Callee {
+    signature: MethodSignature,
+    /// Natives may leave their parameters undeclared
+    native: bool,
+}
+
+/// The method `method_ref` of `ty`, or of the parents of `ty`
+fn callee_of(ty: &TypeHandle, method_ref: &StringMethodReference) -> Option<Callee> {
+    fn callee<T: Any + GetTypeName + GetAssemblyMust + GetTypeVars>(
+        method: CommonMethod<T>,
+    ) -> Callee {
+        Callee {
+            signature: method.signature(),
+            native: method.instructions.is_empty(),
+        }
+    }
+    match ty {
+        TypeHandle::Class(class) => class.mt().get_method(method_ref).ok().map(callee),
+        TypeHandle::Struct(s) => s.mt().get_method(method_ref).ok().map(callee),
+        TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => None,
+    }
+}
+
+/// The type of the static field `name` of `ty`
+fn static_field_of(ty: &TypeHandle, name: &str) -> Option<TypeHandle> {
+    match ty {
+        TypeHandle::Class(class) => {
+            let layout = class.static_layout().ok()?;
+            Some(layout.field(layout.slot_of(name)?)?.1.ty().clone())
+        }
+        TypeHandle::Struct(s) => {
+            let layout = s.static_layout().ok()?;
+            Some(layout.field(layout.slot_of(name)?)?.1.ty().clone())
+        }
+        TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => None,
+    }
+}
+
+/// Checking the code of a method once its assembly is loaded, so that running it cannot go out
+/// of its registers or arguments, or reach for types, methods and fields that do not exist.
+///
+/// The code runs straight through, having no branches, so the type of each register is known
+/// after every instruction wherever it depends on no type variable. The types known are checked
+/// against the parameters of the calls and the return type.
+impl<T: Any + GetTypeName + GetAssemblyMust + GetTypeVars + GetFieldSlot> CommonMethod<T> {
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let register_len = self.attr.register_len() as u64;
+        let mut registers: Vec<Option<TypeHandle>> = vec![None; register_len as usize];
+        for (index, ins) in self.instructions.iter().enumerate() {
+            let fail = |kind| VerifyError {
+                ty: self.mt().ty().name(),
+                method: self.name.clone(),
+                index,
+                kind,
+            };
+            let register = |register: u64| {
+                if register < register_len {
+                    Ok(register as usize)
+                } else {
+                    Err(fail(VerifyErrorKind::RegisterOutOfRange {
+                        register,
+                        register_len,
+                    }))
+                }
+            };
+            let expect =
+                |got: &Option<TypeHandle>, expected: &TypeHandle| match (got, self.known(expected))
+                {
+                    (Some(got), Some(expected)) if !signature::accepts(&expected, got) => {
+                        Err(fail(VerifyErrorKind::TypeMismatch {
+                            got: got.string_reference(),
+                            expected: expected.string_reference(),
+                        }))
+                    }
+                    _ => Ok(()),
+                };
+            let resolve = |type_ref: &StringTypeReference| match self.solve_str_type(type_ref) {
+                Ok(t) => Ok(Some(t)),
+                Err(_) if is_open(type_ref) => Ok(None),
+                Err(_) => Err(fail(VerifyErrorKind::UnresolvedType(type_ref.clone()))),
+            };
+            let core = |name: &'static str| {
+                self.known(&TypeHandle::Unloaded(
+                    StringTypeReference::core_static_single_type(name),
+                ))
+            };
+            match ins {
+                &StringInstruction::LoadTrue { register_addr }
+                | &StringInstruction::LoadFalse { register_addr } => {
+                    registers[register(register_addr)?] = core("System.Boolean");
+                }
+                &StringInstruction::Load_u8 { register_addr, .. }
+                | &StringInstruction::Load_u8_0 { register_addr }
+                | &StringInstruction::Load_u8_1 { register_addr }
+                | &StringInstruction::Load_u8_2 { register_addr }
+                | &StringInstruction::Load_u8_3 { register_addr }
+                | &StringInstruction::Load_u8_4 { register_addr }
+                | &StringInstruction::Load_u8_5 { register_addr } => {
+                    registers[register(register_addr)?] = core("System.UInt8");
+                }
+                &StringInstruction::Load_u64 { register_addr, .. } => {
+                    registers[register(register_addr)?] = core("System.UInt64");
+                }
+                &StringInstruction::LoadArg { register_addr, arg } => {
+                    let ty = self.args.get(arg as usize).ok_or(fail(
+                        VerifyErrorKind::ArgOutOfRange {
+                            arg: arg as u64,
+                            arg_len: self.args.len(),
+                        },
+                    ))?;
+                    registers[register(register_addr)?] = self.known(ty);
+                }
+                #[allow(deprecated)]
+                &StringInstruction::LoadAllArgsAsArray { register_addr } => {
+                    registers[register(register_addr)?] = None;
+                }
+                StringInstruction::InstanceCall {
+                    val,
+                    method,
+                    args,
+                    ret_at,
+                } => {
+                    let receiver = registers[register(*val)?].clone();
+                    let callee = match &receiver {
+                        Some(t) => Some(
+                            callee_of(t, method)
+                                .ok_or(fail(VerifyErrorKind::UnresolvedMethod(method.clone())))?,
+                        ),
+                        None => None,
+                    };
+                    self.check_call(callee.as_ref(), args, &registers, &register, &expect, &fail)?;
+                    registers[register(*ret_at)?] =
+                        callee.and_then(|x| self.known(x.signature.ret_type()));
+                }
+                StringInstruction::StaticCall {
+                    ty,
+                    method,
+                    args,
+                    ret_at,
+                } => {
+                    let callee = match resolve(ty)? {
+                        Some(t) => Some(
+                            callee_of(&t, method)
+                                .ok_or(fail(VerifyErrorKind::UnresolvedMethod(method.clone())))?,
+                        ),
+                        None => None,
+                    };
+                    self.check_call(callee.as_ref(), args, &registers, &register, &expect, &fail)?;
+                    registers[register(*ret_at)?] =
+                        callee.and_then(|x| self.known(x.signature.ret_type()));
+                }
+                StringInstruction::LoadStatic {
+                    register_addr,
+                    ty,
+                    name,
+                } => {
+                    let field = match resolve(ty)? {
+                        Some(t) => Some(
+                            static_field_of(&t, name)
+                                .ok_or(fail(VerifyErrorKind::UnresolvedField(name.clone())))?,
+                        ),
+                        None => None,
+                    };
+                    registers[register(*register_addr)?] = field.and_then(|x| self.known(&x));
+                }
+                StringInstruction::NewObject {
+                    ty,
+                    ctor_name,
+                    args,
+                    register_addr,
+                } => {
+                    let class = resolve(ty)?;
+                    let callee = match &class {
+                        Some(t @ TypeHandle::Class(_)) => {
+                            let ctor = StringMethodReference::Single(ctor_name.clone());
+                            Some(
+                                callee_of(t, &ctor)
+                                    .ok_or(fail(VerifyErrorKind::UnresolvedMethod(ctor)))?,
+                            )
+                        }
+                        Some(_) => return Err(fail(VerifyErrorKind::NotAClass(ty.clone()))),
+                        None => None,
+                    };
+                    self.check_call(callee.as_ref(), args, &registers, &register, &expect, &fail)?;
+                    registers[register(*register_addr)?] = class;
+                }
+                &StringInstruction::ReturnVal { register_addr } => {
+                    expect(&registers[register(register_addr)?], &self.ret_type)?;
+                }
+                StringInstruction::SetField {
+                    register_addr,
+                    field,
+                } => {
+                    register(*register_addr)?;
+                    let slots = self.field_slots().ok();
+                    if slots
+                        .and_then(|x| x.get(index).copied().flatten())
+                        .is_none()
+                    {
+                        return Err(fail(VerifyErrorKind::UnresolvedField(field.clone())));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Verify the code of a method read from a binary assembly the first time this is called,
+    /// see [`CommonMethod::verified_before_run`]. The other methods are trusted.
+    pub(crate) fn check_verified(&self) -> Result<(), VerifyError> {
+        match &self.verified {
+            Some(verified) => verified.get_or_init(|| self.verify()).clone(),
+            None => Ok(()),
+        }
+    }
+    /// `ty` when it names a loaded type, with the type variables bound for the method filled in
+    fn known(&self, ty: &TypeHandle) -> Option<TypeHandle> {
+        match ty {
+            TypeHandle::Class(_) | TypeHandle::Struct(_) => Some(ty.clone()),
+            TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => {
+                self.solve_str_type(&ty.string_reference()).ok()
+            }
+        }
+    }
+    /// Check the arguments of a call in the registers `args` against the parameters of the
+    /// method called, when it is known
+    fn check_call<E>(
+        &self,
+        callee: Option<&Callee>,
+        args: &[u64],
+        registers: &[Option<TypeHandle>],
+        register: &impl Fn(u64) -> Result<usize, E>,
+        expect: &impl Fn(&Option<TypeHandle>, &TypeHandle) -> Result<(), E>,
+        fail: &impl Fn(VerifyErrorKind) -> E,
+    ) -> Result<(), E> {
+        for &arg in args {
+            register(arg)?;
+        }
+        let Some(callee) = callee else {
+            return Ok(());
+        };
+        let params = callee.signature.params();
+        if params.len() != args.len() {
+            if callee.native {
+                return Ok(());
+            }
+            return Err(fail(VerifyErrorKind::ArgCountMismatch {
+                got: args.len(),
+                expected: params.len(),
+            }));
+        }
+        for (&arg, param) in args.iter().zip(params) {
+            expect(&registers[register(arg)?], param)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::{self, Display, Formatter};
+
+use global::{StringName, getset::Getters};
+
+/// The version of an assembly, ordered by its major, minor and then patch number
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AssemblyVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl AssemblyVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl Display for AssemblyVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The versions of an assembly from `min` up to, but not including, `max`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VersionRange {
+    pub min: AssemblyVersion,
+    pub max: Option<AssemblyVersion>,
+}
+
+impl VersionRange {
+    pub const ANY: Self = Self::new(AssemblyVersion::new(0, 0, 0), None);
+    pub const fn new(min: AssemblyVersion, max: Option<AssemblyVersion>) -> Self {
+        Self { min, max }
+    }
+    /// The versions which can replace `version`: the ones from it up to the next major version
+    pub fn compatible_with(version: AssemblyVersion) -> Self {
+        Self::new(
+            version,
+            version
+                .major
+                .checked_add(1)
+                .map(|major| AssemblyVersion::new(major, 0, 0)),
+        )
+    }
+    pub fn contains(&self, version: &AssemblyVersion) -> bool {
+        self.min <= *version && self.max.is_none_or(|max| *version < max)
+    }
+}
+
+impl Display for VersionRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.max {
+            Some(max) => write!(f, ">={}, <{max}", self.min),
+            None => write!(f, ">={}", self.min),
+        }
+    }
+}
+
+/// An assembly which must be loaded, in a version of `range`, for another one to be
+#[derive(Clone, Debug, Getters)]
+#[getset(get = "pub")]
+pub struct AssemblyDependency {
+    name: StringName,
+    range: VersionRange,
+}
+
+impl AssemblyDependency {
+    pub fn new(name: StringName, range: VersionRange) -> Self {
+        Self { name, range }
+    }
+}
+
+impl Display for AssemblyDependency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.range)
+    }
+}
@@ -1,7 +1,10 @@
 use std::{ptr, sync::Arc};
 
-use gc::{Gc, Trace};
-use global::{Result, StringTypeReference, ThreadSafe, UnwrapEnum, indexmap, string_name};
+use export::AssemblyTrait;
+use gc::{Gc, Pinned, Trace};
+use global::{
+    Result, StringName, StringTypeReference, ThreadSafe, UnwrapEnum, indexmap, string_name,
+};
 
 #[derive(Clone, Default, Debug, ThreadSafe, global::PartialEq, UnwrapEnum, Trace)]
 #[fully_eq]
@@ -21,10 +24,20 @@ pub enum Value {
     Int32(i32),
     Int64(i64),
     Int128(i128),
+    /// A value type, which is copied whenever it is passed, returned or stored
     Struct(StructObject),
     #[custom_eq(ptr::eq(a0, a0_))]
     Reference(Gc<ByRefValue>),
+    /// A managed reference to the value in a register, through which the register is changed
     RegisterReference(u64),
+    /// A managed reference to the field of an object in the given slot
+    #[custom_eq(a0.addr() == a0_.addr() && a1 == a1_)]
+    FieldReference(Gc<ByRefValue>, usize),
+    /// A managed reference to the element of an array at the given index
+    #[custom_eq(a0.addr() == a0_.addr() && a1 == a1_)]
+    ElementReference(Gc<ByRefValue>, usize),
+    /// A managed reference to the static field of a type in the given slot of its static layout
+    StaticReference(#[ignore_trace] StringTypeReference, usize),
 }
 
 macro core_type($vm:expr, $name:literal) {
@@ -33,6 +46,87 @@ macro core_type($vm:expr, $name:literal) {
 }
 
 impl Value {
+    /// Pin every object this value references directly, for as long as `pins` lives
+    pub fn pin_references(&self, pins: &mut Vec<Pinned<ByRefValue>>) {
+        match self {
+            Value::Reference(r) | Value::FieldReference(r, _) | Value::ElementReference(r, _) => {
+                pins.push(r.pinned())
+            }
+            Value::Struct(s) => {
+                for (_, field) in s.fields() {
+                    field.pin_references(pins);
+                }
+            }
+            _ => {}
+        }
+    }
+    /// Report every object this value references directly to the incremental collector,
+    /// see [`Gc::write_barrier`]. Call it whenever the value is stored into an object.
+    pub fn write_barrier(&self) {
+        match self {
+            Value::Reference(r) | Value::FieldReference(r, _) | Value::ElementReference(r, _) => {
+                r.write_barrier()
+            }
+            Value::Struct(s) => {
+                for (_, field) in s.fields() {
+                    field.write_barrier();
+                }
+            }
+            _ => {}
+        }
+    }
+    pub fn is_managed_reference(&self) -> bool {
+        matches!(
+            self,
+            Value::RegisterReference(_)
+                | Value::FieldReference(..)
+                | Value::ElementReference(..)
+                | Value::StaticReference(..)
+        )
+    }
+    /// Whether this value is a value type of the assembly `assem` or an instantiation over one,
+    /// holds one in its fields, or references a static of such a type. The objects it references
+    /// are not looked into, see [`ByRefValue::refers_to_assembly`].
+    pub fn refers_to_assembly(&self, assem: &StringName) -> bool {
+        match self {
+            Value::Struct(s) => {
+                s.ty().refers_to_assembly(assem)
+                    || s.fields().any(|(_, v)| v.refers_to_assembly(assem))
+            }
+            Value::StaticReference(t, _) => refers_to_assembly(t, assem),
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    /// The value a field of type `t` starts as: zero for the integers, `False` for booleans,
+    /// null for classes and a struct with defaulted fields for the other value types
+    pub fn default_of(t: &TypeHandle, vm: &Arc<VM>) -> Result<Value> {
+        let s = match t {
+            TypeHandle::Class(_) => return Ok(Value::Reference(vm.null()?)),
+            TypeHandle::Struct(s) => s,
+            TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => return Ok(Value::Void),
+        };
+        if s.assem().name() != StringTypeReference::CORE_ASSEMBLY_NAME {
+            return Ok(Value::Struct(StructObject::make(s.mt.get(), vm)?));
+        }
+        Ok(match &**s.name() {
+            "System.Void" => Value::Void,
+            "System.Boolean" => Value::False,
+            "System.UInt8" => Value::UInt8(0),
+            "System.UInt16" => Value::UInt16(0),
+            "System.UInt32" => Value::UInt32(0),
+            "System.UInt64" => Value::UInt64(0),
+            "System.UInt128" => Value::UInt128(0),
+            "System.Int8" => Value::Int8(0),
+            "System.Int16" => Value::Int16(0),
+            "System.Int32" => Value::Int32(0),
+            "System.Int64" => Value::Int64(0),
+            "System.Int128" => Value::Int128(0),
+            _ => Value::Struct(StructObject::make(s.mt.get(), vm)?),
+        })
+    }
     pub fn ty(&self, cpu: Arc<CPU>) -> Result<TypeHandle> {
         match self {
             Value::Void => Ok(core_type!(cpu.vm(), "System.Void")),
@@ -49,12 +143,16 @@ impl Value {
             Value::Int64(_) => Ok(core_type!(cpu.vm(), "System.Int64")),
             Value::Int128(_) => Ok(core_type!(cpu.vm(), "System.Int128")),
             Value::Struct(s) => Ok(s.ty()),
-            Value::Reference(gc) => Ok(gc.ty(cpu.vm())),
-            Value::RegisterReference(_) => unreachable!(),
+            Value::Reference(gc) => gc.ty(cpu.vm()),
+            Value::RegisterReference(_)
+            | Value::FieldReference(..)
+            | Value::ElementReference(..)
+            | Value::StaticReference(..) => cpu.load_indirect(self)?.ty(cpu),
         }
     }
-    pub fn string_type_reference(&self) -> StringTypeReference {
-        match self {
+    /// The type of the value; a managed reference has the type of what it points to
+    pub fn string_type_reference(&self, cpu: &CPU) -> Result<StringTypeReference> {
+        Ok(match self {
             Value::Void => StringTypeReference::core_static_single_type("System.Void"),
             Value::True | Value::False => {
                 StringTypeReference::core_static_single_type("System.Boolean")
@@ -71,8 +169,13 @@ impl Value {
             Value::Int128(_) => StringTypeReference::core_static_single_type("System.Int128"),
             Value::Struct(s) => s.ty().string_reference(),
             Value::Reference(gc) => gc.string_type_reference(),
-            Value::RegisterReference(_) => unreachable!(),
-        }
+            Value::RegisterReference(_)
+            | Value::FieldReference(..)
+            | Value::ElementReference(..)
+            | Value::StaticReference(..) => {
+                return cpu.load_indirect(self)?.string_type_reference(cpu);
+            }
+        })
     }
 }
 
@@ -82,17 +185,25 @@ pub enum ByRefValue {
     Object(Object),
     Array(Array),
     String(StringValue),
+    WeakReference(WeakReferenceValue),
+    Type(TypeValue),
+    MethodInfo(MethodInfoValue),
+    FieldInfo(FieldInfoValue),
     Null,
 }
 
 impl ByRefValue {
-    pub fn ty(&self, vm: Arc<VM>) -> TypeHandle {
-        match self {
+    pub fn ty(&self, vm: Arc<VM>) -> Result<TypeHandle> {
+        Ok(match self {
             Self::Object(obj) => obj.ty(),
             Self::Array(arr) => arr.ty(vm),
             Self::String(s) => s.ty(vm),
+            Self::WeakReference(w) => w.ty(vm)?,
+            Self::Type(_) => core_type!(vm, "System.Type"),
+            Self::MethodInfo(_) => core_type!(vm, "System.Reflection.MethodInfo"),
+            Self::FieldInfo(_) => core_type!(vm, "System.Reflection.FieldInfo"),
             Self::Null => core_type!(vm, "System.Null"),
-        }
+        })
     }
     pub fn string_type_reference(&self) -> StringTypeReference {
         match self {
@@ -104,9 +215,35 @@ impl ByRefValue {
                 }),
             ),
             Self::String(_) => StringTypeReference::core_static_single_type("System.String"),
+            Self::WeakReference(w) => StringTypeReference::core_generic_type(
+                string_name!("System.WeakReference`1"),
+                Arc::new(indexmap! {
+                    string_name!("@T") => w.t.string_reference(),
+                }),
+            ),
+            Self::Type(_) => StringTypeReference::core_static_single_type("System.Type"),
+            Self::MethodInfo(_) => {
+                StringTypeReference::core_static_single_type("System.Reflection.MethodInfo")
+            }
+            Self::FieldInfo(_) => {
+                StringTypeReference::core_static_single_type("System.Reflection.FieldInfo")
+            }
             Self::Null => StringTypeReference::core_static_single_type("System.Null"),
         }
     }
+    /// Whether the object is of a type of the assembly `assem` or an instantiation over one,
+    /// holds such a value type, or reflects over such a type
+    pub fn refers_to_assembly(&self, assem: &StringName) -> bool {
+        refers_to_assembly(&self.string_type_reference(), assem)
+            || match self {
+                Self::Object(obj) => obj.fields().any(|(_, v)| v.refers_to_assembly(assem)),
+                Self::Array(arr) => arr[..].iter().any(|v| v.refers_to_assembly(assem)),
+                Self::Type(t) => t.get().refers_to_assembly(assem),
+                Self::MethodInfo(m) => m.declaring_type().refers_to_assembly(assem),
+                Self::FieldInfo(f) => f.declaring_type().refers_to_assembly(assem),
+                Self::String(_) | Self::WeakReference(_) | Self::Null => false,
+            }
+    }
 }
 mod string_value {
     use std::{
@@ -114,14 +251,17 @@ mod string_value {
         sync::Arc,
     };
 
-    use gc::Trace;
-    use global::string_name;
+    use gc::{Gc, Trace};
+    use global::{Result, string_name};
 
-    use crate::{type_system::TypeHandle, vm::VM};
+    use super::ByRefValue;
+    use crate::{
+        type_system::TypeHandle,
+        vm::{CPU, VM},
+    };
 
     #[derive(Clone, Trace)]
     pub struct StringValue {
-        #[ignore_trace]
         s: String,
     }
 
@@ -129,8 +269,14 @@ mod string_value {
         pub fn new(s: String) -> Self {
             Self { s }
         }
-        pub fn set(&mut self, val: String) {
-            self.s = val;
+        /// Replace the string held by `this`, whose growth counts against the heap limit
+        pub fn set(cpu: &CPU, mut this: Gc<ByRefValue>, val: String) -> Result<()> {
+            let additional = val
+                .capacity()
+                .saturating_sub(this.unwrap_string_ref()?.0.s.capacity());
+            cpu.heap_grow(&this, additional)?;
+            this.unwrap_string_mut()?.0.s = val;
+            Ok(())
         }
         pub fn get(&self) -> &str {
             &self.s
@@ -166,43 +312,75 @@ mod string_value {
 pub use string_value::*;
 
 mod struct_object {
-    use crate::value::TypeHandle;
     use std::sync::Arc;
 
     use super::Value;
-    use crate::type_system::get_traits::MTGetParent;
-    use crate::type_system::{CommonMethodTable, Struct, StructField};
-    
+    use crate::type_system::{CommonMethodTable, FieldLayout, Struct, StructField, TypeHandle};
+    use crate::vm::VM;
+
     use gc::Trace;
-    use global::derive_ctor::ctor;
-    use global::getset::Getters;
-    use global::{IndexMap, Result, StringName, ThreadSafe, errors::RuntimeError};
+    use global::{Result, StringName, ThreadSafe, errors::RuntimeError};
 
-    #[derive(Clone, derive_more::Debug, ThreadSafe, Trace, ctor)]
-    #[ctor(pub(crate) internal_new)]
+    #[derive(Clone, derive_more::Debug, ThreadSafe, Trace)]
     pub struct StructObject {
         #[ignore_trace]
         #[debug("{}", unsafe { &**mt }.struct_type().name())]
         pub(crate) mt: *mut CommonMethodTable<Struct>,
-        fields: IndexMap<StringName, InstanceField>,
+        #[ignore_trace]
+        #[debug(skip)]
+        layout: Arc<FieldLayout<StructField>>,
+        slots: Vec<Value>,
     }
 
     impl StructObject {
-        pub fn make(mt: *mut CommonMethodTable<Struct>) -> Self {
-            Self {
-                mt,
-                fields: get_instance_fields(unsafe { (&*mt).struct_type() }),
-            }
+        pub fn make(mt: *mut CommonMethodTable<Struct>, vm: &Arc<VM>) -> Result<Self> {
+            let layout = unsafe { (&*mt).struct_type() }.instance_layout()?;
+            Self::with_layout(mt, layout, vm)
+        }
+        /// A value with every field of `layout` defaulted by its type, which is the static
+        /// layout for the value holding the statics of the struct
+        pub(crate) fn with_layout(
+            mt: *mut CommonMethodTable<Struct>,
+            layout: Arc<FieldLayout<StructField>>,
+            vm: &Arc<VM>,
+        ) -> Result<Self> {
+            let slots = layout
+                .fields()
+                .values()
+                .map(|x| Value::default_of(x.ty(), vm))
+                .try_collect::<Vec<_>>()?;
+            Ok(Self { mt, layout, slots })
+        }
+        pub fn get_field(&self, name: impl AsRef<str>) -> Result<&Value> {
+            self.layout
+                .slot_of(name.as_ref())
+                .map(|x| &self.slots[x])
+                .ok_or(RuntimeError::FailedGetField(name.as_ref().into()).into())
         }
-        pub fn get_field(&self, k: impl AsRef<str>) -> Result<&InstanceField> {
-            self.fields
-                .get(k.as_ref())
-                .ok_or(RuntimeError::FailedGetField(k.as_ref().into()).into())
+        pub fn set_field(&mut self, name: impl AsRef<str>, v: &Value) -> Result<()> {
+            let slot = self
+                .layout
+                .slot_of(name.as_ref())
+                .ok_or(RuntimeError::FailedGetField(name.as_ref().into()))?;
+            self.set_slot(slot, v)
         }
-        pub fn get_mut_field(&mut self, name: impl AsRef<str>) -> Result<&mut InstanceField> {
-            self.fields
-                .get_mut(name.as_ref())
-                .ok_or(RuntimeError::FailedGetField(name.as_ref().into()).into())
+        pub fn slot(&self, slot: usize) -> Option<&Value> {
+            self.slots.get(slot)
+        }
+        pub fn set_slot(&mut self, slot: usize, v: &Value) -> Result<()> {
+            let Some(dest) = self.slots.get_mut(slot) else {
+                return Err(RuntimeError::FailedGetField(slot.to_string().as_str().into()).into());
+            };
+            v.write_barrier();
+            *dest = v.clone();
+            Ok(())
+        }
+        pub fn layout(&self) -> &FieldLayout<StructField> {
+            &self.layout
+        }
+        /// The names and values of the fields, in slot order
+        pub fn fields(&self) -> impl Iterator<Item = (&StringName, &Value)> {
+            self.layout.fields().keys().zip(&self.slots)
         }
         pub fn ty(&self) -> TypeHandle {
             unsafe { TypeHandle::Struct((*self.mt).struct_type()) }
@@ -216,91 +394,19 @@ mod struct_object {
                     .struct_type()
                     .name()
                     .eq((&*other.mt).struct_type().name())
-            }) && self.fields.eq(&other.fields)
+            }) && self.slots.eq(&other.slots)
         }
     }
     impl Eq for StructObject {}
-
-    #[derive(Clone, Debug, ctor, Getters, Trace)]
-    #[ctor(pub(crate) internal_new)]
-    #[getset(get = "pub")]
-    pub struct InstanceField {
-        val: Value,
-        #[ignore_trace]
-        field: StructField,
-    }
-
-    impl PartialEq for InstanceField {
-        fn eq(&self, other: &Self) -> bool {
-            self.val.eq(&other.val)
-        }
-    }
-
-    impl Eq for InstanceField {}
-
-    impl InstanceField {
-        pub fn set_val(&mut self, v: &Value) {
-            self.val = v.clone();
-        }
-    }
-
-    pub(crate) fn get_instance_fields(t: Arc<Struct>) -> IndexMap<StringName, InstanceField> {
-        let mut map = IndexMap::new();
-        if let Some(parent) = t.mt()._parent() {
-            map.append(&mut get_instance_fields(parent));
-        }
-        for (k, v) in t.fields() {
-            if v.attr().is_static() {
-                continue;
-            }
-            map.insert(
-                k.clone(),
-                InstanceField {
-                    val: Value::Void,
-                    field: v.clone(),
-                },
-            );
-        }
-        map
-    }
-
-    pub(crate) fn get_static_fields(t: Arc<Struct>) -> IndexMap<StringName, InstanceField> {
-        let mut map = IndexMap::new();
-        if let Some(parent) = t.mt()._parent() {
-            map.append(&mut get_static_fields(parent));
-        }
-        for (k, v) in t.static_fields() {
-            map.insert(
-                k.clone(),
-                InstanceField {
-                    val: Value::Void,
-                    field: v.clone(),
-                },
-            );
-        }
-        map
-    }
 }
 
-pub use struct_object::{InstanceField as StructInstanceField, StructObject};
-
-#[allow(unused_imports)]
-pub(crate) use struct_object::{
-    get_instance_fields as struct_get_instance_fields,
-    get_static_fields as struct_get_static_fields,
-};
+pub use struct_object::StructObject;
 
 mod array {
-    use std::{
-        fmt,
-        ops::{Index, IndexMut},
-        ptr,
-        slice::SliceIndex,
-        sync::Arc,
-    };
+    use std::{fmt, ops::Index, ptr, slice::SliceIndex, sync::Arc};
 
     use gc::{Gc, Trace};
-    use global::{indexmap, string_name};
+    use global::{Result, indexmap, string_name};
 
     use crate::{
         type_system::TypeHandle,
@@ -312,13 +418,12 @@ mod array {
     #[derive(Clone, derive_more::Debug, Trace)]
     pub struct Array {
         #[debug("{}", t.name())]
-        #[ignore_trace]
         pub(crate) t: TypeHandle,
         inner: Vec<Value>,
     }
 
     impl Array {
-        pub fn alloc(cpu: Arc<CPU>, t: TypeHandle) -> Gc<ByRefValue> {
+        pub fn alloc(cpu: Arc<CPU>, t: TypeHandle) -> Result<Gc<ByRefValue>> {
             cpu.heap_alloc(ByRefValue::Array(Self {
                 t,
                 inner: Vec::new(),
@@ -328,7 +433,7 @@ mod array {
             cpu: Arc<CPU>,
             t: TypeHandle,
             capacity: usize,
-        ) -> Gc<ByRefValue> {
+        ) -> Result<Gc<ByRefValue>> {
             cpu.heap_alloc(ByRefValue::Array(Self {
                 t,
                 inner: Vec::with_capacity(capacity),
@@ -338,16 +443,61 @@ mod array {
             cpu: Arc<CPU>,
             t: TypeHandle,
             data: T,
-        ) -> Gc<ByRefValue> {
+        ) -> Result<Gc<ByRefValue>> {
             cpu.heap_alloc(ByRefValue::Array(Self {
                 t,
                 inner: data.as_ref().to_vec(),
             }))
         }
-        pub fn grow_to(&mut self, len: usize) {
-            if self.inner.len() < len {
-                self.inner.resize_with(len, Default::default);
+    }
+
+    /// Operations growing the array held by `this`, whose memory counts against the heap limit
+    impl Array {
+        /// Make room for `additional` more elements, failing if the heap is out of memory
+        fn reserve<'a>(
+            cpu: &CPU,
+            this: &'a mut Gc<ByRefValue>,
+            additional: usize,
+        ) -> Result<&'a mut Self> {
+            let (array,) = this.unwrap_array_ref()?;
+            let capacity = array.inner.capacity();
+            let len = array.inner.len() + additional;
+            if len > capacity {
+                let new_capacity = len.max(capacity * 2);
+                cpu.heap_grow(&*this, (new_capacity - capacity) * size_of::<Value>())?;
+                let (array,) = this.unwrap_array_mut()?;
+                array.inner.reserve_exact(new_capacity - array.inner.len());
+            }
+            Ok(this.unwrap_array_mut()?.0)
+        }
+        pub fn grow_to(cpu: &CPU, mut this: Gc<ByRefValue>, len: usize) -> Result<()> {
+            let additional = len.saturating_sub(this.unwrap_array_ref()?.0.inner.len());
+            let array = Self::reserve(cpu, &mut this, additional)?;
+            if array.inner.len() < len {
+                array.inner.resize_with(len, Default::default);
             }
+            Ok(())
+        }
+        pub fn push(cpu: &CPU, mut this: Gc<ByRefValue>, v: Value) -> Result<()> {
+            let array = Self::reserve(cpu, &mut this, 1)?;
+            v.write_barrier();
+            array.inner.push(v);
+            Ok(())
+        }
+        pub fn set_values<T: AsRef<[Value]>>(
+            cpu: &CPU,
+            mut this: Gc<ByRefValue>,
+            values: T,
+        ) -> Result<()> {
+            let values = values.as_ref();
+            let additional = values
+                .len()
+                .saturating_sub(this.unwrap_array_ref()?.0.inner.len());
+            let array = Self::reserve(cpu, &mut this, additional)?;
+            values.iter().for_each(Value::write_barrier);
+            array.inner.clear();
+            array.inner.extend_from_slice(values);
+            Ok(())
         }
     }
 
@@ -359,12 +509,6 @@ mod array {
         }
     }
 
-    impl<I: SliceIndex<[Value]>> IndexMut<I> for Array {
-        fn index_mut(&mut self, index: I) -> &mut Self::Output {
-            &mut self.inner[index]
-        }
-    }
-
     impl Array {
         pub fn get<I: SliceIndex<[Value]>>(&self, index: I) -> Option<&I::Output> {
             self.inner
@@ -372,17 +516,11 @@ mod array {
                 .map(ptr::from_ref)
                 .map(|x| unsafe { &*x })
         }
-        pub fn get_mut<I: SliceIndex<[Value]>>(&mut self, index: I) -> Option<&mut I::Output> {
-            self.inner
-                .get_mut(index)
-                .map(ptr::from_mut)
-                .map(|x| unsafe { &mut *x })
-        }
-        pub fn push(&mut self, v: Value) {
-            self.inner.push(v);
-        }
-        pub fn set_values<T: AsRef<[Value]>>(&mut self, values: T) {
-            self.inner = values.as_ref().to_vec();
+        /// Store `v` at `index`, returning `None` if it is out of bounds
+        pub fn set(&mut self, index: usize, v: Value) -> Option<()> {
+            v.write_barrier();
+            *self.inner.get_mut(index)? = v;
+            Some(())
         }
     }
 
@@ -412,37 +550,175 @@ mod array {
 
 pub use array::*;
 
+mod weak_reference {
+    use std::sync::Arc;
+
+    use gc::{Gc, Trace, WeakGc};
+    use global::{Result, indexmap, string_name};
+
+    use crate::{type_system::TypeHandle, vm::VM};
+
+    use super::ByRefValue;
+
+    /// Payload of `` System.WeakReference`1 ``
+    #[derive(Clone, derive_more::Debug, Trace)]
+    pub struct WeakReferenceValue {
+        #[debug("{}", t.name())]
+        pub(crate) t: TypeHandle,
+        target: WeakGc<ByRefValue>,
+    }
+
+    impl WeakReferenceValue {
+        pub fn new(t: TypeHandle, target: Option<&Gc<ByRefValue>>) -> Self {
+            Self {
+                t,
+                target: target.map(Gc::downgrade).unwrap_or_else(WeakGc::empty),
+            }
+        }
+        pub fn target(&self) -> Option<Gc<ByRefValue>> {
+            self.target.upgrade()
+        }
+        pub fn set_target(&mut self, target: Option<&Gc<ByRefValue>>) {
+            self.target = target.map(Gc::downgrade).unwrap_or_else(WeakGc::empty);
+        }
+        pub fn is_alive(&self) -> bool {
+            self.target.is_alive()
+        }
+    }
+
+    impl WeakReferenceValue {
+        pub fn ty(&self, vm: Arc<VM>) -> Result<TypeHandle> {
+            vm.get_core_single_type(string_name!("System.WeakReference`1"))?
+                .make_generic(Arc::new(indexmap! {
+                    string_name!("@T") => self.t.clone()
+                }))
+        }
+    }
+}
+
+pub use weak_reference::*;
+
+mod reflection {
+    use gc::Trace;
+    use global::StringName;
+
+    use crate::type_system::TypeHandle;
+
+    /// Payload of `System.Type`
+    #[derive(Clone, derive_more::Debug, Trace)]
+    pub struct TypeValue {
+        #[debug("{}", t.name())]
+        pub(crate) t: TypeHandle,
+    }
+
+    impl TypeValue {
+        pub fn new(t: TypeHandle) -> Self {
+            Self { t }
+        }
+        pub fn get(&self) -> &TypeHandle {
+            &self.t
+        }
+    }
+
+    /// Payload of `System.Reflection.MethodInfo`, naming the method `name` in the method table
+    /// of `t`
+    #[derive(Clone, derive_more::Debug, Trace)]
+    pub struct MethodInfoValue {
+        #[debug("{}", t.name())]
+        pub(crate) t: TypeHandle,
+        pub(crate) name: StringName,
+    }
+
+    impl MethodInfoValue {
+        pub fn new(t: TypeHandle, name: StringName) -> Self {
+            Self { t, name }
+        }
+        pub fn declaring_type(&self) -> &TypeHandle {
+            &self.t
+        }
+        pub fn name(&self) -> &StringName {
+            &self.name
+        }
+    }
+
+    /// Payload of `System.Reflection.FieldInfo`, naming the field `name` declared by `t`
+    #[derive(Clone, derive_more::Debug, Trace)]
+    pub struct FieldInfoValue {
+        #[debug("{}", t.name())]
+        pub(crate) t: TypeHandle,
+        pub(crate) name: StringName,
+        pub(crate) is_static: bool,
+    }
+
+    impl FieldInfoValue {
+        pub fn new(t: TypeHandle, name: StringName, is_static: bool) -> Self {
+            Self { t, name, is_static }
+        }
+        pub fn declaring_type(&self) -> &TypeHandle {
+            &self.t
+        }
+        pub fn name(&self) -> &StringName {
+            &self.name
+        }
+        pub fn is_static(&self) -> bool {
+            self.is_static
+        }
+    }
+}
+
+pub use reflection::*;
+
 mod object {
     use std::sync::Arc;
 
     use gc::{Gc, Trace};
-    use global::{
-        IndexMap, Result, StringName, derive_ctor::ctor, errors::RuntimeError, getset::Getters,
-    };
+    use global::{Result, StringMethodReference, StringName, errors::RuntimeError};
 
     use super::{ByRefValue, Value};
-    use crate::type_system::get_traits::MTGetParent;
+    use crate::vm::{FINALIZE_SIGN, VM};
     use crate::{
-        type_system::{Class, ClassField, CommonMethodTable, TypeHandle},
+        type_system::{Class, ClassField, CommonMethodTable, FieldLayout, TypeHandle},
         vm::CPU,
     };
 
-    #[derive(Clone, derive_more::Debug, ctor, Trace)]
-    #[ctor(pub(crate) internal_new)]
+    #[derive(Clone, derive_more::Debug, Trace)]
     pub struct Object {
         #[debug("{}", unsafe { &**mt }.class().name())]
         #[ignore_trace]
         pub(crate) mt: *mut CommonMethodTable<Class>,
-        fields: IndexMap<StringName, InstanceField>,
+        #[ignore_trace]
+        #[debug(skip)]
+        layout: Arc<FieldLayout<ClassField>>,
+        slots: Vec<Value>,
     }
 
     impl Object {
-        pub fn alloc(cpu: Arc<CPU>, mt: *mut CommonMethodTable<Class>) -> Gc<ByRefValue> {
+        pub fn alloc(cpu: Arc<CPU>, mt: *mut CommonMethodTable<Class>) -> Result<Gc<ByRefValue>> {
             assert!(!mt.is_null());
-            cpu.heap_alloc(ByRefValue::Object(Self {
-                mt: mt.cast(),
-                fields: get_instance_fields((unsafe { &*mt }).class()),
-            }))
+            let layout = (unsafe { &*mt }).class().instance_layout()?;
+            let obj = Self::with_layout(mt, layout, &cpu.vm())?;
+            let obj = cpu.heap_alloc(ByRefValue::Object(obj))?;
+            if unsafe { &*mt }
+                .get_method(&StringMethodReference::Single(FINALIZE_SIGN))
+                .is_ok()
+            {
+                obj.register_for_finalization();
+            }
+            Ok(obj)
+        }
+        /// An object with every field of `layout` defaulted by its type, which is the static
+        /// layout for the object holding the statics of the class
+        pub(crate) fn with_layout(
+            mt: *mut CommonMethodTable<Class>,
+            layout: Arc<FieldLayout<ClassField>>,
+            vm: &Arc<VM>,
+        ) -> Result<Self> {
+            let slots = layout
+                .fields()
+                .values()
+                .map(|x| Value::default_of(x.ty(), vm))
+                .try_collect::<Vec<_>>()?;
+            Ok(Self { mt, layout, slots })
         }
         pub fn call_as_this(&self, _cpu: Arc<CPU>, _method_name: StringName) -> Result<Value> {
             todo!()
@@ -456,81 +732,44 @@ mod object {
     }
 
     impl Object {
-        pub fn get_field(&self, name: StringName) -> Result<&InstanceField> {
-            self.fields
-                .get(&name)
-                .ok_or(RuntimeError::FailedGetField(name).into())
-        }
-        pub fn get_mut_field(&mut self, name: StringName) -> Result<&mut InstanceField> {
-            self.fields
-                .get_mut(&name)
+        pub fn get_field(&self, name: StringName) -> Result<&Value> {
+            self.layout
+                .slot_of(&name)
+                .map(|x| &self.slots[x])
                 .ok_or(RuntimeError::FailedGetField(name).into())
         }
-    }
-
-    #[derive(Clone, Debug, ctor, Getters, Trace)]
-    #[ctor(pub(crate) internal_new)]
-    #[getset(get = "pub")]
-    pub struct InstanceField {
-        val: Value,
-        #[ignore_trace]
-        field: ClassField,
-    }
-
-    impl InstanceField {
-        pub fn set_val(&mut self, v: &Value) {
-            self.val = v.clone();
+        pub fn set_field(&mut self, name: StringName, v: &Value) -> Result<()> {
+            let slot = self
+                .layout
+                .slot_of(&name)
+                .ok_or(RuntimeError::FailedGetField(name))?;
+            self.set_slot(slot, v)
         }
-    }
-
-    pub(crate) fn get_instance_fields(class: Arc<Class>) -> IndexMap<StringName, InstanceField> {
-        let mut map = IndexMap::new();
-        if let Some(parent) = class.mt()._parent() {
-            map.append(&mut get_instance_fields(parent));
+        pub fn slot(&self, slot: usize) -> Option<&Value> {
+            self.slots.get(slot)
         }
-        for (k, v) in class.fields() {
-            if v.attr().is_static() {
-                continue;
-            }
-            map.insert(
-                k.clone(),
-                InstanceField {
-                    val: Value::Void,
-                    field: v.clone(),
-                },
-            );
+        pub fn set_slot(&mut self, slot: usize, v: &Value) -> Result<()> {
+            let Some(dest) = self.slots.get_mut(slot) else {
+                return Err(RuntimeError::FailedGetField(slot.to_string().as_str().into()).into());
+            };
+            v.write_barrier();
+            *dest = v.clone();
+            Ok(())
         }
-        map
-    }
-
-    pub(crate) fn get_static_fields(class: Arc<Class>) -> IndexMap<StringName, InstanceField> {
-        let mut map = IndexMap::new();
-        if let Some(parent) = class.mt()._parent() {
-            map.append(&mut get_static_fields(parent));
+        pub fn layout(&self) -> &FieldLayout<ClassField> {
+            &self.layout
         }
-        for (k, v) in class.static_fields() {
-            map.insert(
-                k.clone(),
-                InstanceField {
-                    val: Value::Void,
-                    field: v.clone(),
-                },
-            );
+        /// The names and values of the fields, in slot order
+        pub fn fields(&self) -> impl Iterator<Item = (&StringName, &Value)> {
+            self.layout.fields().keys().zip(&self.slots)
         }
-        map
     }
 }
 
-pub use object::{InstanceField as ObjectInstanceField, Object};
-
-#[allow(unused_imports)]
-pub(crate) use object::{
-    get_instance_fields as object_get_instance_fields,
-    get_static_fields as object_get_static_fields,
-};
+pub use object::Object;
 
 use crate::{
-    type_system::TypeHandle,
+    type_system::{TypeHandle, refers_to_assembly},
     vm::{CPU, VM},
 };
 
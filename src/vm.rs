@@ -1,12 +1,17 @@
 mod cpu;
+mod heap_snapshot;
+mod safepoint;
 #[cfg(test)]
 mod tests;
+mod unload;
 
 use std::{
     collections::HashMap,
-    sync::{Arc, RwLock},
+    path::Path,
+    sync::{Arc, Mutex, OnceLock, RwLock},
 };
 
+use crate::errors::VMError;
 use crate::type_system::{Class, Struct};
 use crate::value::StructObject;
 use crate::{
@@ -16,20 +21,32 @@ use crate::{
 pub use cpu::CPU;
 use export::{
     AssemblyManagerTrait, AssemblyTrait, CPUTrait, VMTrait, VMTrait_Assembly, VMTrait_CPU,
-    VMTrait_Statics,
+    VMTrait_GC, VMTrait_Statics,
 };
-use gc::Gc;
+use gc::{Gc, Generation, Heap, HeapConfig, HeapStats, ObjectCount, Trace};
 use global::{
     Error, IndexMap, Result, StringMethodReference, StringName, StringTypeReference, ThreadSafe,
-    configs::runtime::VMConfig, errors::RuntimeError, inline_all,
+    configs::runtime::VMConfig, errors::RuntimeError, inline_all, string_name,
 };
+use safepoint::{Mutator, Safepoints};
+
+pub(crate) const FINALIZE_SIGN: StringName = string_name!("Finalize()");
 
 #[derive(ThreadSafe, Debug)]
 pub struct VM {
     config: Arc<RwLock<VMConfig>>,
     cpus: Arc<RwLock<Vec<Arc<CPU>>>>,
+    /// The cpu running finalizers, created on first use
+    finalizer_cpu: OnceLock<Arc<CPU>>,
+    heap: &'static Heap,
+    safepoints: Safepoints,
     assembly_manager: Arc<AssemblyManager>,
     per_vm_statics_map: Arc<RwLock<HashMap<StringTypeReference, Value>>>,
+    /// Held while the statics of a struct are read, changed and put back, so that two stores
+    /// through [`Value::StaticReference`] do not lose one of them
+    statics_store: Mutex<()>,
+    /// See [`VM::null`]
+    null: OnceLock<Gc<ByRefValue>>,
 }
 
 impl VMTrait for VM {}
@@ -51,8 +68,13 @@ impl VM {
         Ok(Arc::new(Self {
             config: Arc::new(RwLock::new(config)),
             cpus: Arc::new(RwLock::new(Vec::with_capacity(1))),
+            finalizer_cpu: OnceLock::new(),
+            heap: Heap::new(),
+            safepoints: Safepoints::default(),
             assembly_manager,
             per_vm_statics_map: Arc::new(RwLock::new(HashMap::new())),
+            statics_store: Mutex::new(()),
+            null: OnceLock::new(),
         }))
     }
 }
@@ -115,6 +137,9 @@ impl VMTrait_Assembly for VM {
             None
         }));
     }
+    fn unload_assembly(self: Arc<Self>, name: StringName) -> Result<()> {
+        self.unload(&name)
+    }
 }
 
 impl VM {
@@ -139,6 +164,167 @@ impl VM {
                     .try_collect::<IndexMap<_, _>>()?,
             ))
     }
+    pub fn heap(&self) -> &'static Heap {
+        self.heap
+    }
+    /// Move `val` into the heap of this VM.
+    ///
+    /// If it would go over [`gc::HeapConfig::max_bytes`] a full collection is made first, and
+    /// an out-of-memory error is returned if it still does not fit.
+    pub fn alloc<T: Trace>(self: &Arc<Self>, val: T) -> Result<Gc<T>> {
+        let val = match self.heap.try_alloc(val) {
+            Ok(gc) => return Ok(gc),
+            Err((_, val)) => val,
+        };
+        // `val` is in no register yet, what it references must survive the collection.
+        // Compacting would move it, so leave that and the finalizers to the next collection.
+        {
+            let _world = self.safepoints.stop_the_world();
+            self.heap.collect_generation(Generation::Old, |visit| {
+                self.visit_roots(visit);
+                visit(&val);
+            });
+        }
+        self.heap
+            .try_alloc(val)
+            .map_err(|(e, _)| VMError::OutOfMemory(e).into())
+    }
+    /// Count `additional` bytes the payload of `owner` grows by against the heap limit.
+    ///
+    /// Like [`VM::alloc`], a full collection is made first if it would not fit,
+    /// which `owner` survives.
+    pub fn grow(self: &Arc<Self>, owner: &dyn Trace, additional: usize) -> Result<()> {
+        if self.heap.try_grow(additional).is_ok() {
+            return Ok(());
+        }
+        {
+            let _world = self.safepoints.stop_the_world();
+            self.heap.collect_generation(Generation::Old, |visit| {
+                self.visit_roots(visit);
+                visit(owner);
+            });
+        }
+        self.heap
+            .try_grow(additional)
+            .map_err(|e| VMError::OutOfMemory(e).into())
+    }
+    /// The object null references point to, allocated on first use and never collected
+    pub fn null(self: &Arc<Self>) -> Result<Gc<ByRefValue>> {
+        if let Some(null) = self.null.get() {
+            return Ok(*null);
+        }
+        let null = self.alloc(ByRefValue::Null)?;
+        null.root();
+        if let Err(null) = self.null.set(null) {
+            // another thread was first
+            null.unroot();
+        }
+        Ok(*self.null.get().unwrap())
+    }
+    /// Collect `generation` of the heap, treating the registers of every CPU and the statics
+    /// as roots, then run the finalizers of the objects found unreachable.
+    ///
+    /// Full collections are followed by [`Heap::compact`] if [`gc::HeapConfig::compacting`] is set.
+    /// Every other CPU running is parked at its next instruction meanwhile.
+    pub fn collect_generation(self: &Arc<Self>, generation: Generation) -> Result<()> {
+        {
+            let _world = self.safepoints.stop_the_world();
+            self.heap
+                .collect_generation(generation, |visit| self.visit_roots(visit));
+            if generation == Generation::Old {
+                self.compact();
+            }
+        }
+        self.run_finalizers()
+    }
+    /// Do a bounded step of incremental collection, see [`Heap::collect_incrementally`].
+    /// Called between instructions, a finished collection is followed by compaction and
+    /// finalizers like [`VM::collect_generation`].
+    pub fn collect_incrementally(self: &Arc<Self>) -> Result<()> {
+        if !self.heap.wants_incremental_step() {
+            return Ok(());
+        }
+        let finished = {
+            let _world = self.safepoints.stop_the_world();
+            let finished = self
+                .heap
+                .collect_incrementally(|visit| self.visit_roots(visit));
+            if finished {
+                self.compact();
+            }
+            finished
+        };
+        if finished {
+            self.run_finalizers()?;
+        }
+        Ok(())
+    }
+    /// Park the current thread while another one collects, see [`VM::enter_managed`]
+    pub(crate) fn safepoint(&self) {
+        self.safepoints.poll();
+    }
+    /// Count the current thread as running managed code until the result is dropped, so that
+    /// collections wait for it to reach a [`VM::safepoint`]
+    pub(crate) fn enter_managed(&self) -> Mutator<'_> {
+        self.safepoints.enter()
+    }
+    /// Run `f` without holding back collections, as natives do around a call which may block,
+    /// like writing to the console. `f` must not touch managed objects or registers.
+    pub(crate) fn blocking<R>(&self, f: impl FnOnce() -> R) -> R {
+        let _native = self.safepoints.enter_native();
+        f()
+    }
+    /// Report the registers of every CPU and the statics.
+    ///
+    /// Called with the heap locked, so the locks taken here are always taken after it.
+    fn visit_roots(&self, visit: &mut dyn FnMut(&dyn Trace)) {
+        for cpu in self.cpus.read().unwrap().iter() {
+            cpu.registers().visit_roots(visit);
+        }
+        for val in self.per_vm_statics_map.read().unwrap().values() {
+            visit(val);
+        }
+    }
+    /// Run [`Heap::compact`] if [`gc::HeapConfig::compacting`] is set
+    fn compact(&self) {
+        if !self.heap.config().compacting {
+            return;
+        }
+        self.heap.compact(
+            |visit| self.visit_roots(visit),
+            |forward| {
+                for cpu in self.cpus.read().unwrap().iter() {
+                    cpu.registers().relocate_roots(forward);
+                }
+                for val in self.per_vm_statics_map.write().unwrap().values_mut() {
+                    val.relocate(forward);
+                }
+            },
+        );
+    }
+    /// Run `Finalize()` of every object queued by the collector on the finalizer cpu.
+    ///
+    /// Every finalizer is run even if one of them fails; the first error is returned.
+    pub fn run_finalizers(self: &Arc<Self>) -> Result<()> {
+        let cpu = self
+            .finalizer_cpu
+            .get_or_init(|| CPU::from_dyn(self.clone().new_cpu().1))
+            .clone();
+        let mut result = Ok(());
+        for obj in self.heap.drain_finalization_queue::<ByRefValue>() {
+            let res = cpu.clone().call_instance_method::<Class>(
+                None,
+                &StringMethodReference::Single(FINALIZE_SIGN),
+                &mut Value::Reference(obj),
+                &mut [],
+            );
+            obj.unroot();
+            if result.is_ok() {
+                result = res.map(|_| ());
+            }
+        }
+        result
+    }
     pub fn is_dynamic_checking_enabled(&self) -> bool {
         self.config.read().unwrap().is_dynamic_checking_enabled()
     }
@@ -165,18 +351,22 @@ impl VM {
             | Value::Int32(_)
             | Value::Int64(_)
             | Value::Int128(_) => Err(RuntimeError::FailedGetField(name.into()).into()),
-            Value::Struct(struct_object) => {
-                Ok(struct_object.get_field(name).map(|x| x.val().clone())?)
-            }
+            Value::Struct(struct_object) => Ok(struct_object.get_field(name)?.clone()),
             Value::Reference(p) => match &**p {
-                ByRefValue::Object(object) => Ok(object
-                    .get_field(StringName::from(name))
-                    .map(|x| x.val().clone())?),
+                ByRefValue::Object(object) => Ok(object.get_field(StringName::from(name))?.clone()),
                 ByRefValue::Array(_) => Err(RuntimeError::UnsupportedGettingField.into()),
                 ByRefValue::String(_) => Err(RuntimeError::UnsupportedGettingField.into()),
+                ByRefValue::WeakReference(_)
+                | ByRefValue::Type(_)
+                | ByRefValue::MethodInfo(_)
+                | ByRefValue::FieldInfo(_) => Err(RuntimeError::UnsupportedGettingField.into()),
                 ByRefValue::Null => todo!(),
             },
-            Value::RegisterReference(_) => unimplemented!(),
+            // the statics of a type are held by value, never through a managed reference
+            Value::RegisterReference(_)
+            | Value::FieldReference(..)
+            | Value::ElementReference(..)
+            | Value::StaticReference(..) => Err(RuntimeError::UnsupportedGettingField.into()),
         }
     }
 }
@@ -192,10 +382,9 @@ impl VMTrait_Statics for VM {
             for t in types {
                 match t {
                     TypeHandle::Class(class) => {
-                        let fields = crate::value::object_get_static_fields(class.clone());
-                        let mt = class.mt.get();
-                        let obj = Object::internal_new(mt, fields);
-                        let p = Gc::new(ByRefValue::Object(obj));
+                        let obj =
+                            Object::with_layout(class.mt.get(), class.static_layout()?, &self)?;
+                        let p = self.alloc(ByRefValue::Object(obj))?;
                         let mut reference = Value::Reference(p);
                         cpu_static.clone().call_instance_method::<Class>(
                             None,
@@ -209,9 +398,7 @@ impl VMTrait_Statics for VM {
                             .insert(class.string_reference(), reference);
                     }
                     TypeHandle::Struct(s) => {
-                        let fields = crate::value::struct_get_static_fields(s.clone());
-                        let mt = s.mt.get();
-                        let obj = StructObject::internal_new(mt, fields);
+                        let obj = StructObject::with_layout(s.mt.get(), s.static_layout()?, &self)?;
                         let mut reference = Value::Struct(obj);
                         cpu_static.clone().call_instance_method::<Struct>(
                             None,
@@ -232,6 +419,28 @@ impl VMTrait_Statics for VM {
     }
 }
 
+impl VMTrait_GC for VM {
+    fn collect_garbage(self: Arc<Self>) -> Result<()> {
+        self.collect_generation(Generation::Old)
+    }
+    fn heap_stats(&self) -> HeapStats {
+        self.heap.stats()
+    }
+    fn heap_config(&self) -> HeapConfig {
+        self.heap.config()
+    }
+    fn set_heap_config(&self, config: HeapConfig) {
+        self.heap.set_config(config)
+    }
+    fn live_objects_by_type(&self) -> HashMap<StringName, ObjectCount> {
+        self.heap
+            .count_objects_by(|x: &ByRefValue| x.string_type_reference().string_name_repr())
+    }
+    fn dump_heap(&self, path: &Path) -> Result<()> {
+        Ok(self.heap_snapshot().save(path)?)
+    }
+}
+
 #[unsafe(no_mangle)]
 #[allow(nonstandard_style)]
 pub extern "Rust" fn NewVM() -> global::Result<Arc<dyn VMTrait>> {
@@ -1,3 +1,4 @@
+mod managed_reference;
 mod register;
 
 use super::VM;
@@ -6,7 +7,7 @@ use crate::type_system::get_traits::GetAssemblyMust;
 use crate::type_system::get_traits::GetTypeName;
 use crate::type_system::get_traits::GetTypeVars;
 use crate::{
-    type_system::TypeHandle,
+    type_system::{Class, TypeHandle},
     value::{Array, ByRefValue, Object, StringValue, Value},
 };
 use export::CPUTrait;
@@ -51,20 +52,21 @@ impl CPUTrait for CPU {
                 let entry_point = entry_class
                     .mt()
                     .get_method(&StringMethodReference::Single(ENTRY_SIGN))?;
-                let mut args = Array::alloc_with_capacity(
+                let args = Array::alloc_with_capacity(
                     self.clone(),
                     self.vm
                         .get_core_single_type(string_name!("System.String"))?,
                     arguments.len(),
-                );
-                let (arg_arr,) = args.unwrap_array_mut()?;
+                )?;
+                // the array is in no register yet, keep it alive while its elements are allocated
+                let pinned_args = args.pinned();
 
                 for a in arguments.iter() {
                     let s = StringValue::new(a.clone());
-                    let gc_ref = Gc::new(ByRefValue::String(s));
-                    arg_arr.push(Value::Reference(gc_ref.clone()));
-                    gc_ref.unroot();
+                    let gc_ref = self.heap_alloc(ByRefValue::String(s))?;
+                    Array::push(&self, args, Value::Reference(gc_ref))?;
                 }
+                drop(pinned_args);
                 let mut val = entry_point.call(
                     self.clone(),
                     &mut Value::Void,
@@ -115,14 +117,24 @@ impl CPU {
     pub fn find_register_continuous_start(&self, length: usize) -> usize {
         self.registers().find_continuous_empty_start(length)
     }
+    /// Reserve `length` continuous empty registers for a frame, which are handed to no other
+    /// frame until [`CPU::leave_frame`], even while they hold nothing
+    pub fn enter_frame(&self, length: usize) -> usize {
+        self.registers().reserve_continuous_empty(length)
+    }
+    pub fn leave_frame(&self, start: usize, length: usize) {
+        self.registers().release(start, length)
+    }
 }
 
 impl CPU {
     #[inline]
-    pub fn heap_alloc<T: Trace>(&self, val: T) -> Gc<T> {
-        let p = Gc::new(val);
-        p.unroot();
-        p
+    pub fn heap_alloc<T: Trace>(&self, val: T) -> Result<Gc<T>> {
+        self.vm.alloc(val)
+    }
+    #[inline]
+    pub fn heap_grow(&self, owner: &dyn Trace, additional: usize) -> Result<()> {
+        self.vm.grow(owner, additional)
     }
 }
 
@@ -136,12 +148,27 @@ impl CPU {
         this_val: &mut Value,
         arguments: &mut [Value],
     ) -> Result<Value> {
-        let ty = if let Some(caller_method) = caller_method {
-            caller_method.solve_str_type(&this_val.string_type_reference())?
-        } else {
-            this_val.ty(self.clone())?
-        };
-        match ty {
+        if this_val.is_managed_reference() {
+            // the method is called on what the reference points to
+            let mut this = self.load_indirect(this_val)?;
+            let Value::Struct(_) = this else {
+                return self.call_instance_method(caller_method, method_ref, &mut this, arguments);
+            };
+            let TypeHandle::Struct(s) = self.receiver_type(caller_method, &this)? else {
+                return Err(RuntimeError::UnsupportedInstanceType.into());
+            };
+            let method = s.mt().get_method(method_ref)?;
+            if !method.instructions.is_empty() {
+                // the code sets the fields through the reference, so the struct is changed
+                // where it lives
+                return method.call(self.clone(), this_val, arguments);
+            }
+            // native code is handed the struct itself, which is stored back afterwards
+            let res = method.call(self.clone(), &mut this, arguments)?;
+            self.store_indirect(this_val, this)?;
+            return Ok(res);
+        }
+        match self.receiver_type(caller_method, this_val)? {
             TypeHandle::Generic(_) => Err(RuntimeError::UnsupportedInstanceType.into()),
             TypeHandle::Class(class) => {
                 class
@@ -157,6 +184,17 @@ impl CPU {
             TypeHandle::Unloaded(_) => unreachable!(),
         }
     }
+    /// The type of `this`, with the type variables of `caller_method` substituted
+    fn receiver_type<T: GetTypeVars + GetTypeName + GetAssemblyMust + Any>(
+        self: &Arc<Self>,
+        caller_method: Option<&CommonMethod<T>>,
+        this: &Value,
+    ) -> Result<TypeHandle> {
+        match caller_method {
+            Some(caller_method) => caller_method.solve_str_type(&this.string_type_reference(self)?),
+            None => this.ty(self.clone()),
+        }
+    }
     pub fn call_static_str_method(
         self: &Arc<Self>,
         type_ref: &StringTypeReference,
@@ -179,6 +217,55 @@ impl CPU {
             TypeHandle::Unloaded(_) => unreachable!(),
         }
     }
+    /// Call the static method `name` of `type_ref` whose parameters best match the types of
+    /// `args`, as picked by `CommonMethodTable::resolve_method`
+    pub fn call_static_method_by_args(
+        self: &Arc<Self>,
+        type_ref: &StringTypeReference,
+        name: &str,
+        args: &mut [Value],
+    ) -> Result<Value> {
+        let arg_types = self.arg_types(args)?;
+        match self.vm.get_type(type_ref)? {
+            TypeHandle::Class(class) => class.mt().resolve_method(name, &arg_types)?.call(
+                self.clone(),
+                &mut Value::Void,
+                args,
+            ),
+            TypeHandle::Struct(s) => {
+                s.mt()
+                    .resolve_method(name, &arg_types)?
+                    .call(self.clone(), &mut Value::Void, args)
+            }
+            TypeHandle::Generic(_) => Err(RuntimeError::FailedGetType(type_ref.clone()).into()),
+            TypeHandle::Unloaded(_) => unreachable!(),
+        }
+    }
+    /// Call the instance method `name` of `this_val` whose parameters best match the types of
+    /// `args`, as picked by `CommonMethodTable::resolve_method`
+    pub fn call_instance_method_by_args(
+        self: Arc<Self>,
+        name: &str,
+        this_val: &mut Value,
+        args: &mut [Value],
+    ) -> Result<Value> {
+        let arg_types = self.arg_types(args)?;
+        let method = match this_val.ty(self.clone())? {
+            TypeHandle::Class(class) => class.mt().resolve_method(name, &arg_types)?.name,
+            TypeHandle::Struct(s) => s.mt().resolve_method(name, &arg_types)?.name,
+            TypeHandle::Generic(_) => return Err(RuntimeError::UnsupportedInstanceType.into()),
+            TypeHandle::Unloaded(_) => unreachable!(),
+        };
+        self.call_instance_method::<Class>(
+            None,
+            &StringMethodReference::Single(method),
+            this_val,
+            args,
+        )
+    }
+    fn arg_types(self: &Arc<Self>, args: &[Value]) -> Result<Vec<TypeHandle>> {
+        args.iter().map(|x| x.ty(self.clone())).try_collect()
+    }
 
     pub fn create_object<T: GetTypeVars + GetTypeName + GetAssemblyMust + Any>(
         self: Arc<Self>,
@@ -196,8 +283,8 @@ impl CPU {
             TypeHandle::Class(class) => class,
             _ => return Err(RuntimeError::UnsupportedObjectType.into()),
         };
-        let obj = Object::alloc(self.clone(), class.mt.get().cast());
-        let mut obj_val = Value::Reference(obj.clone());
+        let obj = Object::alloc(self.clone(), class.mt.get().cast())?;
+        let mut obj_val = Value::Reference(obj);
         if self
             .call_instance_method(
                 caller_method,
@@ -0,0 +1,143 @@
+use global::{Result, StringTypeReference, errors::RuntimeError};
+
+use crate::{
+    type_system::TypeHandle,
+    value::{ByRefValue, Value},
+};
+
+use super::CPU;
+
+/// Taking the address of registers, fields, array elements and statics, and loading and storing
+/// through the managed references it gives.
+///
+/// A reference keeps the object it points into alive and follows it when it is moved. A
+/// reference to a register stays valid while the frame owning the register runs, since the
+/// registers of a frame are handed to no other frame until it returns.
+///
+/// TODO: the instruction set has no load-address or load/store-indirect opcode yet, so only
+/// natives and embedders reach these; the interpreter and the verifier are to dispatch to them
+/// once [`StringInstruction`](global::instruction::StringInstruction) carries the opcodes.
+impl CPU {
+    /// A managed reference to the register `addr`
+    pub fn register_address(&self, addr: u64) -> Value {
+        Value::RegisterReference(addr)
+    }
+    /// A managed reference to the field `name` of the object `obj` references
+    pub fn field_address(&self, obj: &Value, name: &str) -> Result<Value> {
+        let Value::Reference(r) = obj else {
+            return Err(RuntimeError::UnsupportedGettingField.into());
+        };
+        let ByRefValue::Object(object) = &**r else {
+            return Err(RuntimeError::UnsupportedGettingField.into());
+        };
+        let slot = object
+            .layout()
+            .slot_of(name)
+            .ok_or(RuntimeError::FailedGetField(name.into()))?;
+        Ok(Value::FieldReference(*r, slot))
+    }
+    /// A managed reference to the element at `index` of the array `arr` references
+    pub fn element_address(&self, arr: &Value, index: usize) -> Result<Value> {
+        let Value::Reference(r) = arr else {
+            return Err(RuntimeError::WrongType.into());
+        };
+        let (array,) = r.unwrap_array_ref()?;
+        if array.get(index).is_none() {
+            return Err(RuntimeError::ArrayIndexOutOfRange.into());
+        }
+        Ok(Value::ElementReference(*r, index))
+    }
+    /// A managed reference to the static field `name` of the type `ty`
+    pub fn static_address(&self, ty: &StringTypeReference, name: &str) -> Result<Value> {
+        let ty = self.vm.get_type(ty)?;
+        let slot = match &ty {
+            TypeHandle::Class(class) => class.static_layout()?.slot_of(name),
+            TypeHandle::Struct(s) => s.static_layout()?.slot_of(name),
+            TypeHandle::Generic(_) | TypeHandle::Unloaded(_) => None,
+        }
+        .ok_or(RuntimeError::FailedGetField(name.into()))?;
+        Ok(Value::StaticReference(ty.string_reference(), slot))
+    }
+    /// The value `reference` points to
+    pub fn load_indirect(&self, reference: &Value) -> Result<Value> {
+        match reference {
+            Value::RegisterReference(addr) => self.read_register(*addr),
+            Value::FieldReference(obj, slot) => {
+                let (object,) = obj.unwrap_object_ref()?;
+                Ok(object
+                    .slot(*slot)
+                    .ok_or(RuntimeError::FailedGetField(
+                        slot.to_string().as_str().into(),
+                    ))?
+                    .clone())
+            }
+            Value::ElementReference(arr, index) => {
+                let (array,) = arr.unwrap_array_ref()?;
+                Ok(array
+                    .get(*index)
+                    .ok_or(RuntimeError::ArrayIndexOutOfRange)?
+                    .clone())
+            }
+            Value::StaticReference(ty, slot) => {
+                let statics = self.vm.per_vm_statics_map.read().unwrap();
+                let field = match statics
+                    .get(ty)
+                    .ok_or(RuntimeError::FailedGetType(ty.clone()))?
+                {
+                    Value::Reference(r) => r.unwrap_object_ref()?.0.slot(*slot),
+                    Value::Struct(s) => s.slot(*slot),
+                    _ => return Err(RuntimeError::UnsupportedGettingField.into()),
+                };
+                Ok(field
+                    .ok_or(RuntimeError::FailedGetField(
+                        slot.to_string().as_str().into(),
+                    ))?
+                    .clone())
+            }
+            _ => Err(RuntimeError::WrongType.into()),
+        }
+    }
+    /// Store `val` where `reference` points to
+    pub fn store_indirect(&self, reference: &Value, val: Value) -> Result<()> {
+        match reference {
+            Value::RegisterReference(addr) => self.write_register(*addr, val),
+            Value::FieldReference(obj, slot) => {
+                let mut obj = *obj;
+                obj.unwrap_object_mut()?.0.set_slot(*slot, &val)
+            }
+            Value::ElementReference(arr, index) => {
+                let mut arr = *arr;
+                arr.unwrap_array_mut()?
+                    .0
+                    .set(*index, val)
+                    .ok_or(RuntimeError::ArrayIndexOutOfRange.into())
+            }
+            Value::StaticReference(ty, slot) => {
+                // the write barrier takes the heap lock, which must not be taken while the
+                // statics are locked, so they are changed on a copy under `statics_store` instead
+                let _store = self.vm.statics_store.lock().unwrap();
+                let mut statics = self
+                    .vm
+                    .per_vm_statics_map
+                    .read()
+                    .unwrap()
+                    .get(ty)
+                    .ok_or(RuntimeError::FailedGetType(ty.clone()))?
+                    .clone();
+                match &mut statics {
+                    Value::Reference(r) => return r.unwrap_object_mut()?.0.set_slot(*slot, &val),
+                    Value::Struct(s) => s.set_slot(*slot, &val)?,
+                    _ => return Err(RuntimeError::UnsupportedGettingField.into()),
+                }
+                // the statics of a struct are held by value, so the changed copy is put back
+                self.vm
+                    .per_vm_statics_map
+                    .write()
+                    .unwrap()
+                    .insert(ty.clone(), statics);
+                Ok(())
+            }
+            _ => Err(RuntimeError::WrongType.into()),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::sync::{Arc, RwLock};
 
 use enumflags2::{BitFlags, bitflags, make_bitflags};
+use gc::Trace;
 use global::{
     Result, ThreadSafe, errors::RuntimeError, find_util::FindContinuousEmptyStart, inline_all,
 };
@@ -57,11 +58,32 @@ impl RegisterGroup {
                 (r.val == Value::Void)
                     && r.flags.contains(RegisterFlags::Readable)
                     && r.flags.contains(RegisterFlags::Writeable)
+                    && !r.flags.contains(RegisterFlags::InFrame)
             }),
             Box::new(Default::default),
             length,
         )
     }
+    /// Like [`RegisterGroup::find_continuous_empty_start`], marking the registers found as
+    /// owned by a frame
+    pub fn reserve_continuous_empty(&self, length: usize) -> usize {
+        let start = self.find_continuous_empty_start(length);
+        let mut registers = self.registers.write().unwrap();
+        if registers.len() < start + length {
+            registers.resize_with(start + length, Default::default);
+        }
+        for register in &mut registers[start..start + length] {
+            register.flags |= RegisterFlags::InFrame;
+        }
+        start
+    }
+    /// Give back the registers reserved by [`RegisterGroup::reserve_continuous_empty`]
+    pub fn release(&self, start: usize, length: usize) {
+        let mut registers = self.registers.write().unwrap();
+        for register in registers.iter_mut().skip(start).take(length) {
+            register.flags.remove(RegisterFlags::InFrame);
+        }
+    }
 }
 
 impl RegisterGroup {
@@ -70,12 +92,39 @@ impl RegisterGroup {
     }
 }
 
+impl RegisterGroup {
+    /// Report every register value as a gc root
+    pub fn visit_roots(&self, visit: &mut dyn FnMut(&dyn Trace)) {
+        for register in self.registers.read().unwrap().iter() {
+            visit(&register.val);
+        }
+    }
+    /// Like [`RegisterGroup::visit_roots`], naming each root after its register
+    pub fn visit_named_roots(&self, visit: &mut dyn FnMut(&str, &dyn Trace)) {
+        for (i, register) in self.registers.read().unwrap().iter().enumerate() {
+            visit(&format!("register {i}"), &register.val);
+        }
+    }
+    /// Whether `f` holds for the value of a register
+    pub fn any_value(&self, f: impl Fn(&Value) -> bool) -> bool {
+        self.registers.read().unwrap().iter().any(|x| f(&x.val))
+    }
+    /// Update the register values referencing objects moved by a compacting collection
+    pub fn relocate_roots(&self, forward: &dyn Fn(usize) -> Option<usize>) {
+        for register in self.registers.write().unwrap().iter_mut() {
+            register.val.relocate(forward);
+        }
+    }
+}
+
 #[bitflags]
 #[repr(u8)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug, ThreadSafe)]
 enum RegisterFlags {
     Readable,
     Writeable,
+    /// Owned by a frame which has not returned yet
+    InFrame,
 }
 
 #[derive(Clone, Debug, ThreadSafe)]
@@ -0,0 +1,77 @@
+use gc::HeapSnapshot;
+
+use crate::value::{ByRefValue, StructObject, Value};
+
+use super::VM;
+
+impl VM {
+    /// Record the object graph of the heap, with the registers of every CPU and the statics as
+    /// roots. Every other CPU running is parked meanwhile.
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        let _world = self.safepoints.stop_the_world();
+        self.heap.snapshot(describe, |visit| {
+            for cpu in self.cpus.read().unwrap().iter() {
+                cpu.registers().visit_named_roots(&mut |name, val| {
+                    visit(&format!("cpu {} {name}", cpu.id()), val)
+                });
+            }
+            for (t, val) in self.per_vm_statics_map.read().unwrap().iter() {
+                visit(&format!("static {}", t.string_name_repr()), val);
+            }
+        })
+    }
+}
+
+fn describe(val: &ByRefValue) -> (String, Vec<(String, String)>) {
+    let fields = match val {
+        ByRefValue::Object(obj) => obj
+            .fields()
+            .map(|(k, v)| (k.to_string(), describe_value(v)))
+            .collect(),
+        ByRefValue::Array(arr) => arr[..]
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (format!("[{i}]"), describe_value(v)))
+            .collect(),
+        ByRefValue::String(s) => vec![(String::from("value"), format!("{:?}", s.get()))],
+        ByRefValue::WeakReference(w) => vec![(
+            String::from("target"),
+            w.target()
+                .map_or_else(|| String::from("null"), |x| format!("weak #{}", x.addr())),
+        )],
+        ByRefValue::Type(t) => vec![(String::from("type"), t.get().name().to_string())],
+        ByRefValue::MethodInfo(m) => vec![(
+            String::from("method"),
+            format!("{}::{}", m.declaring_type().name(), m.name()),
+        )],
+        ByRefValue::FieldInfo(f) => vec![(
+            String::from("field"),
+            format!("{}::{}", f.declaring_type().name(), f.name()),
+        )],
+        ByRefValue::Null => Vec::new(),
+    };
+    (
+        val.string_type_reference().string_name_repr().to_string(),
+        fields,
+    )
+}
+
+fn describe_value(val: &Value) -> String {
+    match val {
+        Value::Reference(r) => format!("#{}", r.addr()),
+        Value::Struct(s) => describe_struct(s),
+        val => format!("{val:?}"),
+    }
+}
+
+fn describe_struct(s: &StructObject) -> String {
+    let fields = s
+        .fields()
+        .map(|(k, v)| format!("{k}: {}", describe_value(v)))
+        .collect::<Vec<_>>();
+    format!(
+        "{} {{ {} }}",
+        s.ty().string_reference().string_name_repr(),
+        fields.join(", ")
+    )
+}
@@ -0,0 +1,141 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Condvar, Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, ThreadId},
+};
+
+/// Lets a collector stop every thread running managed code of a VM at an instruction
+/// boundary before it touches the heap, and resume them afterwards.
+///
+/// A thread is a mutator between [`Safepoints::enter`] and the drop of the returned guard,
+/// and must call [`Safepoints::poll`] between instructions. Around code which may block
+/// without polling it enters [`Safepoints::enter_native`] instead, and counts as parked.
+#[derive(Debug, Default)]
+pub(crate) struct Safepoints {
+    /// Mirrors `state.stopping`, so that polling needs no lock
+    stopping: AtomicBool,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    stopping: bool,
+    /// Threads running managed code, with how deeply their calls are nested
+    mutators: HashMap<ThreadId, usize>,
+    parked: HashSet<ThreadId>,
+    /// Mutators running native code which touches no managed object, see
+    /// [`Safepoints::enter_native`]
+    native: HashSet<ThreadId>,
+}
+
+impl Safepoints {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+    /// Wait for the world to be resumed, counting as parked meanwhile
+    fn park<'a>(&'a self, mut state: MutexGuard<'a, State>) -> MutexGuard<'a, State> {
+        let id = thread::current().id();
+        state.parked.insert(id);
+        self.changed.notify_all();
+        let mut state = self.changed.wait_while(state, |x| x.stopping).unwrap();
+        state.parked.remove(&id);
+        state
+    }
+    pub(crate) fn enter(&self) -> Mutator<'_> {
+        let id = thread::current().id();
+        let mut state = self.lock();
+        if !state.mutators.contains_key(&id) {
+            // a thread starting to run managed code does not start while the world is stopped
+            state = self.changed.wait_while(state, |x| x.stopping).unwrap();
+        }
+        *state.mutators.entry(id).or_default() += 1;
+        Mutator { safepoints: self }
+    }
+    /// Park the current thread if a collector is stopping the world
+    pub(crate) fn poll(&self) {
+        if self.stopping.load(Ordering::Acquire) {
+            drop(self.park(self.lock()));
+        }
+    }
+    /// Count the current thread as parked until the returned guard is dropped, while it runs
+    /// native code which touches no managed object or register, like a call which may block.
+    /// Dropping the guard waits for a stopped world to be resumed before managed code goes on.
+    pub(crate) fn enter_native(&self) -> Native<'_> {
+        let mut state = self.lock();
+        state.native.insert(thread::current().id());
+        self.changed.notify_all();
+        Native { safepoints: self }
+    }
+    /// Wait until every other mutator is parked. They stay parked until the returned guard
+    /// is dropped.
+    pub(crate) fn stop_the_world(&self) -> WorldStopped<'_> {
+        let id = thread::current().id();
+        // another collector may be stopping the world already, its mutators include this one
+        let mut state = self.park(self.lock());
+        state.stopping = true;
+        self.stopping.store(true, Ordering::Release);
+        drop(
+            self.changed
+                .wait_while(state, |x| {
+                    x.mutators
+                        .keys()
+                        .any(|t| *t != id && !x.parked.contains(t) && !x.native.contains(t))
+                })
+                .unwrap(),
+        );
+        WorldStopped { safepoints: self }
+    }
+}
+
+/// A thread running managed code, see [`Safepoints::enter`]
+pub(crate) struct Mutator<'a> {
+    safepoints: &'a Safepoints,
+}
+
+impl Drop for Mutator<'_> {
+    fn drop(&mut self) {
+        let id = thread::current().id();
+        let mut state = self.safepoints.lock();
+        let depth = state.mutators.get_mut(&id).unwrap();
+        *depth -= 1;
+        if *depth == 0 {
+            state.mutators.remove(&id);
+            self.safepoints.changed.notify_all();
+        }
+    }
+}
+
+/// A thread running native code, see [`Safepoints::enter_native`]
+pub(crate) struct Native<'a> {
+    safepoints: &'a Safepoints,
+}
+
+impl Drop for Native<'_> {
+    fn drop(&mut self) {
+        let state = self.safepoints.lock();
+        let mut state = self
+            .safepoints
+            .changed
+            .wait_while(state, |x| x.stopping)
+            .unwrap();
+        state.native.remove(&thread::current().id());
+    }
+}
+
+/// Every mutator but the current thread is parked while this lives
+pub(crate) struct WorldStopped<'a> {
+    safepoints: &'a Safepoints,
+}
+
+impl Drop for WorldStopped<'_> {
+    fn drop(&mut self) {
+        let mut state = self.safepoints.lock();
+        state.stopping = false;
+        self.safepoints.stopping.store(false, Ordering::Release);
+        self.safepoints.changed.notify_all();
+    }
+}
@@ -3,7 +3,10 @@
 use std::{
     fmt::{self, FormattingOptions},
     io::Cursor,
-    sync::{Arc, LazyLock},
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
 use enumflags2::make_bitflags;
@@ -12,7 +15,8 @@ use global::{
     Result,
     attrs::{
         ClassImplementationFlags, FieldAttr, FieldImplementationFlags, MethodAttr,
-        MethodImplementationFlags, TypeAttr, TypeSpecificAttr, Visibility,
+        MethodImplementationFlags, StructImplementationFlags, TypeAttr, TypeSpecificAttr,
+        Visibility,
     },
     indexmap,
     instruction::StringInstruction,
@@ -20,8 +24,8 @@ use global::{
 };
 
 use crate::{
-    type_system::{Class, ClassField, CommonMethod, CommonMethodTable},
-    value::{Array, Value},
+    type_system::{Class, ClassField, CommonMethod, CommonMethodTable, Struct, StructField},
+    value::{Array, StringValue, StructObject, Value},
 };
 
 static GENERAL_VM: LazyLock<Arc<VM>> = LazyLock::new(|| {
@@ -53,7 +57,8 @@ fn test_vm_cpu() -> Result<()> {
 
 #[test]
 fn test_vm_run() -> Result<()> {
-    let vm = GENERAL_VM.clone();
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    vm.clone().load_statics()?;
     let assem_mgr = AssemblyManager::from_dyn(vm.assembly_manager());
     let assem_mgr = &assem_mgr;
     let assem = Arc::new(Assembly::new(string_name!("Test"), assem_mgr));
@@ -105,7 +110,7 @@ fn test_vm_run() -> Result<()> {
         },
         indexmap! {},
     )));
-    assem_mgr.add_assembly(assem);
+    assem_mgr.add_assembly(assem)?;
     let (i, cpu) = vm.clone().new_cpu();
     let cpu = CPU::from_dyn(cpu);
     cpu.run(
@@ -118,7 +123,8 @@ fn test_vm_run() -> Result<()> {
 
 #[test]
 fn test_to_string() -> Result<()> {
-    let vm = GENERAL_VM.clone();
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    vm.clone().load_statics()?;
     let assem_mgr = AssemblyManager::from_dyn(vm.assembly_manager());
     let assem_mgr = &assem_mgr;
     let assem = Arc::new(Assembly::new(string_name!("Test"), assem_mgr));
@@ -172,7 +178,7 @@ fn test_to_string() -> Result<()> {
         },
         indexmap! {},
     )));
-    assem_mgr.add_assembly(assem);
+    assem_mgr.add_assembly(assem)?;
     let (i, cpu) = vm.clone().new_cpu();
     let cpu = CPU::from_dyn(cpu);
     cpu.run(
@@ -209,9 +215,7 @@ fn test_static() -> Result<()> {
             &mut [],
         )?;
         val.unwrap_string_mut()?.0.set("aaa".to_owned());
-        this_val
-            .get_mut_field(StringName::from("__test"))?
-            .set_val(&Value::Reference(val));
+        this_val.set_field(StringName::from("__test"), &Value::Reference(val))?;
         Ok(Value::Void)
     }
     assem.add_type(TypeHandle::Class(Class::new(
@@ -285,7 +289,7 @@ fn test_static() -> Result<()> {
             ),
         },
     )));
-    assem_mgr.add_assembly(assem.clone());
+    assem_mgr.add_assembly(assem.clone())?;
     let ty = assem
         .get_type(&StringTypeReference::make_static_single(
             "Test",
@@ -312,32 +316,1259 @@ fn test_static() -> Result<()> {
 }
 
 #[test]
-fn test_array() -> Result<()> {
-    let vm = VM::new()?;
-    let (i, cpu) = vm.clone().new_cpu();
+fn test_field_layout() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    let assem_mgr = AssemblyManager::from_dyn(vm.assembly_manager());
+    let assem = Arc::new(Assembly::new(string_name!("Test"), &assem_mgr));
+    let void = assem_mgr.get_type_from_str(&AssemblyManager::System_Void_STRUCT_REF)?;
+    let field = |name: &str, flags| {
+        let attr = FieldAttr::new(Visibility::Public, flags);
+        (
+            StringName::from(name),
+            ClassField::new(StringName::from(name), attr, void.clone()),
+        )
+    };
+    let base = Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+        ),
+        string_name!("Test.Base"),
+        |class| {
+            CommonMethodTable::new(
+                |_| indexmap! {},
+                &class,
+                Some(
+                    assem_mgr
+                        .get_type_from_str(&AssemblyManager::System_Object_CLASS_REF)
+                        .unwrap(),
+                ),
+            )
+        },
+        IndexMap::from_iter([field("a", make_bitflags!(FieldImplementationFlags::{}))]),
+    );
+    let derived = Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+        ),
+        string_name!("Test.Derived"),
+        |class| {
+            CommonMethodTable::new(
+                |table| {
+                    indexmap! {
+                        string_name!("Set()") => CommonMethod::new(
+                            string_name!("Set()"),
+                            MethodAttr::new(Visibility::Public, make_bitflags!(MethodImplementationFlags::{}), 1),
+                            table,
+                            vec![
+                                StringInstruction::Load_u8_1 { register_addr: 0 },
+                                StringInstruction::SetField { register_addr: 0, field: string_name!("b") },
+                                StringInstruction::SetField { register_addr: 0, field: string_name!("a") },
+                                StringInstruction::SetField { register_addr: 0, field: string_name!("s") },
+                            ].into(),
+                            void.clone(),
+                            vec![],
+                            Default::default(),
+                        ),
+                    }
+                },
+                &class,
+                Some(TypeHandle::Class(base.clone())),
+            )
+        },
+        IndexMap::from_iter([
+            field("b", make_bitflags!(FieldImplementationFlags::{})),
+            field("s", make_bitflags!(FieldImplementationFlags::{Static})),
+        ]),
+    );
+    assem.add_type(TypeHandle::Class(base.clone()));
+    assem.add_type(TypeHandle::Class(derived.clone()));
+    assem_mgr.add_assembly(assem.clone())?;
+    assem_mgr.resolve_type_references()?;
+
+    // inherited fields come first, statics are laid out apart
+    let layout = derived.instance_layout()?;
+    assert_eq!(layout.len(), 2);
+    assert_eq!(layout.slot_of("a"), base.instance_layout()?.slot_of("a"));
+    assert_eq!(layout.slot_of("b"), Some(1));
+    assert_eq!(derived.static_layout()?.slot_of("s"), Some(0));
+    let set = derived
+        .mt()
+        .get_method(&StringMethodReference::static_single("Set()"))?;
+    assert_eq!(set.field_slots()?, [None, Some(1), Some(0), None]);
+
+    let (_, cpu) = vm.clone().new_cpu();
     let cpu = CPU::from_dyn(cpu);
-    let mut arr = Array::alloc(
-        cpu.clone(),
-        vm.get_type(&StringTypeReference::Single {
-            assem: string_name!("!"),
-            ty: string_name!("System.Object"),
-        })?,
+    let mut obj = Object::alloc(cpu, derived.mt.get())?;
+    let (obj,) = obj.unwrap_object_mut()?;
+    obj.set_field(string_name!("a"), &Value::UInt8(1))?;
+    obj.set_slot(1, &Value::UInt8(2))?;
+    assert_eq!(obj.slot(0), Some(&Value::UInt8(1)));
+    assert_eq!(obj.get_field(string_name!("b"))?, &Value::UInt8(2));
+    assert!(obj.set_field(string_name!("s"), &Value::Void).is_err());
+
+    // a field whose type can't be loaded fails the layout instead of defaulting to Void
+    let missing = TypeHandle::Unloaded(StringTypeReference::Single {
+        assem: string_name!("Missing"),
+        ty: string_name!("Missing.Type"),
+    });
+    let broken = Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+        ),
+        string_name!("Test.Broken"),
+        |class| CommonMethodTable::new(|_| indexmap! {}, &class, None),
+        IndexMap::from_iter([(
+            string_name!("m"),
+            ClassField::new(
+                string_name!("m"),
+                FieldAttr::new(
+                    Visibility::Public,
+                    make_bitflags!(FieldImplementationFlags::{}),
+                ),
+                missing,
+            ),
+        )]),
     );
-    let (array,) = arr.unwrap_array_mut()?;
-    dbg!(array.ty(vm.clone()).name());
-    array.grow_to(10);
-    for i in 0..10usize {
-        array[i] = Value::UInt64(i as _);
+    assert!(broken.instance_layout().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_unload_assembly() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    let assem_mgr = AssemblyManager::from_dyn(vm.assembly_manager());
+    let name = string_name!("Plugin");
+    let assem = Arc::new(Assembly::new(name.clone(), &assem_mgr));
+    let class = Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+        ),
+        string_name!("Plugin.Thing"),
+        |class| {
+            CommonMethodTable::new(
+                |_| indexmap! {},
+                &class,
+                Some(
+                    assem_mgr
+                        .get_type_from_str(&AssemblyManager::System_Object_CLASS_REF)
+                        .unwrap(),
+                ),
+            )
+        },
+        indexmap! {},
+    );
+    assem.add_type(TypeHandle::Class(class.clone()));
+    assem_mgr.add_assembly(assem)?;
+    // the instantiation is added to the core assembly, and goes with the plugin
+    let array_name = vm
+        .get_core_generic_type(
+            string_name!("System.Array`1"),
+            Arc::new(indexmap! {
+                string_name!("@T") => StringTypeReference::make_static_single("Plugin", "Plugin.Thing"),
+            }),
+        )?
+        .name();
+
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    let obj = Object::alloc(cpu.clone(), class.mt.get())?;
+    cpu.write_register(0, Value::Reference(obj))?;
+    let to_string = StringMethodReference::Single(string_name!("ToString()"));
+    assert!(class.mt().get_method(&to_string).is_ok());
+    assert!(vm.unload(&name).is_err());
+    assert!(vm.get_assembly(name.clone()).is_ok());
+
+    cpu.write_register(0, Value::Void)?;
+    vm.unload(&name)?;
+    assert!(vm.get_assembly(name.clone()).is_err());
+    // the class outlives its assembly, with an empty table
+    assert!(class.mt().get_method(&to_string).is_err());
+    assert!(
+        !Assembly::from_dyn(vm.get_core_assem())
+            .types()
+            .read()
+            .unwrap()
+            .contains_key(&array_name)
+    );
+    assert!(vm.unload(&StringTypeReference::CORE_ASSEMBLY_NAME).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_field_defaults() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    let assem_mgr = AssemblyManager::from_dyn(vm.assembly_manager());
+    let assem = Arc::new(Assembly::new(string_name!("Test"), &assem_mgr));
+    let field = |name: &str, ty: &str| {
+        let attr = FieldAttr::new(
+            Visibility::Public,
+            make_bitflags!(FieldImplementationFlags::{}),
+        );
+        let ty = assem_mgr
+            .get_type_from_str(&StringTypeReference::Single {
+                assem: string_name!("!"),
+                ty: StringName::from(ty),
+            })
+            .unwrap();
+        (
+            StringName::from(name),
+            ClassField::new(StringName::from(name), attr, ty),
+        )
+    };
+    let class = Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+        ),
+        string_name!("Test.Defaults"),
+        |class| CommonMethodTable::new(|_| indexmap! {}, &class, None),
+        IndexMap::from_iter([
+            field("count", "System.UInt64"),
+            field("flag", "System.Boolean"),
+            field("name", "System.String"),
+            field("color", "System.ConsoleColor"),
+        ]),
+    );
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    let obj = Object::alloc(cpu, class.mt.get())?;
+    let (obj,) = obj.unwrap_object_ref()?;
+    assert_eq!(obj.get_field(string_name!("count"))?, &Value::UInt64(0));
+    assert_eq!(obj.get_field(string_name!("flag"))?, &Value::False);
+    let (name,) = obj
+        .get_field(string_name!("name"))?
+        .unwrap_reference_ref()?;
+    assert!(matches!(**name, ByRefValue::Null));
+    assert_eq!(name.addr(), vm.null()?.addr());
+    assert!(matches!(
+        obj.get_field(string_name!("color"))?,
+        Value::Struct(_)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_struct_receiver_by_reference() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    let assem_mgr = AssemblyManager::from_dyn(vm.assembly_manager());
+    let assem_mgr = &assem_mgr;
+    let assem = Arc::new(Assembly::new(string_name!("Test"), assem_mgr));
+    fn Test_Counter_Increment(
+        method: &CommonMethod<Struct>,
+        cpu: Arc<CPU>,
+        this: &mut Value,
+        args: &mut [Value],
+        reg_start: u64,
+    ) -> Result<Value> {
+        let (this,) = this.unwrap_struct_mut()?;
+        let &Value::UInt64(count) = this.get_field("count")? else {
+            panic!()
+        };
+        this.set_field("count", &Value::UInt64(count + 1))?;
+        Ok(Value::Void)
     }
-    let mut s = String::new();
-    let mut formatter = fmt::Formatter::new(&mut s, *FormattingOptions::new().alternate(true));
-    array.dbg_fmt(vm.clone(), &mut formatter)?;
-    eprintln!("{s}");
+    let counter = Struct::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Struct(make_bitflags!(StructImplementationFlags::{})),
+        ),
+        string_name!("Test.Counter"),
+        |s| {
+            CommonMethodTable::new(
+                |table| {
+                    indexmap! {
+                        string_name!("Increment()") => CommonMethod::native(
+                            string_name!("Increment()"),
+                            MethodAttr::new(
+                                Visibility::Public,
+                                make_bitflags!(MethodImplementationFlags::{}),
+                                0,
+                            ),
+                            table,
+                            assem_mgr.get_type_from_str(&AssemblyManager::System_Void_STRUCT_REF).unwrap(),
+                            vec![],
+                            Default::default(),
+                            Test_Counter_Increment,
+                        ),
+                        string_name!("Set()") => CommonMethod::new(
+                            string_name!("Set()"),
+                            MethodAttr::new(
+                                Visibility::Public,
+                                make_bitflags!(MethodImplementationFlags::{}),
+                                0,
+                            ),
+                            table,
+                            vec![
+                                StringInstruction::SetField { register_addr: 5, field: string_name!("count") },
+                            ].into(),
+                            assem_mgr.get_type_from_str(&AssemblyManager::System_Void_STRUCT_REF).unwrap(),
+                            vec![],
+                            Default::default(),
+                        ),
+                    }
+                },
+                &s,
+                Some(
+                    assem_mgr
+                        .get_type_from_str(&AssemblyManager::System_ValueType_STRUCT_REF)
+                        .unwrap(),
+                ),
+            )
+        },
+        indexmap! {
+            string_name!("count") => StructField::new(
+                string_name!("count"),
+                FieldAttr::new(
+                    Visibility::Public,
+                    make_bitflags!(FieldImplementationFlags::{}),
+                ),
+                assem_mgr.get_type_from_str(&StringTypeReference::core_static_single_type("System.UInt64")).unwrap()
+            ),
+        },
+    );
+    assem.add_type(TypeHandle::Struct(counter.clone()));
+    assem.add_type(TypeHandle::Class(Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{Static})),
+        ),
+        string_name!("Test.Program"),
+        |class| {
+            CommonMethodTable::new(
+                |table| {
+                    indexmap! {
+                        string_name!("Run()") => CommonMethod::new(
+                            string_name!("Run()"),
+                            MethodAttr::new(
+                                Visibility::Public,
+                                make_bitflags!(MethodImplementationFlags::{Static}),
+                                0
+                            ),
+                            table,
+                            vec![
+                                StringInstruction::InstanceCall {
+                                    val: 0,
+                                    method: StringMethodReference::Single(string_name!("Increment()")),
+                                    args: vec![],
+                                    ret_at: 1,
+                                },
+                                StringInstruction::InstanceCall {
+                                    val: 0,
+                                    method: StringMethodReference::Single(string_name!("Increment()")),
+                                    args: vec![],
+                                    ret_at: 1,
+                                },
+                            ].into(),
+                            assem_mgr.get_type_from_str(&AssemblyManager::System_Void_STRUCT_REF).unwrap(),
+                            vec![],
+                            Default::default(),
+                        ),
+                    }
+                },
+                &class,
+                Some(
+                    assem_mgr
+                        .get_type_from_str(&AssemblyManager::System_Object_CLASS_REF)
+                        .unwrap(),
+                ),
+            )
+            .cast()
+        },
+        indexmap! {},
+    )));
+    let holder = Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+        ),
+        string_name!("Test.Holder"),
+        |class| {
+            CommonMethodTable::new(
+                |_| indexmap! {},
+                &class,
+                Some(
+                    assem_mgr
+                        .get_type_from_str(&AssemblyManager::System_Object_CLASS_REF)
+                        .unwrap(),
+                ),
+            )
+        },
+        indexmap! {
+            string_name!("c") => ClassField::new(
+                string_name!("c"),
+                FieldAttr::new(
+                    Visibility::Public,
+                    make_bitflags!(FieldImplementationFlags::{}),
+                ),
+                TypeHandle::Struct(counter.clone()),
+            ),
+        },
+    );
+    assem.add_type(TypeHandle::Class(holder.clone()));
+    assem_mgr.add_assembly(assem.clone())?;
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    cpu.write_register(0, Value::Struct(StructObject::make(counter.mt.get(), &vm)?))?;
+    cpu.call_static_str_method(
+        &StringTypeReference::make_static_single("Test", "Test.Program"),
+        &StringMethodReference::static_single("Run()"),
+        &mut [],
+    )?;
+    // both calls changed the struct in the register, not a copy of it
+    let counter = cpu.read_register(0)?;
+    let (counter,) = counter.unwrap_struct_ref()?;
+    assert_eq!(counter.get_field("count")?, &Value::UInt64(2));
+
+    // a struct in a field is changed in place through a reference to the field, by native
+    // code as well as by instructions
+    let obj = Value::Reference(Object::alloc(cpu.clone(), holder.mt.get())?);
+    cpu.write_register(1, obj.clone())?;
+    let count = |obj: &Value| -> Result<Value> {
+        let (obj,) = obj.unwrap_reference_ref()?;
+        let (obj,) = obj.unwrap_object_ref()?;
+        let (c,) = obj.get_field(string_name!("c"))?.unwrap_struct_ref()?;
+        Ok(c.get_field("count")?.clone())
+    };
+    let mut reference = cpu.field_address(&obj, "c")?;
+    cpu.clone().call_instance_method::<Class>(
+        None,
+        &StringMethodReference::Single(string_name!("Increment()")),
+        &mut reference,
+        &mut [],
+    )?;
+    assert_eq!(count(&obj)?, Value::UInt64(1));
+    cpu.write_register(5, Value::UInt64(7))?;
+    cpu.clone().call_instance_method::<Class>(
+        None,
+        &StringMethodReference::Single(string_name!("Set()")),
+        &mut reference,
+        &mut [],
+    )?;
+    assert_eq!(count(&obj)?, Value::UInt64(7));
     Ok(())
 }
+
 #[test]
-fn test_from_ir() -> Result<()> {
-    let vm = GENERAL_VM.clone();
+fn test_managed_references() -> Result<()> {
+    const TEST_CLASS_NAME: StringTypeReference =
+        StringTypeReference::make_static_single("Test", "Test.Holder");
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    let assem_mgr = AssemblyManager::from_dyn(vm.assembly_manager());
+    let assem_mgr = &assem_mgr;
+    let assem = Arc::new(Assembly::new(string_name!("Test"), assem_mgr));
+    fn Test_Holder_sctor(
+        method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this: &mut Value,
+        args: &mut [Value],
+        reg_start: u64,
+    ) -> Result<Value> {
+        Ok(Value::Void)
+    }
+    // an out parameter
+    fn Test_Holder_Fill(
+        method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this: &mut Value,
+        args: &mut [Value],
+        reg_start: u64,
+    ) -> Result<Value> {
+        cpu.store_indirect(&args[0], Value::UInt64(42))?;
+        Ok(Value::Void)
+    }
+    let field = |name: &str, flags| {
+        (
+            StringName::from(name),
+            ClassField::new(
+                StringName::from(name),
+                FieldAttr::new(Visibility::Public, flags),
+                assem_mgr
+                    .get_type_from_str(&StringTypeReference::core_static_single_type(
+                        "System.UInt64",
+                    ))
+                    .unwrap(),
+            ),
+        )
+    };
+    let class = Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+        ),
+        string_name!("Test.Holder"),
+        |class| {
+            CommonMethodTable::new(
+                |table| {
+                    indexmap! {
+                        string_name!(".sctor()") => CommonMethod::native(
+                            string_name!(".sctor()"),
+                            MethodAttr::new(
+                                Visibility::Public,
+                                make_bitflags!(MethodImplementationFlags::{Static}),
+                                0,
+                            ),
+                            table,
+                            assem_mgr.get_type_from_str(&AssemblyManager::System_Void_STRUCT_REF).unwrap(),
+                            vec![],
+                            Default::default(),
+                            Test_Holder_sctor,
+                        ),
+                        string_name!("Fill([!]System.UInt64)") => CommonMethod::native(
+                            string_name!("Fill([!]System.UInt64)"),
+                            MethodAttr::new(
+                                Visibility::Public,
+                                make_bitflags!(MethodImplementationFlags::{Static}),
+                                0,
+                            ),
+                            table,
+                            assem_mgr.get_type_from_str(&AssemblyManager::System_Void_STRUCT_REF).unwrap(),
+                            vec![],
+                            Default::default(),
+                            Test_Holder_Fill,
+                        ),
+                    }
+                },
+                &class,
+                Some(
+                    assem_mgr
+                        .get_type_from_str(&AssemblyManager::System_Object_CLASS_REF)
+                        .unwrap(),
+                ),
+            )
+            .cast()
+        },
+        IndexMap::from_iter([
+            field("value", make_bitflags!(FieldImplementationFlags::{})),
+            field("total", make_bitflags!(FieldImplementationFlags::{Static})),
+        ]),
+    );
+    assem.add_type(TypeHandle::Class(class.clone()));
+    assem_mgr.add_assembly(assem.clone())?;
+    vm.clone().load_statics()?;
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+
+    cpu.write_register(3, Value::UInt64(1))?;
+    let reference = cpu.register_address(3);
+    cpu.store_indirect(&reference, Value::UInt64(2))?;
+    assert_eq!(cpu.read_register(3)?, Value::UInt64(2));
+    assert_eq!(reference.ty(cpu.clone())?.name().as_str(), "System.UInt64");
+
+    let obj = Value::Reference(Object::alloc(cpu.clone(), class.mt.get())?);
+    let reference = cpu.field_address(&obj, "value")?;
+    cpu.store_indirect(&reference, Value::UInt64(7))?;
+    let (object,) = obj.unwrap_reference_ref()?;
+    let (object,) = object.unwrap_object_ref()?;
+    assert_eq!(object.get_field(string_name!("value"))?, &Value::UInt64(7));
+    assert_eq!(cpu.load_indirect(&reference)?, Value::UInt64(7));
+    assert!(cpu.field_address(&obj, "total").is_err());
+
+    let mut arr = Array::alloc(
+        cpu.clone(),
+        vm.get_core_single_type(string_name!("System.UInt64"))?,
+    )?;
+    Array::grow_to(&cpu, arr, 2)?;
+    let arr = Value::Reference(arr);
+    let reference = cpu.element_address(&arr, 1)?;
+    cpu.store_indirect(&reference, Value::UInt64(5))?;
+    let (array,) = arr.unwrap_reference_ref()?;
+    assert_eq!(array.unwrap_array_ref()?.0[1], Value::UInt64(5));
+    assert!(cpu.element_address(&arr, 2).is_err());
+
+    let reference = cpu.static_address(&TEST_CLASS_NAME, "total")?;
+    cpu.store_indirect(&reference, Value::UInt64(9))?;
+    assert_eq!(
+        vm.clone().get_static_from_str(&TEST_CLASS_NAME, "total")?,
+        Value::UInt64(9)
+    );
+    assert!(cpu.static_address(&TEST_CLASS_NAME, "value").is_err());
+
+    cpu.call_static_str_method(
+        &TEST_CLASS_NAME,
+        &StringMethodReference::static_single("Fill([!]System.UInt64)"),
+        &mut [cpu.register_address(4)],
+    )?;
+    assert_eq!(cpu.read_register(4)?, Value::UInt64(42));
+    Ok(())
+}
+
+#[test]
+fn test_reflection() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    let assem_mgr = AssemblyManager::from_dyn(vm.assembly_manager());
+    let assem_mgr = &assem_mgr;
+    let assem = Arc::new(Assembly::new(string_name!("Test"), assem_mgr));
+    fn Test_Point_Get(
+        method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this: &mut Value,
+        args: &mut [Value],
+        reg_start: u64,
+    ) -> Result<Value> {
+        Ok(Value::UInt64(11))
+    }
+    fn Test_Point_Add(
+        method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this: &mut Value,
+        args: &mut [Value],
+        reg_start: u64,
+    ) -> Result<Value> {
+        Ok(args[0].clone())
+    }
+    let uint64 = assem_mgr.get_type_from_str(&StringTypeReference::core_static_single_type(
+        "System.UInt64",
+    ))?;
+    let class = Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+        ),
+        string_name!("Test.Point"),
+        |class| {
+            CommonMethodTable::new(
+                |table| {
+                    indexmap! {
+                        string_name!("Get()") => CommonMethod::native(
+                            string_name!("Get()"),
+                            MethodAttr::new(
+                                Visibility::Public,
+                                make_bitflags!(MethodImplementationFlags::{}),
+                                0,
+                            ),
+                            table,
+                            uint64.clone(),
+                            vec![],
+                            Default::default(),
+                            Test_Point_Get,
+                        ),
+                        string_name!("Add([!]System.UInt64)") => CommonMethod::native(
+                            string_name!("Add([!]System.UInt64)"),
+                            MethodAttr::new(
+                                Visibility::Public,
+                                make_bitflags!(MethodImplementationFlags::{}),
+                                0,
+                            ),
+                            table,
+                            uint64.clone(),
+                            vec![uint64.clone()],
+                            Default::default(),
+                            Test_Point_Add,
+                        ),
+                    }
+                },
+                &class,
+                Some(
+                    assem_mgr
+                        .get_type_from_str(&AssemblyManager::System_Object_CLASS_REF)
+                        .unwrap(),
+                ),
+            )
+            .cast()
+        },
+        indexmap! {
+            string_name!("x") => ClassField::new(
+                string_name!("x"),
+                FieldAttr::new(Visibility::Public, make_bitflags!(FieldImplementationFlags::{})),
+                uint64.clone(),
+            ),
+        },
+    );
+    assem.add_type(TypeHandle::Class(class.clone()));
+    assem_mgr.add_assembly(assem.clone())?;
+    vm.clone().load_statics()?;
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    let call = |this: &mut Value, name: &str, args: &mut [Value]| {
+        cpu.clone().call_instance_method::<Class>(
+            None,
+            &StringMethodReference::Single(name.into()),
+            this,
+            args,
+        )
+    };
+    let string = |s: &str| -> Result<Value> {
+        Ok(Value::Reference(cpu.heap_alloc(ByRefValue::String(
+            StringValue::new(s.to_owned()),
+        ))?))
+    };
+
+    let mut obj = Value::Reference(Object::alloc(cpu.clone(), class.mt.get())?);
+    let mut ty = call(&mut obj, "GetType()", &mut [])?;
+    let name = call(&mut ty, "get_Name()", &mut [])?;
+    let (name,) = name.unwrap_reference_ref()?;
+    assert_eq!(name.unwrap_string_ref()?.0.get(), "Test.Point");
+    let base = call(&mut ty, "get_BaseType()", &mut [])?;
+    let (base,) = base.unwrap_reference_ref()?;
+    assert_eq!(
+        base.unwrap_type_ref()?.0.get().string_reference(),
+        AssemblyManager::System_Object_CLASS_REF
+    );
+
+    let mut field = call(&mut ty, "GetField([!]System.String)", &mut [string("x")?])?;
+    call(
+        &mut field,
+        "SetValue([!]System.Object, [!]System.Object)",
+        &mut [obj.clone(), Value::UInt64(3)],
+    )?;
+    assert_eq!(
+        call(&mut field, "GetValue([!]System.Object)", &mut [obj.clone()])?,
+        Value::UInt64(3)
+    );
+    let fields = call(&mut ty, "GetFields()", &mut [])?;
+    let (fields,) = fields.unwrap_reference_ref()?;
+    assert_eq!(fields.unwrap_array_ref()?.0[..].len(), 1);
+
+    // the methods of the parents are found too
+    let missing = call(
+        &mut ty,
+        "GetMethod([!]System.String)",
+        &mut [string("Missing()")?],
+    )?;
+    assert!(matches!(
+        **missing.unwrap_reference_ref()?.0,
+        ByRefValue::Null
+    ));
+    let method = call(
+        &mut ty,
+        "GetMethod([!]System.String)",
+        &mut [string("ToString()")?],
+    )?;
+    let (info,) = method.unwrap_reference_ref()?;
+    assert_eq!(
+        info.unwrap_method_info_ref()?
+            .0
+            .declaring_type()
+            .string_reference(),
+        AssemblyManager::System_Object_CLASS_REF
+    );
+    let mut method = call(
+        &mut ty,
+        "GetMethod([!]System.String)",
+        &mut [string("Get()")?],
+    )?;
+    let null = Value::Reference(vm.null()?);
+    assert_eq!(
+        call(
+            &mut method,
+            "Invoke([!]System.Object, [!]System.Array`1[[!]System.Object])",
+            &mut [obj.clone(), null.clone()],
+        )?,
+        Value::UInt64(11)
+    );
+    // the method is not called on an object of another type
+    assert!(
+        call(
+            &mut method,
+            "Invoke([!]System.Object, [!]System.Array`1[[!]System.Object])",
+            &mut [string("x")?, null.clone()],
+        )
+        .is_err()
+    );
+    // nor without an object, as it is not static
+    assert!(
+        call(
+            &mut method,
+            "Invoke([!]System.Object, [!]System.Array`1[[!]System.Object])",
+            &mut [null.clone(), null.clone()],
+        )
+        .is_err()
+    );
+    assert!(
+        call(
+            &mut method,
+            "Invoke([!]System.Object, [!]System.Array`1[[!]System.Object])",
+            &mut [obj.clone()],
+        )
+        .is_err()
+    );
+    // the arguments must be as many as the method takes
+    let mut add = call(
+        &mut ty,
+        "GetMethod([!]System.String)",
+        &mut [string("Add([!]System.UInt64)")?],
+    )?;
+    let object = vm.get_type(&AssemblyManager::System_Object_CLASS_REF)?;
+    let arguments = |items: Vec<Value>| -> Result<Value> {
+        Ok(Value::Reference(Array::alloc_with_data(
+            cpu.clone(),
+            object.clone(),
+            items,
+        )?))
+    };
+    assert_eq!(
+        call(
+            &mut add,
+            "Invoke([!]System.Object, [!]System.Array`1[[!]System.Object])",
+            &mut [obj.clone(), arguments(vec![Value::UInt64(5)])?],
+        )?,
+        Value::UInt64(5)
+    );
+    assert!(
+        call(
+            &mut add,
+            "Invoke([!]System.Object, [!]System.Array`1[[!]System.Object])",
+            &mut [obj.clone(), arguments(vec![])?],
+        )
+        .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_array() -> Result<()> {
+    let vm = VM::new()?;
+    let (i, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    let mut arr = Array::alloc(
+        cpu.clone(),
+        vm.get_type(&StringTypeReference::Single {
+            assem: string_name!("!"),
+            ty: string_name!("System.Object"),
+        })?,
+    )?;
+    Array::grow_to(&cpu, arr, 10)?;
+    let (array,) = arr.unwrap_array_mut()?;
+    dbg!(array.ty(vm.clone()).name());
+    for i in 0..10usize {
+        array.set(i, Value::UInt64(i as _)).unwrap();
+    }
+    let mut s = String::new();
+    let mut formatter = fmt::Formatter::new(&mut s, *FormattingOptions::new().alternate(true));
+    array.dbg_fmt(vm.clone(), &mut formatter)?;
+    eprintln!("{s}");
+    Ok(())
+}
+#[test]
+fn test_heap_stats() -> Result<()> {
+    let vm = VM::new()?;
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    let before = vm.heap_stats();
+    let _ = cpu.heap_alloc(ByRefValue::Null)?;
+    let allocated = vm.heap_stats();
+    assert_eq!(allocated.live_objects, before.live_objects + 1);
+    assert!(allocated.total_allocated_bytes > before.total_allocated_bytes);
+    vm.clone().collect_garbage()?;
+    let collected = vm.heap_stats();
+    assert_eq!(collected.live_objects, before.live_objects);
+    assert_eq!(collected.collection_count(Generation::Old), 1);
+    assert_eq!(
+        collected.total_allocated_bytes,
+        allocated.total_allocated_bytes
+    );
+    Ok(())
+}
+#[test]
+fn test_finalization() -> Result<()> {
+    static FINALIZED: AtomicUsize = AtomicUsize::new(0);
+    static SEEN: Mutex<String> = Mutex::new(String::new());
+    fn Test_Finalizable_Finalize(
+        method: &CommonMethod<Class>,
+        cpu: Arc<CPU>,
+        this: &mut Value,
+        args: &mut [Value],
+        reg_start: u64,
+    ) -> Result<Value> {
+        FINALIZED.fetch_add(1, Ordering::SeqCst);
+        // what the object references is resurrected with it while it is finalized
+        let (this,) = this.unwrap_reference_ref()?;
+        let (obj,) = this.unwrap_object_ref()?;
+        let (s,) = obj.get_field(string_name!("s"))?.unwrap_reference_ref()?;
+        *SEEN.lock().unwrap() = s.unwrap_string_ref()?.0.get().to_owned();
+        Ok(Value::Void)
+    }
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    let assem_mgr = AssemblyManager::from_dyn(vm.assembly_manager());
+    let assem = Arc::new(Assembly::new(string_name!("Test"), &assem_mgr));
+    let class = Class::new(
+        &assem,
+        TypeAttr::new(
+            Visibility::Public,
+            TypeSpecificAttr::Class(make_bitflags!(ClassImplementationFlags::{})),
+        ),
+        string_name!("Test.Finalizable"),
+        |class| {
+            CommonMethodTable::new(
+                |table| {
+                    indexmap! {
+                        string_name!("Finalize()") => CommonMethod::native(
+                            string_name!("Finalize()"),
+                            MethodAttr::new(
+                                Visibility::Public,
+                                make_bitflags!(MethodImplementationFlags::{}),
+                                0,
+                            ),
+                            table,
+                            assem_mgr
+                                .get_type_from_str(&AssemblyManager::System_Void_STRUCT_REF)
+                                .unwrap(),
+                            vec![],
+                            Default::default(),
+                            Test_Finalizable_Finalize,
+                        ),
+                    }
+                },
+                &class,
+                Some(
+                    assem_mgr
+                        .get_type_from_str(&AssemblyManager::System_Object_CLASS_REF)
+                        .unwrap(),
+                ),
+            )
+        },
+        indexmap! {
+            string_name!("s") => ClassField::new(
+                string_name!("s"),
+                FieldAttr::new(Visibility::Public, make_bitflags!(FieldImplementationFlags::{})),
+                assem_mgr.get_type_from_str(&StringTypeReference::core_static_single_type(
+                    "System.String",
+                ))?,
+            ),
+        },
+    );
+    assem.add_type(TypeHandle::Class(class.clone()));
+    assem_mgr.add_assembly(assem)?;
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    let s = cpu.heap_alloc(ByRefValue::String(StringValue::new("child".to_owned())))?;
+    let mut obj = Object::alloc(cpu.clone(), class.mt.get())?;
+    obj.unwrap_object_mut()?
+        .0
+        .set_field(string_name!("s"), &Value::Reference(s))?;
+
+    // registered as its class has a finalizer, which is not run while it is reachable
+    cpu.registers().write(0, Value::Reference(obj))?;
+    vm.clone().collect_garbage()?;
+    assert_eq!(FINALIZED.load(Ordering::SeqCst), 0);
+
+    // queued once unreachable, and kept with what it references until its finalizer has run
+    cpu.registers().write(0, Value::Void)?;
+    vm.clone().collect_garbage()?;
+    assert_eq!(FINALIZED.load(Ordering::SeqCst), 1);
+    assert_eq!(*SEEN.lock().unwrap(), "child");
+    let resurrected = vm.heap_stats().live_objects;
+
+    // unrooted after its finalizer has run, the next collection frees it without running it again
+    vm.clone().collect_garbage()?;
+    assert_eq!(FINALIZED.load(Ordering::SeqCst), 1);
+    assert_eq!(vm.heap_stats().live_objects, resurrected - 2);
+    Ok(())
+}
+#[test]
+fn test_weak_reference() -> Result<()> {
+    let vm = VM::new()?;
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    let class = vm
+        .get_core_generic_type(
+            string_name!("System.WeakReference`1"),
+            Arc::new(indexmap! {
+                string_name!("@T") => StringTypeReference::core_static_single_type("System.String"),
+            }),
+        )?
+        .unwrap_class();
+    let call = |this: &mut Value, name: &str, args: &mut [Value]| {
+        cpu.clone().call_instance_method::<Class>(
+            None,
+            &StringMethodReference::Single(name.into()),
+            this,
+            args,
+        )
+    };
+    let target = cpu.heap_alloc(ByRefValue::String(StringValue::new("target".to_owned())))?;
+    let mut weak = Value::Reference(Object::alloc(cpu.clone(), class.mt.get())?);
+    call(&mut weak, ".ctor(@T)", &mut [Value::Reference(target)])?;
+    cpu.registers().write(0, weak.clone())?;
+    cpu.registers().write(1, Value::Reference(target))?;
+    vm.clone().collect_garbage()?;
+    assert_eq!(call(&mut weak, "get_IsAlive()", &mut [])?, Value::True);
+    let got = call(&mut weak, "get_Target()", &mut [])?;
+    assert_eq!(got.unwrap_reference_ref()?.0.addr(), target.addr());
+
+    // the weak reference alone does not keep its target alive
+    cpu.registers().write(1, Value::Void)?;
+    vm.clone().collect_garbage()?;
+    assert_eq!(call(&mut weak, "get_IsAlive()", &mut [])?, Value::False);
+    let got = call(&mut weak, "get_Target()", &mut [])?;
+    assert_eq!(got.unwrap_reference_ref()?.0.addr(), vm.null()?.addr());
+    Ok(())
+}
+#[test]
+fn test_heap_snapshot() -> Result<()> {
+    let vm = VM::new()?;
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    let s = cpu.heap_alloc(ByRefValue::String(StringValue::new("leak".to_owned())))?;
+    let arr = Array::alloc_with_data(
+        cpu.clone(),
+        vm.get_type(&StringTypeReference::core_static_single_type(
+            "System.String",
+        ))?,
+        [Value::Reference(s)],
+    )?;
+    arr.root();
+    let path = std::env::temp_dir().join(format!("pl-heap-snapshot-{}", std::process::id()));
+    vm.dump_heap(&path)?;
+    let snapshot = gc::HeapSnapshot::load(&path)?;
+    std::fs::remove_file(&path)?;
+    let retainers = snapshot.retainers(s.addr());
+    assert!(matches!(retainers[..], [gc::Retainer::Object(x)] if x.id == arr.addr()));
+    assert_eq!(
+        snapshot.object(arr.addr()).unwrap().fields,
+        vec![("[0]".to_owned(), format!("#{}", s.addr()))]
+    );
+    let dominators = snapshot.dominators();
+    assert_eq!(
+        dominators.dominator(s.addr()),
+        Some(gc::Dominator::Object(arr.addr()))
+    );
+    // the array owns a buffer of one value, the string one of four bytes
+    assert_eq!(
+        dominators.retained_size(arr.addr()),
+        Some(2 * std::mem::size_of::<ByRefValue>() + std::mem::size_of::<Value>() + 4)
+    );
+    arr.unroot();
+    Ok(())
+}
+#[test]
+fn test_compacting_collection() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    vm.heap().set_config(HeapConfig {
+        compacting: true,
+        ..Default::default()
+    });
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    for _ in 0..100 {
+        let _ = cpu.heap_alloc(ByRefValue::String(StringValue::new("garbage".to_owned())))?;
+    }
+    let s = cpu.heap_alloc(ByRefValue::String(StringValue::new("kept".to_owned())))?;
+    let old_addr = s.addr();
+    cpu.registers().write(0, Value::Reference(s))?;
+    vm.clone().collect_garbage()?;
+    let val = cpu.registers().read(0)?;
+    let (s,) = val.unwrap_reference_ref()?;
+    assert_ne!(s.addr(), old_addr);
+    assert_eq!(s.unwrap_string_ref()?.0.get(), "kept");
+    Ok(())
+}
+#[test]
+fn test_pins_and_handles() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    vm.heap().set_config(HeapConfig {
+        compacting: true,
+        ..Default::default()
+    });
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    for _ in 0..100 {
+        let _ = cpu.heap_alloc(ByRefValue::Null)?;
+    }
+    let pinned = cpu.heap_alloc(ByRefValue::String(StringValue::new("pinned".to_owned())))?;
+    let pinned = pinned.pinned();
+    let pinned_addr = pinned.gc().addr();
+    let handle = gc::Handle::new(
+        &cpu.heap_alloc(ByRefValue::String(StringValue::new("handle".to_owned())))?,
+    );
+    vm.clone().collect_garbage()?;
+    assert_eq!(pinned.gc().addr(), pinned_addr);
+    assert_eq!(pinned.unwrap_string_ref()?.0.get(), "pinned");
+    assert_eq!(handle.get().unwrap_string_ref()?.0.get(), "handle");
+    assert_eq!(vm.heap_stats().live_objects, 2);
+    drop(pinned);
+    drop(handle);
+    vm.clone().collect_garbage()?;
+    assert_eq!(vm.heap_stats().live_objects, 0);
+    Ok(())
+}
+#[test]
+fn test_incremental_collection() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    vm.heap().set_config(HeapConfig {
+        mark_budget: 1,
+        incremental_threshold: 1,
+        ..Default::default()
+    });
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    let object = vm.get_core_single_type(string_name!("System.Object"))?;
+    let mut arr = Array::alloc(cpu.clone(), object.clone())?;
+    for _ in 0..10 {
+        let _ = cpu.heap_alloc(ByRefValue::Null)?;
+        Array::push(
+            &cpu,
+            arr,
+            Value::Reference(cpu.heap_alloc(ByRefValue::Null)?),
+        )?;
+    }
+    cpu.registers().write(0, Value::Reference(arr))?;
+    vm.collect_incrementally()?;
+    let stored = cpu.heap_alloc(ByRefValue::String(StringValue::new("stored".to_owned())))?;
+    Array::push(&cpu, arr, Value::Reference(stored))?;
+    let mut steps = 0;
+    while vm.heap_stats().collection_count(Generation::Old) == 0 {
+        vm.collect_incrementally()?;
+        steps += 1;
+    }
+    assert!(steps > 10);
+    assert_eq!(vm.heap_stats().live_objects, 12);
+    let (arr,) = arr.unwrap_array_ref()?;
+    assert_eq!(
+        arr[10]
+            .unwrap_reference_ref()?
+            .0
+            .unwrap_string_ref()?
+            .0
+            .get(),
+        "stored"
+    );
+    Ok(())
+}
+#[test]
+fn test_memory_limit() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    // as an embedder holding the VM behind the exported trait sets it
+    let embedded: Arc<dyn VMTrait> = vm.clone();
+    embedded.set_heap_config(HeapConfig {
+        max_bytes: Some(4 * size_of::<ByRefValue>()),
+        ..embedded.heap_config()
+    });
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    // garbage is collected to make room
+    for _ in 0..10 {
+        let _ = cpu.heap_alloc(ByRefValue::Null)?;
+    }
+    for i in 0..4 {
+        cpu.registers()
+            .write(i, Value::Reference(cpu.heap_alloc(ByRefValue::Null)?))?;
+    }
+    assert!(cpu.heap_alloc(ByRefValue::Null).is_err());
+    assert_eq!(vm.heap_stats().live_objects, 4);
+    cpu.registers().write(0, Value::Void)?;
+    let _ = cpu.heap_alloc(ByRefValue::Null)?;
+    // growing an object counts against the limit as well
+    let object = vm.get_core_single_type(string_name!("System.Object"))?;
+    let arr = Array::alloc(cpu.clone(), object)?;
+    assert!(Array::grow_to(&cpu, arr, 1024).is_err());
+    assert!(vm.heap_stats().live_bytes <= 4 * size_of::<ByRefValue>());
+    Ok(())
+}
+#[test]
+fn test_large_objects() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    vm.heap().set_config(HeapConfig {
+        compacting: true,
+        large_object_threshold: Some(1024),
+        ..Default::default()
+    });
+    let (_, cpu) = vm.clone().new_cpu();
+    let cpu = CPU::from_dyn(cpu);
+    let object = vm.get_core_single_type(string_name!("System.Object"))?;
+    for _ in 0..100 {
+        let _ = cpu.heap_alloc(ByRefValue::Null)?;
+    }
+    let data = vec![Value::Void; 1000];
+    let arr = Array::alloc_with_data(cpu.clone(), object, data)?;
+    let text = StringValue::new("x".repeat(10));
+    let s = cpu.heap_alloc(ByRefValue::String(text))?;
+    assert_eq!(vm.heap_stats().large_objects, 1);
+    cpu.registers().write(0, Value::Reference(arr))?;
+    cpu.registers().write(1, Value::Reference(s))?;
+    vm.clone().collect_garbage()?;
+    let stats = vm.heap_stats();
+    assert_eq!((stats.live_objects, stats.large_objects), (2, 1));
+    assert!(stats.large_bytes >= 1000 * size_of::<Value>());
+    // compaction may move the string, but never the array
+    let val = cpu.registers().read(0)?;
+    let (moved,) = val.unwrap_reference_ref()?;
+    assert_eq!(moved.addr(), arr.addr());
+    assert!(moved.unwrap_array_ref()?.0.get(999).is_some());
+    cpu.registers().write(0, Value::Void)?;
+    vm.clone().collect_garbage()?;
+    assert_eq!(vm.heap_stats().large_bytes, 0);
+    Ok(())
+}
+#[test]
+fn test_collect_while_cpus_run() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    vm.heap().set_config(HeapConfig {
+        compacting: true,
+        ..Default::default()
+    });
+    let workers = (0..4)
+        .map(|_| {
+            let vm = vm.clone();
+            std::thread::spawn(move || {
+                let run = || -> Result<()> {
+                    let (_, cpu) = vm.clone().new_cpu();
+                    let cpu = CPU::from_dyn(cpu);
+                    let _mutator = vm.enter_managed();
+                    for i in 0..1000u64 {
+                        vm.safepoint();
+                        let _ = cpu.heap_alloc(ByRefValue::Null)?;
+                        let s = StringValue::new(i.to_string());
+                        let s = cpu.heap_alloc(ByRefValue::String(s))?;
+                        cpu.registers().write(0, Value::Reference(s))?;
+                        vm.safepoint();
+                        let val = cpu.registers().read(0)?;
+                        let (s,) = val.unwrap_reference_ref()?;
+                        assert_eq!(s.unwrap_string_ref()?.0.get(), i.to_string());
+                    }
+                    Ok(())
+                };
+                run().unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+    while !workers.iter().all(|x| x.is_finished()) {
+        vm.clone().collect_garbage()?;
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    Ok(())
+}
+#[test]
+fn test_collect_while_native_blocks() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
+    let (blocked_tx, blocked_rx) = std::sync::mpsc::channel();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let worker = {
+        let vm = vm.clone();
+        std::thread::spawn(move || {
+            let _mutator = vm.enter_managed();
+            vm.blocking(|| {
+                blocked_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+            });
+        })
+    };
+    blocked_rx.recv().unwrap();
+    // the mutator blocked in native code does not hold back the collection
+    vm.clone().collect_garbage()?;
+    release_tx.send(()).unwrap();
+    worker.join().unwrap();
+    Ok(())
+}
+#[test]
+fn test_from_ir() -> Result<()> {
+    let vm = VM::with_config(VMConfig::builder().build())?;
     vm.assembly_manager()
         .clone()
         .load_from_binary_assemblies(&[binary::Assembly::from_file("./test.plb")?])?;
@@ -350,7 +1581,7 @@ fn test_from_ir() -> Result<()> {
             })?
             .unwrap_class_ref()
             .mt()
-            .parent
+            .parent()
             .unwrap()
             .unwrap_class_ref()
             .mt()
@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use export::AssemblyManagerTrait;
+use gc::Generation;
+use global::{Result, StringName, StringTypeReference};
+
+use crate::{
+    errors::VMError,
+    type_system::{Assembly, refers_to_assembly},
+    value::{ByRefValue, Value},
+};
+
+use super::VM;
+
+impl VM {
+    /// Unload the assembly `name` with the instantiations of generic types and methods over its
+    /// types, emptying their method tables.
+    ///
+    /// Nothing is changed if the assembly is still in use: if a type of another assembly
+    /// depends on it, a method of it is running, a register holds a value of one of its types
+    /// or an object of one of them is reachable other than through its own statics. Otherwise
+    /// the statics of its types are dropped and the heap collected, so that the objects they
+    /// held are finalized while the types still exist. The world is then stopped again to
+    /// check that nothing started using the assembly meanwhile and to remove it. If something
+    /// did, it stays loaded without its statics. The handles to its types only fail to find
+    /// their methods afterwards.
+    pub fn unload(self: &Arc<Self>, name: &StringName) -> Result<()> {
+        if *name == StringTypeReference::CORE_ASSEMBLY_NAME {
+            return Err(VMError::UnloadCore.into());
+        }
+        let assembly = Assembly::from_dyn(self.assembly_manager.get_assembly(name.clone())?);
+        let statics = {
+            let _world = self.safepoints.stop_the_world();
+            self.check_unused(&assembly, name)?;
+            let reachable = self.heap.any_reachable(
+                |x: &ByRefValue| x.refers_to_assembly(name),
+                |visit| {
+                    for cpu in self.cpus.read().unwrap().iter() {
+                        cpu.registers().visit_roots(visit);
+                    }
+                    for (t, val) in self.per_vm_statics_map.read().unwrap().iter() {
+                        if !refers_to_assembly(t, name) {
+                            visit(val);
+                        }
+                    }
+                },
+            );
+            if reachable {
+                return Err(in_use(name, "a live object holds one of its types"));
+            }
+            self.extract_statics(name)
+        };
+        // nothing reaches the objects the statics held anymore, so they are only finalized and
+        // freed; the objects finalized by the first collection are freed by the second
+        drop(statics);
+        self.collect_generation(Generation::Old)?;
+        self.collect_generation(Generation::Old)?;
+
+        // the checks are made again with the world stopped until the assembly is removed, as
+        // its code may have been called meanwhile
+        let _world = self.safepoints.stop_the_world();
+        self.check_unused(&assembly, name)?;
+        // any object of its types left, even garbage, would outlive the tables it points into
+        if self
+            .heap
+            .count_objects_by(|x: &ByRefValue| x.refers_to_assembly(name))
+            .contains_key(&true)
+        {
+            return Err(in_use(name, "an object of one of its types is left"));
+        }
+        drop(self.extract_statics(name));
+        drop(assembly);
+        unsafe { self.assembly_manager.remove_assembly(name) }
+    }
+    /// Fail if another assembly depends on `assembly`, a method of it is running or a register
+    /// holds a value of one of its types. Called with the world stopped, so that no mutator
+    /// starts a call meanwhile.
+    fn check_unused(&self, assembly: &Assembly, name: &StringName) -> Result<()> {
+        if let Some(dependent) = self.assembly_manager.dependents_of(name).first() {
+            return Err(in_use(name, &format!("assembly {dependent} depends on it")));
+        }
+        if assembly.running_frames() > 0 {
+            return Err(in_use(name, "a method of it is running"));
+        }
+        if self
+            .cpus
+            .read()
+            .unwrap()
+            .iter()
+            .any(|cpu| cpu.registers().any_value(|x| x.refers_to_assembly(name)))
+        {
+            return Err(in_use(name, "a register holds a value of one of its types"));
+        }
+        Ok(())
+    }
+    /// Take the statics of the types of the assembly `name` out of the VM
+    fn extract_statics(&self, name: &StringName) -> Vec<(StringTypeReference, Value)> {
+        self.per_vm_statics_map
+            .write()
+            .unwrap()
+            .extract_if(|t, _| refers_to_assembly(t, name))
+            .collect()
+    }
+}
+
+fn in_use(name: &StringName, reason: &str) -> global::Error {
+    VMError::AssemblyInUse {
+        name: name.clone(),
+        reason: reason.to_owned(),
+    }
+    .into()
+}